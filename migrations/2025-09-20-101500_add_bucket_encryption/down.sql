@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files DROP COLUMN encryption_key;
+
+ALTER TABLE buckets DROP COLUMN encryption_key;
+ALTER TABLE buckets DROP COLUMN encrypted;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE buckets ADD COLUMN encrypted BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE buckets ADD COLUMN encryption_key BYTEA;
+
+ALTER TABLE files ADD COLUMN encryption_key BYTEA;
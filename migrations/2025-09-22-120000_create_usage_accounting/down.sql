@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE organization_usage_history;
+DROP TABLE bucket_usage_history;
+
+ALTER TABLE organizations DROP COLUMN quota_objects;
+ALTER TABLE organizations DROP COLUMN soft_quota_bytes;
+ALTER TABLE organizations DROP COLUMN quota_bytes;
+ALTER TABLE organizations DROP COLUMN object_count;
+ALTER TABLE organizations DROP COLUMN used_bytes;
+
+ALTER TABLE buckets DROP COLUMN quota_objects;
+ALTER TABLE buckets DROP COLUMN soft_quota_bytes;
+ALTER TABLE buckets DROP COLUMN quota_bytes;
+ALTER TABLE buckets DROP COLUMN object_count;
+ALTER TABLE buckets DROP COLUMN used_bytes;
+
+ALTER TABLE files DROP COLUMN size;
@@ -0,0 +1,42 @@
+-- Your SQL goes here
+ALTER TABLE files ADD COLUMN size BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE buckets ADD COLUMN used_bytes BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE buckets ADD COLUMN object_count BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE buckets ADD COLUMN quota_bytes BIGINT;
+ALTER TABLE buckets ADD COLUMN soft_quota_bytes BIGINT;
+ALTER TABLE buckets ADD COLUMN quota_objects BIGINT;
+
+ALTER TABLE organizations ADD COLUMN used_bytes BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE organizations ADD COLUMN object_count BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE organizations ADD COLUMN quota_bytes BIGINT;
+ALTER TABLE organizations ADD COLUMN soft_quota_bytes BIGINT;
+ALTER TABLE organizations ADD COLUMN quota_objects BIGINT;
+
+CREATE TABLE bucket_usage_history (
+    bucket_id UUID NOT NULL,
+    day DATE NOT NULL,
+    used_bytes BIGINT NOT NULL,
+    object_count BIGINT NOT NULL
+);
+
+ALTER TABLE bucket_usage_history ADD PRIMARY KEY (bucket_id, day);
+ALTER TABLE bucket_usage_history ADD FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE CASCADE;
+
+CREATE TABLE organization_usage_history (
+    organization_id UUID NOT NULL,
+    day DATE NOT NULL,
+    used_bytes BIGINT NOT NULL,
+    object_count BIGINT NOT NULL
+);
+
+ALTER TABLE organization_usage_history ADD PRIMARY KEY (organization_id, day);
+ALTER TABLE organization_usage_history ADD FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE;
+
+-- Sizes of files uploaded before this migration are unknown, only object counts can be backfilled
+UPDATE buckets SET object_count = (
+    SELECT count(*) FROM files INNER JOIN folders ON folders.id = files.folder_id WHERE folders.bucket_id = buckets.id
+);
+UPDATE organizations SET object_count = (
+    SELECT coalesce(sum(object_count), 0) FROM buckets WHERE buckets.organization_id = organizations.id
+);
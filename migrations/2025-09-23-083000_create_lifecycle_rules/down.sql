@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE lifecycle_rules;
+
+DROP TYPE lifecycle_action;
+
+ALTER TABLE files DROP COLUMN storage_tier;
+
+DROP TYPE storage_tier;
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TYPE storage_tier AS ENUM ('standard', 'cold');
+
+ALTER TABLE files ADD COLUMN storage_tier storage_tier NOT NULL DEFAULT 'standard';
+
+CREATE TYPE lifecycle_action AS ENUM ('expire', 'transition');
+
+CREATE TABLE lifecycle_rules (
+    id UUID PRIMARY KEY,
+    bucket_id UUID NOT NULL,
+    prefix VARCHAR(1024) NOT NULL DEFAULT '',
+    action lifecycle_action NOT NULL,
+    age_days INTEGER NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+
+    created_by UUID NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP,
+    last_run_at TIMESTAMP
+);
+
+ALTER TABLE lifecycle_rules ADD FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE CASCADE;
+ALTER TABLE lifecycle_rules ADD FOREIGN KEY (created_by) REFERENCES users(id);
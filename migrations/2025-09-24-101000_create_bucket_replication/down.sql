@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE replication_tasks;
+DROP TABLE bucket_replications;
+
+ALTER TABLE files DROP COLUMN replication_status;
+
+DROP TYPE replication_operation;
+DROP TYPE replication_status;
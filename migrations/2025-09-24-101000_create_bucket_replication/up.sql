@@ -0,0 +1,39 @@
+-- Your SQL goes here
+CREATE TYPE replication_status AS ENUM ('pending', 'completed', 'failed');
+CREATE TYPE replication_operation AS ENUM ('put', 'delete');
+
+ALTER TABLE files ADD COLUMN replication_status replication_status;
+
+CREATE TABLE bucket_replications (
+    id UUID PRIMARY KEY,
+    source_bucket_id UUID NOT NULL,
+    destination_bucket_id UUID NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+
+    created_by UUID NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP
+);
+
+ALTER TABLE bucket_replications ADD FOREIGN KEY (source_bucket_id) REFERENCES buckets(id) ON DELETE CASCADE;
+ALTER TABLE bucket_replications ADD FOREIGN KEY (destination_bucket_id) REFERENCES buckets(id) ON DELETE CASCADE;
+ALTER TABLE bucket_replications ADD FOREIGN KEY (created_by) REFERENCES users(id);
+ALTER TABLE bucket_replications ADD CONSTRAINT unique_bucket_replication UNIQUE (source_bucket_id, destination_bucket_id);
+
+CREATE TABLE replication_tasks (
+    id UUID PRIMARY KEY,
+    replication_id UUID NOT NULL,
+    file_id UUID,
+    path TEXT NOT NULL,
+    operation replication_operation NOT NULL,
+    status replication_status NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT now(),
+
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP
+);
+
+ALTER TABLE replication_tasks ADD FOREIGN KEY (replication_id) REFERENCES bucket_replications(id) ON DELETE CASCADE;
+CREATE INDEX replication_tasks_pending_idx ON replication_tasks (next_attempt_at) WHERE status = 'pending';
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE buckets ADD COLUMN cors_rules JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE buckets ADD COLUMN website JSONB;
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE bucket_domains (
+    id UUID PRIMARY KEY,
+    bucket_id UUID NOT NULL,
+    hostname VARCHAR(253) NOT NULL,
+    verification_token CHAR(32) NOT NULL,
+    verified_at TIMESTAMP,
+
+    created_by UUID NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+ALTER TABLE bucket_domains ADD FOREIGN KEY (bucket_id) REFERENCES buckets(id) ON DELETE CASCADE;
+ALTER TABLE bucket_domains ADD FOREIGN KEY (created_by) REFERENCES users(id);
+ALTER TABLE bucket_domains ADD CONSTRAINT unique_bucket_domain_hostname UNIQUE (hostname);
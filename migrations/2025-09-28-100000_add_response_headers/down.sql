@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files DROP COLUMN content_type;
+ALTER TABLE files DROP COLUMN content_disposition;
+ALTER TABLE files DROP COLUMN cache_control;
+
+ALTER TABLE buckets DROP COLUMN content_type;
+ALTER TABLE buckets DROP COLUMN content_disposition;
+ALTER TABLE buckets DROP COLUMN cache_control;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE buckets ADD COLUMN cache_control VARCHAR(255);
+ALTER TABLE buckets ADD COLUMN content_disposition VARCHAR(511);
+ALTER TABLE buckets ADD COLUMN content_type VARCHAR(255);
+
+ALTER TABLE files ADD COLUMN cache_control VARCHAR(255);
+ALTER TABLE files ADD COLUMN content_disposition VARCHAR(511);
+ALTER TABLE files ADD COLUMN content_type VARCHAR(255);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE folder_metadata;
+DROP TABLE file_metadata;
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE file_metadata (
+    file_id UUID NOT NULL,
+    key VARCHAR(128) NOT NULL,
+    value VARCHAR(1024) NOT NULL,
+    PRIMARY KEY (file_id, key)
+);
+
+ALTER TABLE file_metadata ADD FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE;
+CREATE INDEX file_metadata_key_value ON file_metadata(key, value);
+
+CREATE TABLE folder_metadata (
+    folder_id UUID NOT NULL,
+    key VARCHAR(128) NOT NULL,
+    value VARCHAR(1024) NOT NULL,
+    PRIMARY KEY (folder_id, key)
+);
+
+ALTER TABLE folder_metadata ADD FOREIGN KEY (folder_id) REFERENCES folders(id) ON DELETE CASCADE;
+CREATE INDEX folder_metadata_key_value ON folder_metadata(key, value);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE file_contents;
+DROP TYPE extraction_status;
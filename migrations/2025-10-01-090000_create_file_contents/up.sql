@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TYPE extraction_status AS ENUM ('pending', 'indexed', 'unsupported', 'failed');
+
+CREATE TABLE file_contents (
+    file_id UUID PRIMARY KEY,
+    status extraction_status NOT NULL DEFAULT 'pending',
+    content TEXT,
+    content_tsv TSVECTOR GENERATED ALWAYS AS (to_tsvector('english', COALESCE(content, ''))) STORED,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+ALTER TABLE file_contents ADD FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE;
+CREATE INDEX file_contents_tsv ON file_contents USING GIN (content_tsv);
+CREATE INDEX file_contents_pending ON file_contents(updated_at) WHERE status = 'pending';
+
+INSERT INTO file_contents (file_id) SELECT id FROM files;
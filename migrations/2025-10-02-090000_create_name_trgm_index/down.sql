@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX folders_name_trgm_idx;
+DROP INDEX files_name_trgm_idx;
+
+DROP EXTENSION IF EXISTS pg_trgm;
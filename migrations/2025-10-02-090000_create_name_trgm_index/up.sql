@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX files_name_trgm_idx ON files USING GIN (name gin_trgm_ops);
+CREATE INDEX folders_name_trgm_idx ON folders USING GIN (name gin_trgm_ops);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+-- Child folders and their files go with the root folder
+DELETE FROM folders WHERE bucket_id = '00000000-0000-0000-0000-000000000001' AND parent_id IS NULL;
+DELETE FROM buckets WHERE id = '00000000-0000-0000-0000-000000000001';
+DELETE FROM organizations WHERE id = '00000000-0000-0000-0000-000000000000';
+DELETE FROM users WHERE id = '00000000-0000-0000-0000-000000000000';
+
+ALTER TABLE users DROP COLUMN username_changed_at;
+ALTER TABLE users DROP COLUMN pending_email;
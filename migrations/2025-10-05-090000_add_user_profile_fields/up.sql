@@ -0,0 +1,17 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN pending_email VARCHAR(255);
+ALTER TABLE users ADD COLUMN username_changed_at TIMESTAMP;
+
+-- Internal owner of system buckets, it has no password so it cannot sign in
+INSERT INTO users (id, name, email, username, is_verified)
+VALUES ('00000000-0000-0000-0000-000000000000', 'Blaze', 'system@blaze.internal', '.system', TRUE);
+
+INSERT INTO organizations (id, name, created_by)
+VALUES ('00000000-0000-0000-0000-000000000000', '.system', '00000000-0000-0000-0000-000000000000');
+
+-- Public so avatars are served through /f without a signature
+INSERT INTO buckets (id, name, organization_id, created_by, visibility)
+VALUES ('00000000-0000-0000-0000-000000000001', 'avatars', '00000000-0000-0000-0000-000000000000', '00000000-0000-0000-0000-000000000000', 'public');
+
+INSERT INTO folders (id, name, bucket_id, parent_id, created_by)
+VALUES (gen_random_uuid(), '', '00000000-0000-0000-0000-000000000001', NULL, '00000000-0000-0000-0000-000000000000');
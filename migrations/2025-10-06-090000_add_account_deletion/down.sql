@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM users WHERE id = '00000000-0000-0000-0000-000000000002';
+
+DROP INDEX users_deletion_scheduled_idx;
+ALTER TABLE users DROP COLUMN deletion_scheduled_at;
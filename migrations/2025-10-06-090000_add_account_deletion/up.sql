@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN deletion_scheduled_at TIMESTAMP;
+
+CREATE INDEX users_deletion_scheduled_idx ON users(deletion_scheduled_at) WHERE deletion_scheduled_at IS NOT NULL;
+
+-- Content authored by deleted accounts is reassigned to this user
+INSERT INTO users (id, name, email, username, is_verified)
+VALUES ('00000000-0000-0000-0000-000000000002', 'Deleted user', 'deleted@blaze.internal', '.deleted', FALSE);
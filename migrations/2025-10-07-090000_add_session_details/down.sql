@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX user_session_user_id_idx;
+DROP INDEX user_session_jti_idx;
+
+ALTER TABLE user_session DROP COLUMN last_used_at;
+ALTER TABLE user_session DROP COLUMN ip_address;
+ALTER TABLE user_session DROP COLUMN user_agent;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE user_session ADD COLUMN user_agent VARCHAR(511);
+ALTER TABLE user_session ADD COLUMN ip_address VARCHAR(64);
+ALTER TABLE user_session ADD COLUMN last_used_at TIMESTAMP NOT NULL DEFAULT now();
+
+UPDATE user_session SET last_used_at = COALESCE(updated_at, created_at);
+
+CREATE UNIQUE INDEX user_session_jti_idx ON user_session (jti);
+CREATE INDEX user_session_user_id_idx ON user_session (user_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE security_events;
+DROP TYPE security_event_kind;
+DROP TABLE rotated_refresh_tokens;
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Every refresh token a session has rotated away from, presenting one again means it leaked
+CREATE TABLE rotated_refresh_tokens (
+    jti UUID PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES user_session(id) ON DELETE CASCADE,
+    rotated_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX rotated_refresh_tokens_session_id_idx ON rotated_refresh_tokens (session_id);
+
+CREATE TYPE security_event_kind AS ENUM ('refresh_token_reuse');
+
+CREATE TABLE security_events (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind security_event_kind NOT NULL,
+    session_id UUID,
+    user_agent VARCHAR(511),
+    ip_address VARCHAR(64),
+    created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX security_events_user_id_idx ON security_events (user_id, created_at);
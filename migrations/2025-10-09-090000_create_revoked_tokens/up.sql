@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- Access tokens that must stop working before they expire, rows are useless once `expires_at` has passed
+CREATE TABLE revoked_tokens (
+    jti UUID PRIMARY KEY,
+    expires_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX revoked_tokens_expires_at_idx ON revoked_tokens (expires_at);
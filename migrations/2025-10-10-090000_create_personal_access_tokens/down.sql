@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE personal_access_tokens;
+DROP TYPE token_scope;
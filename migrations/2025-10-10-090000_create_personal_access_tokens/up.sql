@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TYPE token_scope AS ENUM ('read', 'write', 'admin');
+
+CREATE TABLE personal_access_tokens (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    -- First characters of the token so the user can tell tokens apart, the rest is only stored hashed
+    token_prefix VARCHAR(16) NOT NULL,
+    token_hash CHAR(64) NOT NULL UNIQUE,
+    scope token_scope NOT NULL,
+    -- NULL means every organization the user belongs to
+    organization_ids UUID[] CHECK (array_position(organization_ids, NULL) IS NULL),
+    expires_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+    last_used_ip VARCHAR(64),
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    UNIQUE (user_id, name)
+);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE organizations DROP COLUMN require_two_factor;
+DROP TABLE recovery_codes;
+ALTER TABLE users DROP COLUMN totp_last_step;
+ALTER TABLE users DROP COLUMN totp_enabled_at;
+ALTER TABLE users DROP COLUMN totp_secret;
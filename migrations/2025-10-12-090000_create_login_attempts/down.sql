@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM security_events WHERE kind = 'account_lockout';
+ALTER TYPE security_event_kind RENAME TO security_event_kind_old;
+CREATE TYPE security_event_kind AS ENUM ('refresh_token_reuse');
+ALTER TABLE security_events ALTER COLUMN kind TYPE security_event_kind USING kind::text::security_event_kind;
+DROP TYPE security_event_kind_old;
+
+DROP TABLE login_attempts;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- One row per throttled subject, `user:<username>` for an account and `ip:<address>` for a client
+CREATE TABLE login_attempts (
+    subject VARCHAR(320) PRIMARY KEY,
+    failures INTEGER NOT NULL DEFAULT 0,
+    locked_until TIMESTAMP,
+    last_failure_at TIMESTAMP NOT NULL DEFAULT now()
+);
+CREATE INDEX login_attempts_last_failure_at_idx ON login_attempts (last_failure_at);
+
+ALTER TYPE security_event_kind ADD VALUE 'account_lockout';
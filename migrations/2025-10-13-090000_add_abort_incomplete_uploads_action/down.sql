@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM lifecycle_rules WHERE action = 'abort_incomplete_uploads';
+
+ALTER TYPE lifecycle_action RENAME TO lifecycle_action_old;
+
+CREATE TYPE lifecycle_action AS ENUM ('expire', 'transition');
+
+ALTER TABLE lifecycle_rules ALTER COLUMN action TYPE lifecycle_action USING action::TEXT::lifecycle_action;
+
+DROP TYPE lifecycle_action_old;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Removes a bucket's scratch files of uploads that never completed, age_days after they were last written
+ALTER TYPE lifecycle_action ADD VALUE 'abort_incomplete_uploads';
@@ -0,0 +1,103 @@
+use crate::auth::auth_dto::ClientInfo;
+use crate::error::ApiResponse;
+use crate::schema::login_attempts;
+use actix_web::http::StatusCode;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+
+lazy_static! {
+    // Failures allowed before the first lockout, a client address gets more room since it can be shared
+    static ref MAX_ACCOUNT_ATTEMPTS: i32 = std::env::var("LOGIN_MAX_ACCOUNT_ATTEMPTS").ok().and_then(|v| v.parse().ok()).unwrap_or(5);
+    static ref MAX_IP_ATTEMPTS: i32 = std::env::var("LOGIN_MAX_IP_ATTEMPTS").ok().and_then(|v| v.parse().ok()).unwrap_or(20);
+    // Every failure past the limit doubles the lockout, up to the maximum
+    static ref BASE_LOCKOUT: i64 = std::env::var("LOGIN_BASE_LOCKOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(30);
+    static ref MAX_LOCKOUT: i64 = std::env::var("LOGIN_MAX_LOCKOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60 * 60);
+    // Counters start over once nothing failed for this long
+    static ref ATTEMPT_WINDOW: i64 = std::env::var("LOGIN_ATTEMPT_WINDOW_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(24 * 60 * 60);
+}
+
+// Usernames are counted whether or not the account exists, so lockouts do not reveal which ones do.
+// They are hashed so any length fits the subject column
+pub struct Subjects {
+    account: String,
+    ip: Option<String>,
+}
+
+pub fn subjects(username: &str, client: &ClientInfo) -> Subjects {
+    Subjects {
+        account: format!("user:{}", hex::encode(Sha256::digest(username.to_lowercase()))),
+        ip: client.ip_address.as_ref().map(|ip_address| format!("ip:{ip_address}")),
+    }
+}
+
+pub async fn ensure_allowed(subjects: &Subjects, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let candidates = std::iter::once(&subjects.account).chain(&subjects.ip).collect::<Vec<_>>();
+    let locked = login_attempts::table
+        .filter(login_attempts::subject.eq_any(candidates))
+        .filter(login_attempts::locked_until.gt(Utc::now().naive_utc()))
+        .count()
+        .get_result::<i64>(conn)
+        .await?;
+    if locked > 0 {
+        return Err(ApiResponse::new(StatusCode::TOO_MANY_REQUESTS, "Too many failed login attempts, try again later".to_string()));
+    }
+    Ok(())
+}
+
+// Returns whether the account got locked by this failure
+pub async fn record_failure(subjects: &Subjects, conn: &mut AsyncPgConnection) -> Result<bool, ApiResponse> {
+    let now = Utc::now().naive_utc();
+    diesel::delete(login_attempts::table)
+        .filter(login_attempts::last_failure_at.lt(now - Duration::seconds(*ATTEMPT_WINDOW)))
+        .filter(login_attempts::locked_until.is_null().or(login_attempts::locked_until.lt(now)))
+        .execute(conn)
+        .await?;
+    let account_locked = record(subjects.account.clone(), *MAX_ACCOUNT_ATTEMPTS, now, conn).await?;
+    if let Some(ip) = &subjects.ip && record(ip.clone(), *MAX_IP_ATTEMPTS, now, conn).await? {
+        warn!("Locked out {ip} after too many failed logins");
+    }
+    Ok(account_locked)
+}
+
+// Only the account starts over, otherwise logging into an own account would reset the address
+pub async fn reset(subjects: &Subjects, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    diesel::delete(login_attempts::table.find(&subjects.account))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn record(subject: String, max_attempts: i32, now: NaiveDateTime, conn: &mut AsyncPgConnection) -> Result<bool, ApiResponse> {
+    conn.transaction::<bool, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            diesel::insert_into(login_attempts::table)
+                .values((login_attempts::subject.eq(&subject), login_attempts::last_failure_at.eq(now)))
+                .on_conflict_do_nothing()
+                .execute(conn)
+                .await?;
+            let (failures, last_failure_at) = login_attempts::table.find(&subject)
+                .select((login_attempts::failures, login_attempts::last_failure_at))
+                .for_update()
+                .first::<(i32, NaiveDateTime)>(conn)
+                .await?;
+            let failures = if last_failure_at < now - Duration::seconds(*ATTEMPT_WINDOW) { 1 } else { failures + 1 };
+            let locked_until = (failures >= max_attempts).then(|| now + lockout(failures - max_attempts));
+            diesel::update(login_attempts::table.find(&subject))
+                .set((
+                    login_attempts::failures.eq(failures),
+                    login_attempts::locked_until.eq(locked_until),
+                    login_attempts::last_failure_at.eq(now)))
+                .execute(conn)
+                .await?;
+            Ok(locked_until.is_some())
+        })
+    }).await
+}
+
+fn lockout(excess_failures: i32) -> Duration {
+    let seconds = BASE_LOCKOUT.saturating_mul(1 << excess_failures.clamp(0, 30));
+    Duration::seconds(seconds.min(*MAX_LOCKOUT))
+}
@@ -1,6 +1,12 @@
 use crate::util::deserializer_util::{trim, trim_lower};
 use crate::util::validator_util::validate_password;
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use uuid::Uuid;
 use validator_derive::Validate;
 #[derive(Deserialize, Validate)]
 pub struct LoginDto {
@@ -19,6 +25,70 @@ pub struct TokenDto {
     pub token: String,
 }
 
+// Returned by the password step when the account has two-factor authentication enabled
+#[derive(Serialize)]
+pub struct MfaChallengeDto {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct MfaLoginDto {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+pub enum LoginResult {
+    // Access token and refresh token
+    Tokens(String, String),
+    // Challenge token that still needs a second factor
+    Challenge(String),
+}
+
+#[derive(Serialize)]
+pub struct SessionDto {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    // The session the request was made with
+    pub current: bool,
+}
+
+lazy_static! {
+    // Comma separated proxy addresses whose Forwarded and X-Forwarded-For headers are believed
+    static ref TRUSTED_PROXIES: Vec<IpAddr> = std::env::var("TRUSTED_PROXIES").unwrap_or_default()
+        .split(',')
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect();
+}
+
+// Where a session is used from, recorded when it is opened and on every refresh
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl From<&HttpRequest> for ClientInfo {
+    fn from(request: &HttpRequest) -> ClientInfo {
+        let user_agent = request.headers().get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(511).collect());
+        // Forwarding headers are client controlled, only a trusted proxy may set the address
+        let peer_ip = request.peer_addr().map(|addr| addr.ip());
+        let ip_address = if peer_ip.is_some_and(|ip| TRUSTED_PROXIES.contains(&ip)) {
+            // The peer address comes with a port, forwarded ones usually do not
+            request.connection_info().realip_remote_addr()
+                .map(|addr| addr.parse::<SocketAddr>().map(|addr| addr.ip().to_string()).unwrap_or(addr.to_string()))
+        } else {
+            peer_ip.map(|ip| ip.to_string())
+        };
+        let ip_address = ip_address.map(|addr| addr.chars().take(64).collect());
+        ClientInfo { user_agent, ip_address }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct CodeDto {
     pub code: String,
@@ -1,47 +1,65 @@
-use crate::auth::auth_dto::{CodeDto, LoginDto, TokenDto};
-use crate::auth::auth_middleware::jwt_auth;
+use crate::auth::auth_dto::{ClientInfo, CodeDto, LoginDto, LoginResult, MfaChallengeDto, MfaLoginDto, SessionDto, TokenDto};
+use crate::auth::auth_middleware::{jwt_auth, session_only};
+use crate::auth::auth_model::Claims;
 use crate::auth::auth_service;
 use crate::error::ApiResponse;
-use crate::user::user_model::User;
+use crate::user::user_model::{User, UserSession};
+use crate::util::jwt_util;
 use actix_web::cookie::{Cookie, SameSite};
-use actix_web::http::header::LOCATION;
+use actix_web::http::header::{CACHE_CONTROL, LOCATION};
 use actix_web::http::StatusCode;
 use actix_web::middleware::from_fn;
-use actix_web::web::{Json, Query, Redirect, ServiceConfig};
-use actix_web::{get, post, web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use actix_web::web::{Json, Path, Query, Redirect, ServiceConfig};
+use actix_web::{delete, get, post, web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use uuid::Uuid;
 
 #[post("login")]
-async fn login(dto: Json<LoginDto>) -> HttpResponse {
+async fn login(dto: Json<LoginDto>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
     let LoginDto { username, password } = dto.into_inner();
-    let (token, refresh_toke) = auth_service::login(username, password).await.unwrap();
+    let (token, refresh_toke) = match auth_service::login(username, password, ClientInfo::from(&request)).await? {
+        LoginResult::Tokens(token, refresh_toke) => (token, refresh_toke),
+        LoginResult::Challenge(mfa_token) => return Ok(HttpResponse::Ok().json(MfaChallengeDto { mfa_required: true, mfa_token })),
+    };
     let cookie = Cookie::build("refresh_token", refresh_toke)
         .path("/api/auth/refresh_token")
         .http_only(true)
         .secure(true)
         .same_site(SameSite::Strict)
         .finish();
-    HttpResponse::Ok().cookie(cookie)
+    Ok(HttpResponse::Ok().cookie(cookie)
         .content_type("application/json")
-        .json(Json(TokenDto{ token }))
+        .json(Json(TokenDto{ token })))
+}
+
+#[post("login/mfa")]
+async fn login_mfa(dto: Json<MfaLoginDto>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let MfaLoginDto { mfa_token, code } = dto.into_inner();
+    let (token, refresh_toke) = auth_service::complete_mfa_login(mfa_token, code, ClientInfo::from(&request)).await?;
+    let cookie = Cookie::build("refresh_token", refresh_toke)
+        .path("/api/auth/refresh_token")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .finish();
+    Ok(HttpResponse::Ok().cookie(cookie)
+        .content_type("application/json")
+        .json(Json(TokenDto{ token })))
 }
 
 #[post("refresh_token")]
-async fn refresh_token(request: HttpRequest) -> HttpResponse {
-    match request.cookie("refresh_token") {
-        Some(refresh_token) => {
-            let (token, refresh_token) = auth_service::refresh_token(refresh_token.value().to_string()).await.unwrap();
-            let cookie = Cookie::build("refresh_token", refresh_token)
-                .path("/api/auth/refresh_token")
-                .http_only(true)
-                .secure(true)
-                .same_site(SameSite::Strict)
-                .finish();
-            HttpResponse::Ok().cookie(cookie)
-            .content_type("application/json")
-            .json(Json(TokenDto{ token }))
-        },
-        _ => {HttpResponse::BadRequest().finish()}
-    }
+async fn refresh_token(request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let refresh_token = request.cookie("refresh_token")
+        .ok_or(ApiResponse::new(StatusCode::UNAUTHORIZED, "Missing refresh token".to_string()))?;
+    let (token, refresh_token) = auth_service::refresh_token(refresh_token.value().to_string(), ClientInfo::from(&request)).await?;
+    let cookie = Cookie::build("refresh_token", refresh_token)
+        .path("/api/auth/refresh_token")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .finish();
+    Ok(HttpResponse::Ok().cookie(cookie)
+        .content_type("application/json")
+        .json(Json(TokenDto{ token })))
 }
 
 #[get("google")]
@@ -49,19 +67,22 @@ async fn google_auth() -> impl Responder {
     Redirect::to(auth_service::google_redirect_url())
 }
 #[get("google/callback")]
-async fn google_callback(code: Query<CodeDto>) -> HttpResponse {
+async fn google_callback(code: Query<CodeDto>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
     let CodeDto { code } = code.into_inner();
-    let (token, refresh_toke) = auth_service::google_oauth(code).await.unwrap();
+    let (token, refresh_toke) = match auth_service::google_oauth(code, ClientInfo::from(&request)).await? {
+        LoginResult::Tokens(token, refresh_toke) => (token, refresh_toke),
+        LoginResult::Challenge(mfa_token) => return Ok(mfa_redirect(mfa_token)),
+    };
     let cookie = Cookie::build("refresh_token", refresh_toke)
         .path("/api/auth/refresh_token")
         .http_only(true)
         .secure(true)
         .same_site(SameSite::Strict)
         .finish();
-    HttpResponse::TemporaryRedirect()
+    Ok(HttpResponse::TemporaryRedirect()
         .append_header((LOCATION, format!("/auth/success?token={token}")))
         .cookie(cookie)
-        .finish()
+        .finish())
 }
 
 #[get("github")]
@@ -71,21 +92,39 @@ async fn github_auth() -> impl Responder {
 
 
 #[get("github/callback")]
-async fn github_callback(code: Query<CodeDto>) -> HttpResponse {
+async fn github_callback(code: Query<CodeDto>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
     let CodeDto { code } = code.into_inner();
-    let (token, refresh_toke) = auth_service::github_oauth(code).await.unwrap();
+    let (token, refresh_toke) = match auth_service::github_oauth(code, ClientInfo::from(&request)).await? {
+        LoginResult::Tokens(token, refresh_toke) => (token, refresh_toke),
+        LoginResult::Challenge(mfa_token) => return Ok(mfa_redirect(mfa_token)),
+    };
     let cookie = Cookie::build("refresh_token", refresh_toke)
         .path("/api/auth/refresh_token")
         .http_only(true)
         .secure(true)
         .same_site(SameSite::Strict)
         .finish();
-    HttpResponse::TemporaryRedirect()
+    Ok(HttpResponse::TemporaryRedirect()
         .append_header((LOCATION, format!("/auth/success?token={token}")))
         .cookie(cookie)
+        .finish())
+}
+
+// The frontend asks for a code and finishes the login through `login/mfa`. The challenge goes in
+// the fragment, which browsers never send to a server, so it stays out of logs and Referer headers
+fn mfa_redirect(mfa_token: String) -> HttpResponse {
+    HttpResponse::TemporaryRedirect()
+        .append_header((LOCATION, format!("/auth/mfa#mfa_token={mfa_token}")))
         .finish()
 }
 
+#[get("/.well-known/jwks.json")]
+async fn jwks() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "public, max-age=300"))
+        .json(jwt_util::jwks())
+}
+
 #[get("me")]
 async fn who_am_i(request: HttpRequest) -> Result<impl Responder, ApiResponse> {
     if let Some(user) = request.extensions().get::<User>() {
@@ -95,13 +134,61 @@ async fn who_am_i(request: HttpRequest) -> Result<impl Responder, ApiResponse> {
     }
 }
 
+#[post("logout", wrap = "from_fn(session_only)")]
+async fn logout(request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let extensions = request.extensions();
+    let session = extensions.get::<UserSession>().unwrap();
+    let claims = extensions.get::<Claims>().unwrap();
+    auth_service::logout(session, claims).await?;
+    // Same name and path as the cookie set on login, otherwise the browser keeps it
+    let mut cookie = Cookie::build("refresh_token", "")
+        .path("/api/auth/refresh_token")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .finish();
+    cookie.make_removal();
+    Ok(HttpResponse::NoContent().cookie(cookie).finish())
+}
+
+#[get("sessions", wrap = "from_fn(session_only)")]
+async fn list_sessions(request: HttpRequest) -> Result<Json<Vec<SessionDto>>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let session = extensions.get::<UserSession>().unwrap();
+    let sessions = auth_service::list_sessions(user, session).await?;
+    Ok(Json(sessions))
+}
+
+#[delete("sessions", wrap = "from_fn(session_only)")]
+async fn revoke_other_sessions(request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let session = extensions.get::<UserSession>().unwrap();
+    auth_service::revoke_other_sessions(user, session).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("sessions/{id}", wrap = "from_fn(session_only)")]
+async fn revoke_session(id: Path<Uuid>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    auth_service::revoke_session(user, id.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
 pub fn auth_routes(cfg: &mut ServiceConfig) {
     cfg.service(login);
+    cfg.service(login_mfa);
     cfg.service(google_auth);
     cfg.service(github_auth);
     cfg.service(refresh_token);
 
     cfg.service(web::scope("")
         .wrap(from_fn(jwt_auth))
-        .service(who_am_i));
+        .service(who_am_i)
+        .service(logout)
+        .service(list_sessions)
+        .service(revoke_other_sessions)
+        .service(revoke_session));
 }
@@ -3,26 +3,63 @@ use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::http::StatusCode;
 use actix_web::{Error, HttpMessage};
 use actix_web::middleware::Next;
+use crate::auth::auth_dto::ClientInfo;
+use crate::auth::auth_service;
 use crate::error::ApiResponse;
+use crate::mfa::mfa_service;
+use crate::token::token_model::{PersonalAccessToken, TokenScope};
+use crate::token::token_service::{self, OrganizationLimit, TOKEN_PREFIX};
 use crate::util::jwt_util::decode;
-use crate::user::user_service;
 
 pub async fn jwt_auth (request: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
-    let auth_header = request.headers().get("Authorization");
-    if let None = auth_header {
+    let auth_header = request.headers().get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .ok_or(ApiResponse::new(StatusCode::UNAUTHORIZED, "Missing token".to_string()))?;
+    let token = auth_header.strip_prefix("Bearer ").unwrap_or(auth_header).to_string();
 
+    if token.starts_with(TOKEN_PREFIX) {
+        let ClientInfo { ip_address, .. } = ClientInfo::from(request.request());
+        let (user, token) = token_service::authenticate(&token, request.method(), ip_address).await?;
+        debug!("Token auth: {:?} ({})", user.id, token.name);
+        let limit = OrganizationLimit { allowed: token.organization_ids.clone(), blocked: mfa_service::blocked_organizations(&user).await? };
+        request.extensions_mut().insert(user);
+        request.extensions_mut().insert(token);
+        return token_service::limit_organizations(limit, next.call(request)).await;
+    }
+
+    // Expired, malformed and tokens signed by a retired key are all just not signed in
+    let claims = match decode(&token) {
+        Err(e) => {
+            debug!("JWT rejected: {e}");
+            return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Invalid or expired token".to_string()).into());
+        }
+        Ok(token_data) => token_data.claims,
     };
-    let auth_header = auth_header.unwrap().to_str().unwrap();
-    let sub = match decode(auth_header) {
-        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
-        Ok(token_data) => token_data.claims.sub,
-    };
-    debug!("JWT auth: {:?}", sub);
+    debug!("JWT auth: {:?}", claims.sub);
 
-    let user = user_service::find_by_id(sub)
-        .await.ok_or(ApiResponse::new(StatusCode::UNAUTHORIZED, "Invalid token".to_string()))?;
+    let (user, session) = auth_service::authenticate(&claims).await?;
+    let limit = OrganizationLimit { blocked: mfa_service::blocked_organizations(&user).await?, ..Default::default() };
 
     request.extensions_mut().insert(user);
+    request.extensions_mut().insert(session);
+    request.extensions_mut().insert(claims);
+
+    token_service::limit_organizations(limit, next.call(request)).await
+}
+
+// Credentials and the account itself are only managed from a signed in session,
+// so a leaked token cannot mint new tokens or lock the owner out. Wrapped inside `jwt_auth`
+pub async fn session_only(request: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if request.extensions().contains::<PersonalAccessToken>() {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Personal access tokens cannot be used for this request, sign in instead".to_string()).into());
+    }
+    next.call(request).await
+}
 
+// Members, secrets and ownership of an organization need an admin token. Wrapped inside `jwt_auth`
+pub async fn admin_token(request: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if request.extensions().get::<PersonalAccessToken>().is_some_and(|token| token.scope != TokenScope::ADMIN) {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "The scope of this token does not allow this request".to_string()).into());
+    }
     next.call(request).await
-}
\ No newline at end of file
+}
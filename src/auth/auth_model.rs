@@ -1,9 +1,13 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: Uuid,
+    // Session the token was issued for, revoking the session revokes the token
+    pub sid: Uuid,
+    // Lets a single access token be put on the revocation list
+    pub jti: Uuid,
     pub iss: String,
     pub iat: i64,
     pub exp: i64,
@@ -16,4 +20,14 @@ pub struct RefreshTokenClaims {
     pub iss: String,
     pub iat: i64,
     pub exp: i64,
-}
\ No newline at end of file
+}
+// Links mailed to a user and login challenges, `aud` keeps them from being accepted as access or refresh tokens
+#[derive(Serialize, Deserialize)]
+pub struct EmailTokenClaims {
+    pub sub: Uuid,
+    pub email: String,
+    pub aud: String,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+}
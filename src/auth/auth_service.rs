@@ -1,18 +1,23 @@
 use crate::config::db_config;
 use crate::error::ApiResponse;
+use crate::mfa::mfa_service;
 use crate::schema::users;
 use crate::schema::users::username;
-use crate::user::user_model::{User, UserSession};
+use crate::user::user_model::{SecurityEvent, SecurityEventKind, User, UserSession};
+use crate::user::user_service;
 use crate::util::jwt_util;
 use actix_web::http::StatusCode;
-use chrono::Utc;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use diesel::dsl;
 use diesel::prelude::*;
-use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
 use lazy_static::lazy_static;
 use reqwest::header::USER_AGENT;
 use uuid::Uuid;
-use crate::auth::auth_dto::{GithubOauthResponse, GithubUser, GoogleUser};
-use crate::schema::user_session;
+use crate::auth::attempt_service::{self, Subjects};
+use crate::auth::auth_dto::{ClientInfo, GithubOauthResponse, GithubUser, GoogleUser, LoginResult, SessionDto};
+use crate::auth::auth_model::Claims;
+use crate::schema::{revoked_tokens, rotated_refresh_tokens, security_events, user_session};
 
 lazy_static! {
     static ref GOOGLE_CLIENT_ID: String = std::env::var("GOOGLE_OAUTH_CLIENT_ID").unwrap().to_string();
@@ -26,23 +31,80 @@ lazy_static! {
     static ref GITHUB_REDIRECT_URI: String = std::env::var("GITHUB_REDIRECT_URI").unwrap().to_string();
     static ref GITHUB_AUTH_ENDPOINT: String = "https://github.com/login/oauth/authorize".to_string();
     static ref GITHUB_SCOPE: String = "user".to_string();
+
+    static ref SESSION_IDLE_TIMEOUT: i64 = std::env::var("SESSION_IDLE_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(7 * 24 * 60 * 60);
+    static ref SESSION_ABSOLUTE_TIMEOUT: i64 = std::env::var("SESSION_ABSOLUTE_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(30 * 24 * 60 * 60);
+    // How long a just rotated refresh token still counts as the current one, e.g. for two tabs refreshing at once
+    static ref REFRESH_REUSE_GRACE: i64 = std::env::var("REFRESH_REUSE_GRACE_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(10);
+
+    // Stands in for the password of unknown users so a failed login takes as long either way
+    static ref DUMMY_PASSWORD_HASH: String = bcrypt::hash(Uuid::now_v7().to_string(), 10).expect("Cannot hash dummy password");
 }
-pub async fn login(uname: String, password: String) -> Result<(String, String), ApiResponse>  {
+pub async fn login(uname: String, password: String, client: ClientInfo) -> Result<LoginResult, ApiResponse>  {
+    let subjects = attempt_service::subjects(&uname, &client);
     let pool = db_config::get_connection_pool().await;
     let mut conn = pool.get().await?;
+    attempt_service::ensure_allowed(&subjects, &mut conn).await?;
     let user = users::table
         .filter(username.eq(uname))
         .first::<User>(&mut conn)
-        .await?;
+        .await
+        .optional()?;
     drop(conn);
-    let actual_password = &user.password.clone().ok_or(ApiResponse::new(StatusCode::FORBIDDEN, "Password login is not available".to_string()))?;
-    match bcrypt::verify(password, actual_password) {
-        Ok(is_verified) if is_verified => (),
-        _ => return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Unauthorized".to_string())),
-    };
+    // Unknown users and accounts without a password still pay for a hash check and get the same answer
+    let actual_password = user.as_ref().and_then(|user| user.password.as_deref()).unwrap_or(&DUMMY_PASSWORD_HASH);
+    let is_verified = bcrypt::verify(password, actual_password).unwrap_or(false);
+
+    let mut conn = db_config::get_connection().await?;
+    match user {
+        Some(user) if is_verified && user.password.is_some() => {
+            let result = finish_login(user, client, &mut conn).await?;
+            // With 2FA the account only starts over once the second step succeeded
+            if let LoginResult::Tokens(..) = result {
+                attempt_service::reset(&subjects, &mut conn).await?;
+            }
+            Ok(result)
+        }
+        user => {
+            record_failed_login(&subjects, user.as_ref(), &client, &mut conn).await?;
+            Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Invalid username or password".to_string()))
+        }
+    }
+}
 
+pub async fn complete_mfa_login(mfa_token: String, code: String, client: ClientInfo) -> Result<(String, String), ApiResponse> {
     let mut conn = db_config::get_connection().await?;
-    create_token(user, &mut conn).await
+    let user = mfa_service::challenged_user(&mfa_token, &mut conn).await?;
+    // Codes count against the same account, a known password does not buy unlimited guesses
+    let subjects = attempt_service::subjects(&user.username, &client);
+    attempt_service::ensure_allowed(&subjects, &mut conn).await?;
+    if !mfa_service::verify_login_code(&user, &code, &mut conn).await? {
+        record_failed_login(&subjects, Some(&user), &client, &mut conn).await?;
+        return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Invalid two-factor code".to_string()));
+    }
+    attempt_service::reset(&subjects, &mut conn).await?;
+    create_token(user, client, &mut conn).await
+}
+
+async fn record_failed_login(subjects: &Subjects, user: Option<&User>, client: &ClientInfo, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let locked = attempt_service::record_failure(subjects, conn).await?;
+    if let (true, Some(user)) = (locked, user) {
+        warn!("Locked account {} after too many failed logins", user.id);
+        diesel::insert_into(security_events::table)
+            .values(SecurityEvent::new(user.id, SecurityEventKind::LOCKOUT, None, client.user_agent.clone(), client.ip_address.clone()))
+            .execute(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+// The password or the OAuth provider only counts as the first factor when 2FA is enabled
+async fn finish_login(user: User, client: ClientInfo, conn: &mut AsyncPgConnection) -> Result<LoginResult, ApiResponse> {
+    if user.totp_enabled_at.is_some() {
+        return Ok(LoginResult::Challenge(mfa_service::issue_challenge(&user)?));
+    }
+    let (token, refresh_token) = create_token(user, client, conn).await?;
+    Ok(LoginResult::Tokens(token, refresh_token))
 }
 
 pub fn google_redirect_url() -> String {
@@ -61,7 +123,7 @@ pub fn github_redirect_url() -> String {
     format!("{endpoint}?client_id={github_client_id}&redirect_uri={github_redirect_uri}&scope={scope}")
 }
 
-pub async fn google_oauth(code: String) -> Result<(String, String), ApiResponse> {
+pub async fn google_oauth(code: String, client: ClientInfo) -> Result<LoginResult, ApiResponse> {
     let form = reqwest::multipart::Form::new()
         .text("client_id", &*GOOGLE_CLIENT_ID)
         .text("client_secret", &*GOOGLE_CLIENT_SECRET)
@@ -82,10 +144,10 @@ pub async fn google_oauth(code: String) -> Result<(String, String), ApiResponse>
         .header("Authorization", format!("Bearer {}", res.access_token))
         .send().await.unwrap()
         .json::<GoogleUser>().await.unwrap();
-    find_user_and_create_token(user.email, user.name, user.picture).await
+    find_user_and_create_token(user.email, user.name, user.picture, client).await
 }
 
-pub async fn github_oauth(code: String) -> Result<(String, String), ApiResponse> {
+pub async fn github_oauth(code: String, client: ClientInfo) -> Result<LoginResult, ApiResponse> {
     let form = reqwest::multipart::Form::new()
         .text("client_id", "Ov23liI2rrVysGbUPvxj")
         .text("client_secret", "e3f503ba66a0185c046eb7ec429b117d4acc7e94")
@@ -108,10 +170,10 @@ pub async fn github_oauth(code: String) -> Result<(String, String), ApiResponse>
         .send().await.unwrap()
         .json::<GithubUser>().await.unwrap();
 
-    find_user_and_create_token(user.email, user.name, user.avatar_url).await
+    find_user_and_create_token(user.email, user.name, user.avatar_url, client).await
 }
 
-async fn find_user_and_create_token(email: String, name: String, image: String) -> Result<(String, String), ApiResponse> {
+async fn find_user_and_create_token(email: String, name: String, image: String, client: ClientInfo) -> Result<LoginResult, ApiResponse> {
     let mut conn = db_config::get_connection().await?;
     let existing_user: Option<User> = users::table
         .filter(users::email.eq(&email))
@@ -123,60 +185,247 @@ async fn find_user_and_create_token(email: String, name: String, image: String)
         Some(user) => user,
         _ => {
             let user = User::new(name.to_string(), name.to_string().to_lowercase().replace(" ", "_") + &*Uuid::now_v7().to_string(), email, None, Some(image));
-            diesel::insert_into(users::table)
+            let user = diesel::insert_into(users::table)
                 .values(user)
                 .get_result(&mut conn)
-                .await?
+                .await?;
+            if let Err(e) = user_service::send_verification(&user, &user.email, &mut conn).await {
+                error!("Cannot send verification mail to {}: {e}", user.id);
+            }
+            user
         }
     };
-    create_token(user, &mut conn).await
+    finish_login(user, client, &mut conn).await
 }
 
-async fn create_token(user: User, conn: &mut AsyncPgConnection) -> Result<(String, String), ApiResponse> {
-    let token = match jwt_util::issue(user.id) {
+async fn create_token(user: User, client: ClientInfo, conn: &mut AsyncPgConnection) -> Result<(String, String), ApiResponse> {
+    let jti = Uuid::now_v7();
+    let session = diesel::insert_into(user_session::table)
+        .values(UserSession::new(jti, user.id, client.user_agent, client.ip_address))
+        .get_result::<UserSession>(conn)
+        .await?;
+    let token = match jwt_util::issue(user.id, session.id) {
         Ok(token) => token,
         _ => return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Unauthorized".to_string()))
     };
-    let jti = Uuid::now_v7();
     let refresh_token = match jwt_util::issue_refresh_token(user.id, jti) {
         Ok(token) => token,
         _ => return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Unauthorized".to_string()))
     };
-    let _ = diesel::insert_into(user_session::table)
-        .values(UserSession::new(jti, user.id))
-        .get_result::<UserSession>(conn)
-        .await?;
     Ok((token, refresh_token))
 }
 
-pub async fn refresh_token(token: String) -> Result<(String, String), ApiResponse> {
-    let token_claims = jwt_util::decode_refresh_token(&token).unwrap();
-    let jti = token_claims.claims.jti;
-    let user_id = token_claims.claims.sub;
-    println!("User ID: {}", user_id);
-    println!("JTI: {}", jti);
+// Oldest `last_used_at` and `created_at` a session may have and still be accepted
+fn session_cutoffs() -> (NaiveDateTime, NaiveDateTime) {
+    let now = Utc::now().naive_utc();
+    (now - Duration::seconds(*SESSION_IDLE_TIMEOUT), now - Duration::seconds(*SESSION_ABSOLUTE_TIMEOUT))
+}
+
+// Resolves an access token to its user, the token dies with its session
+pub async fn authenticate(claims: &Claims) -> Result<(User, UserSession), ApiResponse> {
+    let (idle_cutoff, absolute_cutoff) = session_cutoffs();
     let mut conn = db_config::get_connection().await?;
-    let _ = user_session::table
+    users::table
+        .inner_join(user_session::table)
+        .filter(users::id.eq(claims.sub))
+        .filter(user_session::id.eq(claims.sid))
+        .filter(user_session::last_used_at.gt(idle_cutoff))
+        .filter(user_session::created_at.gt(absolute_cutoff))
+        .filter(dsl::not(dsl::exists(revoked_tokens::table.find(claims.jti))))
+        .select((User::as_select(), UserSession::as_select()))
+        .first::<(User, UserSession)>(&mut conn)
+        .await
+        .optional()?
+        .ok_or(ApiResponse::new(StatusCode::UNAUTHORIZED, "Session has expired or was revoked".to_string()))
+}
+
+pub async fn list_sessions(user: &User, current: &UserSession) -> Result<Vec<SessionDto>, ApiResponse> {
+    let (idle_cutoff, absolute_cutoff) = session_cutoffs();
+    let mut conn = db_config::get_connection().await?;
+    // Expired sessions cannot be used anymore, listing is a good moment to drop them
+    diesel::delete(user_session::table)
+        .filter(user_session::user_id.eq(user.id))
+        .filter(user_session::last_used_at.le(idle_cutoff).or(user_session::created_at.le(absolute_cutoff)))
+        .execute(&mut conn)
+        .await?;
+    let sessions = user_session::table
+        .filter(user_session::user_id.eq(user.id))
+        .order(user_session::last_used_at.desc())
+        .load::<UserSession>(&mut conn)
+        .await?
+        .into_iter()
+        .map(|session| SessionDto {
+            id: session.id,
+            current: session.id == current.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+        })
+        .collect();
+    Ok(sessions)
+}
+
+pub async fn revoke_session(user: &User, session_id: Uuid) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let deleted = diesel::delete(user_session::table)
+        .filter(user_session::id.eq(session_id))
+        .filter(user_session::user_id.eq(user.id))
+        .execute(&mut conn)
+        .await?;
+    if deleted == 0 {
+        return Err(ApiResponse::new(StatusCode::NOT_FOUND, "Session not found".to_string()));
+    }
+    Ok(())
+}
+
+// Signs the user out everywhere except the session making the request
+pub async fn revoke_other_sessions(user: &User, current: &UserSession) -> Result<usize, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let deleted = diesel::delete(user_session::table)
+        .filter(user_session::user_id.eq(user.id))
+        .filter(user_session::id.ne(current.id))
+        .execute(&mut conn)
+        .await?;
+    Ok(deleted)
+}
+
+// The session is gone for good, the token making the request is revoked as well in case it is cached somewhere
+pub async fn logout(session: &UserSession, claims: &Claims) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    revoke_access_token(claims, &mut conn).await?;
+    diesel::delete(user_session::table.find(session.id))
+        .execute(&mut conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn revoke_access_token(claims: &Claims, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let now = Utc::now().naive_utc();
+    // An entry is only needed until its token would have expired anyway
+    diesel::delete(revoked_tokens::table.filter(revoked_tokens::expires_at.le(now)))
+        .execute(conn)
+        .await?;
+    let expires_at = DateTime::from_timestamp(claims.exp, 0).map(|exp| exp.naive_utc()).unwrap_or(now);
+    diesel::insert_into(revoked_tokens::table)
+        .values((revoked_tokens::jti.eq(claims.jti), revoked_tokens::expires_at.eq(expires_at)))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn refresh_token(token: String, client: ClientInfo) -> Result<(String, String), ApiResponse> {
+    let claims = jwt_util::decode_refresh_token(&token)
+        .map_err(|_| ApiResponse::new(StatusCode::UNAUTHORIZED, "Invalid refresh token".to_string()))?
+        .claims;
+    let jti = claims.jti;
+    let user_id = claims.sub;
+    let mut conn = db_config::get_connection().await?;
+    let session = user_session::table
         .filter(user_session::jti.eq(jti))
         .filter(user_session::user_id.eq(user_id))
         .get_result::<UserSession>(&mut conn)
-        .await?;
+        .await
+        .optional()?;
+    let Some(session) = session else {
+        if let Some(session) = recently_rotated(jti, user_id, &mut conn).await? {
+            return issue_tokens(user_id, session.id, session.jti);
+        }
+        revoke_family_on_reuse(jti, user_id, client, &mut conn).await?;
+        return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Invalid refresh token".to_string()));
+    };
+    let (idle_cutoff, absolute_cutoff) = session_cutoffs();
+    if session.last_used_at <= idle_cutoff || session.created_at <= absolute_cutoff {
+        diesel::delete(user_session::table.find(session.id))
+            .execute(&mut conn)
+            .await?;
+        return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Session has expired, sign in again".to_string()));
+    }
     let updated_jti = Uuid::now_v7();
-    let _ = diesel::update(user_session::dsl::user_session)
-        .filter(user_session::jti.eq(jti))
+    let session_id = session.id;
+    let ClientInfo { user_agent, ip_address } = client;
+    let rotated = conn.transaction::<bool, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let now = Utc::now().naive_utc();
+            // Only the request still holding the current jti gets to rotate it
+            let rotated = diesel::update(user_session::table.find(session_id))
+                .filter(user_session::jti.eq(jti))
+                .set((
+                    user_session::jti.eq(updated_jti),
+                    user_session::updated_at.eq(now),
+                    user_session::last_used_at.eq(now),
+                    user_session::user_agent.eq(user_agent),
+                    user_session::ip_address.eq(ip_address)))
+                .execute(conn)
+                .await?;
+            if rotated == 0 {
+                return Ok(false);
+            }
+            diesel::insert_into(rotated_refresh_tokens::table)
+                .values((
+                    rotated_refresh_tokens::jti.eq(jti),
+                    rotated_refresh_tokens::session_id.eq(session_id),
+                    rotated_refresh_tokens::rotated_at.eq(now)))
+                .execute(conn)
+                .await?;
+            Ok(true)
+        })
+    }).await?;
+    if !rotated {
+        // A concurrent refresh rotated it first, hand out what it rotated to
+        return match recently_rotated(jti, user_id, &mut conn).await? {
+            Some(session) => issue_tokens(user_id, session.id, session.jti),
+            None => Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Invalid refresh token".to_string())),
+        };
+    }
+    issue_tokens(user_id, session_id, updated_jti)
+}
+
+fn issue_tokens(user_id: Uuid, session_id: Uuid, jti: Uuid) -> Result<(String, String), ApiResponse> {
+    let token = jwt_util::issue(user_id, session_id)
+        .map_err(|_| ApiResponse::new(StatusCode::UNAUTHORIZED, "Unauthorized".to_string()))?;
+    let refresh_token = jwt_util::issue_refresh_token(user_id, jti)
+        .map_err(|_| ApiResponse::new(StatusCode::UNAUTHORIZED, "Unauthorized".to_string()))?;
+    Ok((token, refresh_token))
+}
+
+// The session a refresh token was rotated out of within REFRESH_REUSE_GRACE_SECS, such a token is not reuse yet
+async fn recently_rotated(jti: Uuid, user_id: Uuid, conn: &mut AsyncPgConnection) -> Result<Option<UserSession>, ApiResponse> {
+    let session = rotated_refresh_tokens::table.find(jti)
+        .inner_join(user_session::table)
         .filter(user_session::user_id.eq(user_id))
-        .set((
-            user_session::jti.eq(updated_jti),
-            user_session::updated_at.eq(Utc::now().naive_utc())))
-        .execute(&mut conn)
-        .await?;
-    let token = match jwt_util::issue(user_id) {
-        Ok(token) => token,
-        _ => return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Unauthorized".to_string()))
-    };
-    let refresh_token = match jwt_util::issue_refresh_token(user_id, updated_jti) {
-        Ok(token) => token,
-        _ => return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "Unauthorized".to_string()))
+        .filter(rotated_refresh_tokens::rotated_at.gt(Utc::now().naive_utc() - Duration::seconds(*REFRESH_REUSE_GRACE)))
+        .select(UserSession::as_select())
+        .first::<UserSession>(conn)
+        .await
+        .optional()?;
+    Ok(session)
+}
+
+// A rotated token coming back means someone else holds the family, so the whole session goes
+async fn revoke_family_on_reuse(jti: Uuid, user_id: Uuid, client: ClientInfo, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let session_id = rotated_refresh_tokens::table.find(jti)
+        .inner_join(user_session::table)
+        .filter(user_session::user_id.eq(user_id))
+        .select(rotated_refresh_tokens::session_id)
+        .first::<Uuid>(conn)
+        .await
+        .optional()?;
+    let Some(session_id) = session_id else {
+        return Ok(());
     };
-    Ok((token, refresh_token))
-}
\ No newline at end of file
+    warn!("Refresh token reuse for user {user_id}, revoking session {session_id}");
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            diesel::delete(user_session::table.find(session_id))
+                .execute(conn)
+                .await?;
+            diesel::insert_into(security_events::table)
+                .values(SecurityEvent::new(user_id, SecurityEventKind::REUSE, Some(session_id), client.user_agent, client.ip_address))
+                .execute(conn)
+                .await?;
+            Ok(())
+        })
+    }).await
+}
@@ -1,5 +1,6 @@
 pub mod auth_handler;
 mod auth_dto;
 mod auth_service;
+mod attempt_service;
 pub mod auth_model;
 pub mod auth_middleware;
\ No newline at end of file
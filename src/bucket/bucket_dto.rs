@@ -1,13 +1,16 @@
 use serde::Deserialize;
 use uuid::Uuid;
 use validator_derive::Validate;
-use crate::bucket::bucket_model::BucketVisibility;
+use crate::bucket::bucket_model::{BucketVisibility, CorsRule};
+use crate::file::file_model::ResponseHeaders;
 
 #[derive(Deserialize, Validate)]
 pub struct CreateBucketDto {
     #[validate(length(min = 4, max = 255))]
     pub name: String,
-    pub organization_id: Uuid
+    pub organization_id: Uuid,
+    #[serde(default)]
+    pub encrypted: bool,
 }
 
 #[derive(Deserialize, Validate)]
@@ -16,7 +19,8 @@ pub struct UpdateBucketDto {
 
     #[validate(length(min = 4, max = 255))]
     pub name: Option<String>,
-    pub visibility: Option<BucketVisibility>
+    pub visibility: Option<BucketVisibility>,
+    pub encrypted: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -30,4 +34,16 @@ pub struct SearchBucketDto {
 #[derive(Deserialize)]
 pub struct BucketIdDTO {
     pub bucket_id: Uuid
-}
\ No newline at end of file
+}
+#[derive(Deserialize)]
+pub struct UpdateBucketCorsDto {
+    pub bucket_id: Uuid,
+    pub rules: Vec<CorsRule>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateBucketHeadersDto {
+    pub bucket_id: Uuid,
+    #[serde(flatten)]
+    pub headers: ResponseHeaders,
+}
@@ -1,12 +1,12 @@
-use crate::bucket::bucket_dto::{BucketIdDTO, SearchBucketDto, UpdateBucketDto};
+use crate::bucket::bucket_dto::{BucketIdDTO, SearchBucketDto, UpdateBucketCorsDto, UpdateBucketDto, UpdateBucketHeadersDto};
 use crate::{bucket::{bucket_dto::CreateBucketDto, bucket_model::Bucket, bucket_service}, error::ApiResponse, user::user_model::User};
 use actix_web::web::{Path, Query};
 use actix_web::{delete, get, post, put, web::{Json, ServiceConfig}, HttpMessage, HttpRequest};
 
 #[post("")]
 async fn create(dto: Json<CreateBucketDto>, request: HttpRequest) -> Result<Json<Bucket>, ApiResponse> {
-    let CreateBucketDto { name, organization_id } = dto.into_inner();
-    let bucket = bucket_service::create(name, organization_id, request.extensions().get::<User>().unwrap()).await?;
+    let CreateBucketDto { name, organization_id, encrypted } = dto.into_inner();
+    let bucket = bucket_service::create(name, organization_id, encrypted, request.extensions().get::<User>().unwrap()).await?;
     Ok(Json(bucket))
 }
 
@@ -24,8 +24,26 @@ async fn list(dto: Query<SearchBucketDto>, request: HttpRequest) -> Result<Json<
 async fn update(dto: Json<UpdateBucketDto>, request: HttpRequest) -> Result<Json<Bucket>, ApiResponse> {
     let extensions = request.extensions();
     let user = extensions.get::<User>().unwrap();
-    let UpdateBucketDto { bucket_id, name, visibility} = dto.into_inner();
-    let bucket = bucket_service::update_bucket(bucket_id, name, visibility, user).await?;
+    let UpdateBucketDto { bucket_id, name, visibility, encrypted } = dto.into_inner();
+    let bucket = bucket_service::update_bucket(bucket_id, name, visibility, encrypted, user).await?;
+    Ok(Json(bucket))
+}
+
+#[put("cors")]
+async fn update_cors(dto: Json<UpdateBucketCorsDto>, request: HttpRequest) -> Result<Json<Bucket>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let UpdateBucketCorsDto { bucket_id, rules } = dto.into_inner();
+    let bucket = bucket_service::update_cors(bucket_id, rules, user).await?;
+    Ok(Json(bucket))
+}
+
+#[put("headers")]
+async fn update_headers(dto: Json<UpdateBucketHeadersDto>, request: HttpRequest) -> Result<Json<Bucket>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let UpdateBucketHeadersDto { bucket_id, headers } = dto.into_inner();
+    let bucket = bucket_service::update_headers(bucket_id, headers, user).await?;
     Ok(Json(bucket))
 }
 
@@ -43,5 +61,7 @@ pub fn bucket_routes(cfg: &mut ServiceConfig) {
     cfg.service(list);
     cfg.service(create);
     cfg.service(update);
+    cfg.service(update_cors);
+    cfg.service(update_headers);
     cfg.service(delete);
 }
\ No newline at end of file
@@ -0,0 +1,84 @@
+use crate::bucket::bucket_model::CorsRule;
+use crate::bucket::bucket_service;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+
+// Applies the bucket's CORS rules to /f requests and answers preflight requests itself
+pub async fn bucket_cors(request: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let mut segments = request.match_info().unprocessed().trim_start_matches('/').splitn(3, '/');
+    let rules = match (segments.next(), segments.next()) {
+        (Some(organization_name), Some(bucket_name)) => bucket_service::find_by_name(organization_name, bucket_name).await?
+            .map(|bucket| bucket.cors_rules())
+            .unwrap_or_default(),
+        _ => vec![],
+    };
+    let origin = request.headers().get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .map(str::to_string);
+    let preflight_method = request.headers().get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|method| method.to_str().ok())
+        .filter(|_| request.method() == Method::OPTIONS)
+        .map(str::to_string);
+
+    let (Some(origin), Some(method)) = (origin.as_deref(), preflight_method) else {
+        let method = request.method().to_string();
+        let mut response = next.call(request).await?;
+        // Caches must not hand a response to one origin, or to a same-origin request, to another origin
+        if !rules.is_empty() {
+            let headers = response.headers_mut();
+            vary_origin(headers);
+            if let Some(origin) = origin.as_deref()
+                && let Some(rule) = rules.iter().find(|rule| rule.allows_origin(origin) && rule.allows_method(&method)) {
+                insert(headers, header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+                if !rule.expose_headers.is_empty() {
+                    insert(headers, header::ACCESS_CONTROL_EXPOSE_HEADERS, &rule.expose_headers.join(", "));
+                }
+            }
+        }
+        return Ok(response.map_into_left_body());
+    };
+
+    let requested_headers: Vec<String> = request.headers().get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|headers| headers.to_str().ok())
+        .map(|headers| headers.split(',').map(|header| header.trim().to_string()).filter(|header| !header.is_empty()).collect())
+        .unwrap_or_default();
+    let rule = rules.iter().find(|rule| rule.allows_origin(origin)
+        && rule.allows_method(&method)
+        && requested_headers.iter().all(|header| rule.allows_header(header)));
+    let mut response = match rule {
+        Some(rule) => preflight_response(rule, origin, &method, &requested_headers),
+        None => HttpResponse::Forbidden().body("CORS request not allowed"),
+    };
+    if !rules.is_empty() {
+        vary_origin(response.headers_mut());
+    }
+    Ok(request.into_response(response).map_into_right_body())
+}
+
+fn preflight_response(rule: &CorsRule, origin: &str, method: &str, requested_headers: &[String]) -> HttpResponse {
+    let mut response = HttpResponse::NoContent().finish();
+    let headers = response.headers_mut();
+    insert(headers, header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    insert(headers, header::ACCESS_CONTROL_ALLOW_METHODS, &method.to_uppercase());
+    if !requested_headers.is_empty() {
+        insert(headers, header::ACCESS_CONTROL_ALLOW_HEADERS, &requested_headers.join(", "));
+    }
+    if let Some(max_age) = rule.max_age_seconds {
+        insert(headers, header::ACCESS_CONTROL_MAX_AGE, &max_age.to_string());
+    }
+    response
+}
+
+fn vary_origin(headers: &mut HeaderMap) {
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+}
+
+fn insert(headers: &mut HeaderMap, name: header::HeaderName, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
@@ -18,7 +18,20 @@ pub struct Bucket {
     pub created_by: Uuid,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
-    pub visibility: BucketVisibility
+    pub visibility: BucketVisibility,
+    pub encrypted: bool,
+    #[serde(skip_serializing)]
+    pub encryption_key: Option<Vec<u8>>,
+    pub used_bytes: i64,
+    pub object_count: i64,
+    pub quota_bytes: Option<i64>,
+    pub soft_quota_bytes: Option<i64>,
+    pub quota_objects: Option<i64>,
+    pub cors_rules: serde_json::Value,
+    pub website: Option<serde_json::Value>,
+    pub cache_control: Option<String>,
+    pub content_disposition: Option<String>,
+    pub content_type: Option<String>,
 }
 
 #[derive(DbEnum, Deserialize, Serialize, PartialEq, Debug)]
@@ -32,7 +45,21 @@ pub enum BucketVisibility {
 #[diesel(table_name = buckets)]
 pub struct BucketChangeset {
     pub name: Option<String>,
-    pub visibility: Option<BucketVisibility>
+    pub visibility: Option<BucketVisibility>,
+    pub encrypted: Option<bool>,
+    pub encryption_key: Option<Vec<u8>>,
+}
+
+// A single CORS rule; a request is allowed by the first rule matching its origin and method
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CorsRule {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    #[serde(default)]
+    pub expose_headers: Vec<String>,
+    pub max_age_seconds: Option<u32>,
 }
 
 impl Bucket {
@@ -45,6 +72,39 @@ impl Bucket {
             created_at: Utc::now().naive_utc(),
             updated_at: None,
             visibility: BucketVisibility::PRIVATE,
+            encrypted: false,
+            encryption_key: None,
+            used_bytes: 0,
+            object_count: 0,
+            quota_bytes: None,
+            soft_quota_bytes: None,
+            quota_objects: None,
+            cors_rules: serde_json::Value::Array(vec![]),
+            website: None,
+            cache_control: None,
+            content_disposition: None,
+            content_type: None,
         }
     }
+
+    pub fn cors_rules(&self) -> Vec<CorsRule> {
+        serde_json::from_value(self.cors_rules.clone()).unwrap_or_default()
+    }
+}
+
+impl CorsRule {
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| match allowed.split_once('*') {
+            Some((prefix, suffix)) => origin.len() >= prefix.len() + suffix.len() && origin.starts_with(prefix) && origin.ends_with(suffix),
+            None => allowed == origin,
+        })
+    }
+
+    pub fn allows_method(&self, method: &str) -> bool {
+        self.allowed_methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(method))
+    }
+
+    pub fn allows_header(&self, header: &str) -> bool {
+        self.allowed_headers.iter().any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(header))
+    }
 }
\ No newline at end of file
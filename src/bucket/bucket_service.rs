@@ -1,25 +1,42 @@
 use actix_web::http::StatusCode;
-use diesel::{BoolExpressionMethods, ExpressionMethods, JoinOnDsl, PgTextExpressionMethods, QueryDsl, SelectableHelper};
+use diesel::{BoolExpressionMethods, ExpressionMethods, JoinOnDsl, OptionalExtension, PgTextExpressionMethods, QueryDsl, SelectableHelper};
 use diesel_async::{AsyncConnection, RunQueryDsl};
 use tokio::fs;
 use uuid::Uuid;
 
 use crate::folder::folder_model::Folder;
-use crate::organization::organization_model::{Organization, UserOrganization};
+use crate::organization::organization_model::{Organization, OrganizationRole, UserOrganization};
 use crate::schema::{folders, organizations, user_organizations};
 use crate::{bucket::bucket_model::Bucket, config::db_config, error::ApiResponse, organization::organization_service, schema::buckets, user::user_model::User};
-use crate::bucket::bucket_model::{BucketChangeset, BucketVisibility};
+use crate::bucket::bucket_model::{BucketChangeset, BucketVisibility, CorsRule};
+use crate::encryption::encryption_service;
+use crate::file::file_model::ResponseHeaders;
+use crate::file::file_service;
 use crate::folder::folder_service::EDITABLE_ROLES;
+use crate::token::token_service;
+use crate::usage::usage_service;
+use chrono::Utc;
+use lazy_static::lazy_static;
 
-pub async fn create(name: String, organization_id: Uuid, user: &User) -> Result<Bucket, ApiResponse> {
+lazy_static! {
+    static ref CORS_ROLES: [OrganizationRole; 2] = [OrganizationRole::OWNER, OrganizationRole::ADMIN];
+}
+const CORS_METHODS: [&str; 4] = ["GET", "HEAD", "PUT", "DELETE"];
+
+pub async fn create(name: String, organization_id: Uuid, encrypted: bool, user: &User) -> Result<Bucket, ApiResponse> {
     let mut conn = db_config::get_connection().await?;
     let (organization, _) = organization_service::validate_access(organization_id, user.id, &mut conn).await
         .map_err(|_| ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()))?;
+    let mut bucket = Bucket::new(name, organization_id, user.id);
+    if encrypted {
+        bucket.encrypted = true;
+        bucket.encryption_key = Some(encryption_service::new_bucket_key()?);
+    }
 
     let bucket = conn.transaction::<Bucket, ApiResponse, _>(|mut conn| {
         Box::pin(async move {
             let bucket = diesel::insert_into(buckets::table)
-                .values(Bucket::new(name, organization_id, user.id))
+                .values(bucket)
                 .get_result::<Bucket>(&mut conn)
                 .await?;
             let _root_folder = diesel::insert_into(folders::table)
@@ -57,13 +74,14 @@ pub async fn list(organization_id: Uuid, keyword: Option<String>, limit: i64, cu
     Ok(buckets)
 }
 
-pub async fn update_bucket(bucket_id: Uuid, name: Option<String>, visibility: Option<BucketVisibility>, user: &User) -> Result<Bucket, ApiResponse> {
+pub async fn update_bucket(bucket_id: Uuid, name: Option<String>, visibility: Option<BucketVisibility>, encrypted: Option<bool>, user: &User) -> Result<Bucket, ApiResponse> {
     let mut conn = db_config::get_connection().await?;
     let (bucket, user_organization) = buckets::table.find(bucket_id)
         .left_join(user_organizations::table.on(buckets::organization_id.eq(user_organizations::organization_id).and(user_organizations::user_id.eq(user.id))))
         .select((Bucket::as_select(), Option::<UserOrganization>::as_select()))
         .first::<(Bucket, Option<UserOrganization>)>(&mut conn)
         .await?;
+    let user_organization = token_service::restrict(user_organization);
     if user_organization.is_none() {
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "User doesn't have access to this organization".to_string()));
     }
@@ -71,7 +89,12 @@ pub async fn update_bucket(bucket_id: Uuid, name: Option<String>, visibility: Op
     if !EDITABLE_ROLES.contains(&user_organization.role) {
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to update a bucket".to_string()));
     }
-    let changeset = BucketChangeset { name, visibility };
+    // Existing objects keep their keys, so the bucket key is kept even when encryption is turned off
+    let encryption_key = match (encrypted, &bucket.encryption_key) {
+        (Some(true), None) => Some(encryption_service::new_bucket_key()?),
+        _ => None,
+    };
+    let changeset = BucketChangeset { name, visibility, encrypted, encryption_key };
     let bucket = diesel::update(buckets::table)
         .set(changeset)
         .filter(buckets::id.eq(bucket.id))
@@ -91,13 +114,92 @@ pub async fn delete_bucket(bucket_id: Uuid, user: &User) -> Result<Bucket, ApiRe
         .select((Option::<UserOrganization>::as_select(), Option::<Organization>::as_select()))
         .get_result::<(Option::<UserOrganization>, Option<Organization>)>(&mut conn)
         .await?;
+    let user_organization = token_service::restrict(user_organization);
 
     if user_organization.is_none() {
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "User doesn't have access to this organization".to_string()));
     }
-    let bucket = diesel::delete(buckets::table.find(bucket_id)).get_result::<Bucket>(&mut conn).await?;
+    let bucket = conn.transaction::<Bucket, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let bucket = diesel::delete(buckets::table.find(bucket_id)).get_result::<Bucket>(conn).await?;
+            usage_service::remove_bucket(&bucket, conn).await?;
+            Ok(bucket)
+        })
+    }).await?;
     drop(conn);
     let path = "files/".to_string() + &organization.unwrap().name + "/" + &bucket.name;
     let _ = fs::remove_dir_all(path).await;
     Ok(bucket)
-}
\ No newline at end of file
+}
+pub async fn update_cors(bucket_id: Uuid, rules: Vec<CorsRule>, user: &User) -> Result<Bucket, ApiResponse> {
+    validate_cors(&rules)?;
+    let mut conn = db_config::get_connection().await?;
+    let (bucket, user_organization) = buckets::table.find(bucket_id)
+        .left_join(user_organizations::table.on(buckets::organization_id.eq(user_organizations::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select((Bucket::as_select(), Option::<UserOrganization>::as_select()))
+        .first::<(Bucket, Option<UserOrganization>)>(&mut conn)
+        .await?;
+    match token_service::restrict(user_organization) {
+        Some(user_organization) if CORS_ROLES.contains(&user_organization.role) => (),
+        _ => return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to change CORS rules".to_string()))
+    }
+    let rules = serde_json::to_value(rules)
+        .map_err(|e| ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let bucket = diesel::update(buckets::table.find(bucket.id))
+        .set((buckets::cors_rules.eq(rules), buckets::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<Bucket>(&mut conn)
+        .await?;
+    Ok(bucket)
+}
+
+pub async fn update_headers(bucket_id: Uuid, headers: ResponseHeaders, user: &User) -> Result<Bucket, ApiResponse> {
+    file_service::validate_response_headers(&headers)?;
+    let mut conn = db_config::get_connection().await?;
+    let (bucket, user_organization) = buckets::table.find(bucket_id)
+        .left_join(user_organizations::table.on(buckets::organization_id.eq(user_organizations::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select((Bucket::as_select(), Option::<UserOrganization>::as_select()))
+        .first::<(Bucket, Option<UserOrganization>)>(&mut conn)
+        .await?;
+    match token_service::restrict(user_organization) {
+        Some(user_organization) if EDITABLE_ROLES.contains(&user_organization.role) => (),
+        _ => return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to update a bucket".to_string()))
+    }
+    let ResponseHeaders { cache_control, content_disposition, content_type } = headers;
+    let bucket = diesel::update(buckets::table.find(bucket.id))
+        .set((
+            buckets::cache_control.eq(cache_control),
+            buckets::content_disposition.eq(content_disposition),
+            buckets::content_type.eq(content_type),
+            buckets::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<Bucket>(&mut conn)
+        .await?;
+    Ok(bucket)
+}
+
+fn validate_cors(rules: &[CorsRule]) -> Result<(), ApiResponse> {
+    for rule in rules {
+        if rule.allowed_origins.is_empty() || rule.allowed_methods.is_empty() {
+            return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "CORS rules need at least one origin and method".to_string()));
+        }
+        if let Some(origin) = rule.allowed_origins.iter().find(|origin| origin.matches('*').count() > 1) {
+            return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("Origin {origin} can contain at most one wildcard")));
+        }
+        if let Some(method) = rule.allowed_methods.iter().find(|method| !CORS_METHODS.contains(&method.to_uppercase().as_str())) {
+            return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("Method {method} is not supported, use one of {}", CORS_METHODS.join(", "))));
+        }
+    }
+    Ok(())
+}
+
+pub async fn find_by_name(organization_name: &str, bucket_name: &str) -> Result<Option<Bucket>, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let bucket = buckets::table
+        .inner_join(organizations::table.on(organizations::id.eq(buckets::organization_id)))
+        .filter(organizations::name.eq(organization_name))
+        .filter(buckets::name.eq(bucket_name))
+        .select(Bucket::as_select())
+        .first::<Bucket>(&mut conn)
+        .await
+        .optional()?;
+    Ok(bucket)
+}
@@ -1,4 +1,5 @@
 pub mod bucket_model;
 mod bucket_dto;
 pub mod bucket_handler;
-mod bucket_service;
\ No newline at end of file
+pub mod bucket_middleware;
+pub mod bucket_service;
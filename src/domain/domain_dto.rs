@@ -0,0 +1,13 @@
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct CreateDomainDto {
+    pub bucket_id: Uuid,
+    pub hostname: String,
+}
+
+#[derive(Deserialize)]
+pub struct DomainIdDto {
+    pub domain_id: Uuid,
+}
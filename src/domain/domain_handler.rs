@@ -0,0 +1,50 @@
+use crate::domain::domain_dto::{CreateDomainDto, DomainIdDto};
+use crate::domain::domain_model::BucketDomain;
+use crate::domain::domain_service;
+use crate::error::ApiResponse;
+use crate::user::user_model::User;
+use actix_web::web::{Json, Path, ServiceConfig};
+use actix_web::{delete, get, post, HttpMessage, HttpRequest};
+use uuid::Uuid;
+
+#[get("bucket/{bucket_id}")]
+async fn list(bucket_id: Path<Uuid>, request: HttpRequest) -> Result<Json<Vec<BucketDomain>>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let domains = domain_service::list(bucket_id.into_inner(), user).await?;
+    Ok(Json(domains))
+}
+
+#[post("")]
+async fn create(dto: Json<CreateDomainDto>, request: HttpRequest) -> Result<Json<BucketDomain>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let CreateDomainDto { bucket_id, hostname } = dto.into_inner();
+    let domain = domain_service::create(bucket_id, hostname, user).await?;
+    Ok(Json(domain))
+}
+
+#[post("{domain_id}/verify")]
+async fn verify(dto: Path<DomainIdDto>, request: HttpRequest) -> Result<Json<BucketDomain>, ApiResponse> {
+    let DomainIdDto { domain_id } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let domain = domain_service::verify(domain_id, user).await?;
+    Ok(Json(domain))
+}
+
+#[delete("{domain_id}")]
+async fn delete(dto: Path<DomainIdDto>, request: HttpRequest) -> Result<Json<BucketDomain>, ApiResponse> {
+    let DomainIdDto { domain_id } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let domain = domain_service::delete(domain_id, user).await?;
+    Ok(Json(domain))
+}
+
+pub fn domain_routes(cfg: &mut ServiceConfig) {
+    cfg.service(list);
+    cfg.service(create);
+    cfg.service(verify);
+    cfg.service(delete);
+}
@@ -0,0 +1,43 @@
+use crate::bucket::bucket_model::Bucket;
+use crate::schema::bucket_domains;
+use crate::user::user_model::User;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Associations, Identifiable, Insertable, Queryable, Selectable};
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Insertable, Identifiable, Selectable, Queryable, Associations, Serialize, Debug)]
+#[diesel(belongs_to(User, foreign_key = created_by))]
+#[diesel(belongs_to(Bucket))]
+#[diesel(table_name = bucket_domains)]
+pub struct BucketDomain {
+    pub id: Uuid,
+    pub bucket_id: Uuid,
+    pub hostname: String,
+    pub verification_token: String,
+    pub verified_at: Option<NaiveDateTime>,
+    pub created_by: Uuid,
+    pub created_at: NaiveDateTime,
+}
+
+impl BucketDomain {
+    pub fn new(bucket_id: Uuid, hostname: String, created_by: Uuid) -> Self {
+        let verification_token: String = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+
+        BucketDomain {
+            id: Uuid::now_v7(),
+            bucket_id,
+            hostname,
+            verification_token,
+            verified_at: None,
+            created_by,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
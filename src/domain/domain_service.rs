@@ -0,0 +1,204 @@
+use crate::bucket::bucket_model::Bucket;
+use crate::config::db_config;
+use crate::domain::domain_model::BucketDomain;
+use crate::error::ApiResponse;
+use crate::mail::mail_service::APP_URL;
+use crate::organization::organization_model::{Organization, OrganizationRole, UserOrganization};
+use crate::schema::{bucket_domains, buckets, organizations, user_organizations};
+use crate::token::token_service;
+use crate::user::user_model::User;
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use diesel::{BoolExpressionMethods, ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::env;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// Users prove they control a hostname by publishing its token in a TXT record of `<label>.<hostname>`
+const VERIFICATION_LABEL: &str = "_blaze-verify";
+const CACHE_TTL: Duration = Duration::from_secs(60);
+const CACHE_CAPACITY: usize = 10_000;
+
+// Organization and bucket name a hostname resolved to, and when it was looked up
+type CachedTarget = (Option<(String, String)>, Instant);
+
+lazy_static! {
+    static ref DOMAIN_ROLES: [OrganizationRole; 2] = [OrganizationRole::OWNER, OrganizationRole::ADMIN];
+    // Every request looks its Host up here, so unmapped hosts are cached as well
+    static ref DOMAIN_CACHE: RwLock<HashMap<String, CachedTarget>> = RwLock::new(HashMap::new());
+    // Hostnames the server answers on itself, comma separated, next to the host of APP_URL
+    static ref RESERVED_HOSTNAMES: Vec<String> = env::var("DOMAIN_RESERVED_HOSTNAMES").unwrap_or_default()
+        .split(',')
+        .map(|hostname| hostname.trim().trim_end_matches('.').to_lowercase())
+        .filter(|hostname| !hostname.is_empty())
+        .chain(reqwest::Url::parse(&APP_URL).ok().and_then(|url| url.host_str().map(str::to_lowercase)))
+        .collect();
+    static ref WEBSITE_DOMAIN: Option<String> = env::var("WEBSITE_DOMAIN").ok().map(|domain| domain.to_lowercase());
+}
+
+pub async fn list(bucket_id: Uuid, user: &User) -> Result<Vec<BucketDomain>, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_administered_bucket(bucket_id, user, &mut conn).await?;
+    let domains = bucket_domains::table
+        .filter(bucket_domains::bucket_id.eq(bucket_id))
+        .order(bucket_domains::hostname)
+        .select(BucketDomain::as_select())
+        .load::<BucketDomain>(&mut conn)
+        .await?;
+    Ok(domains)
+}
+
+pub async fn create(bucket_id: Uuid, hostname: String, user: &User) -> Result<BucketDomain, ApiResponse> {
+    let hostname = hostname.trim().trim_end_matches('.').to_lowercase();
+    validate_hostname(&hostname)?;
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_administered_bucket(bucket_id, user, &mut conn).await?;
+    let domain = diesel::insert_into(bucket_domains::table)
+        .values(BucketDomain::new(bucket_id, hostname, user.id))
+        .get_result::<BucketDomain>(&mut conn)
+        .await
+        .map_err(|_| ApiResponse::new(StatusCode::CONFLICT, "This hostname is already attached to a bucket".to_string()))?;
+    Ok(domain)
+}
+
+// Looks the token up in DNS, so only whoever controls the zone can attach the hostname to a bucket.
+// Serving it over HTTP would only prove the hostname points at this server
+pub async fn verify(domain_id: Uuid, user: &User) -> Result<BucketDomain, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let domain = find_domain(domain_id, &mut conn).await?;
+    let _ = find_administered_bucket(domain.bucket_id, user, &mut conn).await?;
+    validate_hostname(&domain.hostname)?;
+    let taken = bucket_domains::table
+        .filter(bucket_domains::hostname.eq(&domain.hostname))
+        .filter(bucket_domains::id.ne(domain.id))
+        .filter(bucket_domains::verified_at.is_not_null())
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await?;
+    if taken > 0 {
+        return Err(ApiResponse::new(StatusCode::CONFLICT, "This hostname is already verified for another bucket".to_string()));
+    }
+    if !publishes_token(&domain).await {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("No TXT record of {VERIFICATION_LABEL}.{} contains the verification token", domain.hostname)));
+    }
+
+    let domain = diesel::update(bucket_domains::table.find(domain_id))
+        .set(bucket_domains::verified_at.eq(Utc::now().naive_utc()))
+        .get_result::<BucketDomain>(&mut conn)
+        .await?;
+    forget(&domain.hostname);
+    Ok(domain)
+}
+
+pub async fn delete(domain_id: Uuid, user: &User) -> Result<BucketDomain, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let domain = find_domain(domain_id, &mut conn).await?;
+    let _ = find_administered_bucket(domain.bucket_id, user, &mut conn).await?;
+    let domain = diesel::delete(bucket_domains::table.find(domain_id))
+        .get_result::<BucketDomain>(&mut conn)
+        .await?;
+    forget(&domain.hostname);
+    Ok(domain)
+}
+
+// Returns the organization and bucket names a verified hostname is mapped to
+pub async fn resolve(hostname: &str) -> Result<Option<(String, String)>, ApiResponse> {
+    // Never let a stored domain shadow the API
+    if is_reserved(hostname) {
+        return Ok(None);
+    }
+    if let Some((target, cached_at)) = DOMAIN_CACHE.read().unwrap().get(hostname)
+        && cached_at.elapsed() < CACHE_TTL {
+        return Ok(target.clone());
+    }
+    let mut conn = db_config::get_connection().await?;
+    let target = bucket_domains::table
+        .inner_join(buckets::table.on(buckets::id.eq(bucket_domains::bucket_id)))
+        .inner_join(organizations::table.on(organizations::id.eq(buckets::organization_id)))
+        .filter(bucket_domains::hostname.eq(hostname))
+        .filter(bucket_domains::verified_at.is_not_null())
+        .select((organizations::name, buckets::name))
+        .first::<(String, String)>(&mut conn)
+        .await
+        .optional()?;
+    let mut cache = DOMAIN_CACHE.write().unwrap();
+    // Host headers are client controlled, keep the cache from growing without bound
+    if cache.len() >= CACHE_CAPACITY {
+        cache.retain(|_, (_, cached_at)| cached_at.elapsed() < CACHE_TTL);
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+    }
+    cache.insert(hostname.to_string(), (target.clone(), Instant::now()));
+    Ok(target)
+}
+
+async fn publishes_token(domain: &BucketDomain) -> bool {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()
+        .unwrap_or_else(|_| TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default()));
+    let name = format!("{VERIFICATION_LABEL}.{}.", domain.hostname);
+    match resolver.txt_lookup(name.as_str()).await {
+        // Long records arrive split into several strings
+        Ok(records) => records.iter().any(|record| record.txt_data().iter()
+            .map(|data| String::from_utf8_lossy(data).into_owned())
+            .collect::<String>()
+            .trim() == domain.verification_token),
+        Err(e) => {
+            debug!("Cannot look up TXT records of {name}: {e}");
+            false
+        }
+    }
+}
+
+// The API host, website bucket hosts and addresses cannot be claimed
+fn is_reserved(hostname: &str) -> bool {
+    let is_address = hostname.rsplit('.').next().is_some_and(|label| label.chars().all(|c| c.is_ascii_digit()));
+    let is_website = WEBSITE_DOMAIN.as_ref().is_some_and(|domain| hostname == domain || hostname.ends_with(&format!(".{domain}")));
+    is_address || is_website || hostname == "localhost" || hostname.ends_with(".localhost") || RESERVED_HOSTNAMES.iter().any(|reserved| reserved == hostname)
+}
+
+fn forget(hostname: &str) {
+    DOMAIN_CACHE.write().unwrap().remove(hostname);
+}
+
+fn validate_hostname(hostname: &str) -> Result<(), ApiResponse> {
+    let valid = hostname.len() <= 253
+        && hostname.contains('.')
+        && hostname.split('.').all(|label| !label.is_empty() && label.len() <= 63
+            && !label.starts_with('-') && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+    if !valid {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("{hostname} is not a valid hostname")));
+    }
+    if is_reserved(hostname) {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("{hostname} is reserved and cannot be attached to a bucket")));
+    }
+    Ok(())
+}
+
+async fn find_domain(domain_id: Uuid, conn: &mut AsyncPgConnection) -> Result<BucketDomain, ApiResponse> {
+    bucket_domains::table.find(domain_id)
+        .select(BucketDomain::as_select())
+        .first::<BucketDomain>(conn)
+        .await
+        .map_err(|_| ApiResponse::new(StatusCode::NOT_FOUND, "Domain not found".to_string()))
+}
+
+async fn find_administered_bucket(bucket_id: Uuid, user: &User, conn: &mut AsyncPgConnection) -> Result<(Bucket, Organization), ApiResponse> {
+    let (bucket, organization, user_organization) = buckets::table.find(bucket_id)
+        .inner_join(organizations::table.on(organizations::id.eq(buckets::organization_id)))
+        .left_join(user_organizations::table.on(buckets::organization_id.eq(user_organizations::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select((Bucket::as_select(), Organization::as_select(), Option::<UserOrganization>::as_select()))
+        .first::<(Bucket, Organization, Option<UserOrganization>)>(conn)
+        .await?;
+    match token_service::restrict(user_organization) {
+        Some(user_organization) if DOMAIN_ROLES.contains(&user_organization.role) => Ok((bucket, organization)),
+        _ => Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to manage domains of this bucket".to_string()))
+    }
+}
@@ -0,0 +1,4 @@
+pub mod domain_handler;
+pub mod domain_service;
+mod domain_dto;
+pub mod domain_model;
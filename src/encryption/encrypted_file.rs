@@ -0,0 +1,118 @@
+use crate::util::crypto_util::{self, StreamCipher, CHUNK_SIZE, HEADER_LEN, TAG_LEN};
+use actix_files::HttpRange;
+use actix_web::body::{BoxBody, SizedStream};
+use actix_web::http::header::{self, ContentType};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use futures_util::stream;
+use std::io::{Error, ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+// An encrypted object on disk, decrypted chunk by chunk while it is streamed out
+pub struct EncryptedFile {
+    path: PathBuf,
+    cipher: Arc<StreamCipher>,
+    ciphertext_len: u64,
+    len: u64,
+    content_type: actix_web::mime::Mime,
+}
+
+struct ReadState {
+    file: Option<fs::File>,
+    path: PathBuf,
+    cipher: Arc<StreamCipher>,
+    ciphertext_len: u64,
+    chunk: u64,
+    skip: usize,
+    remaining: u64,
+}
+
+impl EncryptedFile {
+    pub async fn open(path: impl AsRef<Path>, data_key: &[u8]) -> std::io::Result<EncryptedFile> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = fs::File::open(&path).await?;
+        let ciphertext_len = file.metadata().await?.len();
+        let mut header = vec![0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header).await?;
+        let cipher = StreamCipher::from_header(data_key, &header)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid encryption header"))?;
+        let len = crypto_util::plaintext_len(ciphertext_len)
+            .ok_or(Error::new(ErrorKind::InvalidData, "Truncated encrypted file"))?;
+        let content_type = path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(actix_files::file_extension_to_mime)
+            .unwrap_or(actix_web::mime::APPLICATION_OCTET_STREAM);
+
+        Ok(EncryptedFile { path, cipher: Arc::new(cipher), ciphertext_len, len, content_type })
+    }
+
+    fn stream(self, offset: u64, length: u64) -> impl futures_util::Stream<Item = Result<Bytes, Error>> {
+        let state = ReadState {
+            file: None,
+            path: self.path,
+            cipher: self.cipher,
+            ciphertext_len: self.ciphertext_len,
+            chunk: offset / CHUNK_SIZE,
+            skip: (offset % CHUNK_SIZE) as usize,
+            remaining: length,
+        };
+        stream::try_unfold(state, |mut state| async move {
+            if state.remaining == 0 {
+                return Ok(None);
+            }
+            let start = crypto_util::chunk_offset(state.chunk);
+            if state.file.is_none() {
+                let mut file = fs::File::open(&state.path).await?;
+                file.seek(SeekFrom::Start(start)).await?;
+                state.file = Some(file);
+            }
+            let last = state.chunk + 1 == crypto_util::chunk_count(state.ciphertext_len);
+            let size = if last { state.ciphertext_len - start } else { CHUNK_SIZE + TAG_LEN };
+            let mut sealed = vec![0u8; size as usize];
+            state.file.as_mut().unwrap().read_exact(&mut sealed).await?;
+            let plain = state.cipher.open(state.chunk, last, &sealed)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Encrypted chunk failed authentication"))?;
+
+            let end = plain.len().min(state.skip + state.remaining as usize);
+            let bytes = Bytes::copy_from_slice(&plain[state.skip..end]);
+            state.remaining -= bytes.len() as u64;
+            state.skip = 0;
+            state.chunk += 1;
+            Ok(Some((bytes, state)))
+        })
+    }
+}
+
+impl Responder for EncryptedFile {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        let mut res = HttpResponse::build(StatusCode::OK);
+        res.insert_header(ContentType(self.content_type.clone()));
+        res.insert_header((header::ACCEPT_RANGES, "bytes"));
+
+        let (mut offset, mut length) = (0, self.len);
+        if let Some(range) = req.headers().get(header::RANGE) {
+            match range.to_str().ok().map(|range| HttpRange::parse(range, self.len)) {
+                Some(Ok(ranges)) => {
+                    offset = ranges[0].start;
+                    length = ranges[0].length;
+                    res.status(StatusCode::PARTIAL_CONTENT);
+                    res.insert_header((header::CONTENT_ENCODING, "identity"));
+                    res.insert_header((header::CONTENT_RANGE, format!("bytes {}-{}/{}", offset, offset + length - 1, self.len)));
+                }
+                Some(Err(_)) => {
+                    res.insert_header((header::CONTENT_RANGE, format!("bytes */{}", self.len)));
+                    return res.status(StatusCode::RANGE_NOT_SATISFIABLE).finish();
+                }
+                None => return res.status(StatusCode::BAD_REQUEST).finish(),
+            }
+        }
+
+        res.body(SizedStream::new(length, self.stream(offset, length)))
+    }
+}
@@ -0,0 +1,201 @@
+use crate::bucket::bucket_model::Bucket;
+use crate::config::db_config;
+use crate::error::ApiResponse;
+use crate::schema::{buckets, files, folders};
+use crate::util::crypto_util;
+use actix_web::http::header::HeaderMap;
+use actix_web::http::StatusCode;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::Utc;
+use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use std::env;
+use uuid::Uuid;
+
+pub const CUSTOMER_KEY_HEADER: &str = "x-blaze-encryption-key";
+pub const CUSTOMER_KEY_HASH_HEADER: &str = "x-blaze-encryption-key-sha256";
+
+lazy_static! {
+    static ref MASTER_KEY: Option<Vec<u8>> = read_master_key("MASTER_KEY");
+    static ref PREVIOUS_MASTER_KEY: Option<Vec<u8>> = read_master_key("PREVIOUS_MASTER_KEY");
+}
+
+fn read_master_key(name: &str) -> Option<Vec<u8>> {
+    let key = hex::decode(env::var(name).ok()?).expect("Master key must be hex encoded");
+    if key.len() != crypto_util::KEY_LEN {
+        panic!("{name} must be {} bytes", crypto_util::KEY_LEN);
+    }
+    Some(key)
+}
+
+fn master_key() -> Result<&'static [u8], ApiResponse> {
+    MASTER_KEY.as_deref()
+        .ok_or(ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Encryption is not configured".to_string()))
+}
+
+fn key_error(_: aes_gcm::Error) -> ApiResponse {
+    ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot unwrap encryption key".to_string())
+}
+
+// Generates a new bucket key, wrapped by the master key
+pub fn new_bucket_key() -> Result<Vec<u8>, ApiResponse> {
+    crypto_util::wrap_key(master_key()?, &crypto_util::generate_key()).map_err(key_error)
+}
+
+fn bucket_key(bucket: &Bucket) -> Result<Vec<u8>, ApiResponse> {
+    let wrapped = bucket.encryption_key.as_ref()
+        .ok_or(ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Bucket has no encryption key".to_string()))?;
+    crypto_util::unwrap_key(master_key()?, wrapped).map_err(key_error)
+}
+
+pub struct DataKey {
+    pub key: Vec<u8>,
+    pub wrapped: Vec<u8>,
+}
+
+// Returns a fresh data key and its bucket-wrapped form when the bucket encrypts new objects
+pub fn new_data_key(bucket: &Bucket) -> Result<Option<DataKey>, ApiResponse> {
+    if !bucket.encrypted {
+        return Ok(None);
+    }
+    let key = crypto_util::generate_key();
+    let wrapped = crypto_util::wrap_key(&bucket_key(bucket)?, &key).map_err(key_error)?;
+    Ok(Some(DataKey { key, wrapped }))
+}
+
+pub fn data_key(bucket: &Bucket, wrapped_data_key: &[u8]) -> Result<Vec<u8>, ApiResponse> {
+    crypto_util::unwrap_key(&bucket_key(bucket)?, wrapped_data_key).map_err(key_error)
+}
+
+// A key supplied by the client on each request (SSE-C); only its fingerprint is ever stored
+pub struct CustomerKey {
+    pub key: Vec<u8>,
+    pub fingerprint: String,
+}
+
+pub fn customer_key_from_headers(headers: &HeaderMap) -> Result<Option<CustomerKey>, ApiResponse> {
+    let header = |name| headers.get(name).map(|value| value.to_str().unwrap_or_default());
+    let (key, key_hash) = match (header(CUSTOMER_KEY_HEADER), header(CUSTOMER_KEY_HASH_HEADER)) {
+        (None, None) => return Ok(None),
+        (Some(key), Some(key_hash)) => (key, key_hash),
+        _ => return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("Both {CUSTOMER_KEY_HEADER} and {CUSTOMER_KEY_HASH_HEADER} are required"))),
+    };
+    let key = BASE64_STANDARD.decode(key)
+        .ok()
+        .filter(|key| key.len() == crypto_util::KEY_LEN)
+        .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "Encryption key must be a base64 encoded 256-bit key".to_string()))?;
+    let digest = Sha256::digest(&key);
+    if BASE64_STANDARD.encode(digest) != key_hash {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Encryption key does not match its hash".to_string()));
+    }
+    Ok(Some(CustomerKey { key, fingerprint: hex::encode(digest) }))
+}
+
+pub fn new_customer_data_key(customer_key: &CustomerKey) -> Result<DataKey, ApiResponse> {
+    let key = crypto_util::generate_key();
+    let wrapped = crypto_util::wrap_key(&customer_key.key, &key).map_err(key_error)?;
+    Ok(DataKey { key, wrapped })
+}
+
+pub fn customer_data_key(fingerprint: &str, wrapped_data_key: &[u8], customer_key: Option<&CustomerKey>) -> Result<Vec<u8>, ApiResponse> {
+    let customer_key = customer_key
+        .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "This file is encrypted with a customer provided key".to_string()))?;
+    if customer_key.fingerprint != fingerprint {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Encryption key does not match".to_string()));
+    }
+    crypto_util::unwrap_key(&customer_key.key, wrapped_data_key).map_err(key_error)
+}
+
+// Re-wraps every bucket key that is still under PREVIOUS_MASTER_KEY with MASTER_KEY
+pub async fn rotate_master_key() -> Result<usize, ApiResponse> {
+    let master_key = master_key()?;
+    let previous_key = PREVIOUS_MASTER_KEY.as_deref()
+        .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "PREVIOUS_MASTER_KEY must be set".to_string()))?;
+    let mut conn = db_config::get_connection().await?;
+
+    conn.transaction::<usize, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let keys = buckets::table
+                .filter(buckets::encryption_key.is_not_null())
+                .select((buckets::id, buckets::encryption_key))
+                .for_update()
+                .load::<(Uuid, Option<Vec<u8>>)>(conn)
+                .await?;
+            let mut rotated = 0;
+            for (bucket_id, wrapped) in keys {
+                let wrapped = wrapped.unwrap_or_default();
+                if crypto_util::unwrap_key(master_key, &wrapped).is_ok() {
+                    continue;
+                }
+                let key = crypto_util::unwrap_key(previous_key, &wrapped).map_err(key_error)?;
+                diesel::update(buckets::table.find(bucket_id))
+                    .set((
+                        buckets::encryption_key.eq(crypto_util::wrap_key(master_key, &key).map_err(key_error)?),
+                        buckets::updated_at.eq(Utc::now().naive_utc())))
+                    .execute(conn)
+                    .await?;
+                rotated += 1;
+            }
+            Ok(rotated)
+        })
+    }).await
+}
+
+// Replaces a bucket key and re-wraps the data keys of its files; file contents are not touched
+pub async fn rotate_bucket_key(bucket_id: Uuid) -> Result<usize, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+
+    conn.transaction::<usize, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let bucket = buckets::table.find(bucket_id)
+                .select(Bucket::as_select())
+                .for_update()
+                .first::<Bucket>(conn)
+                .await?;
+            let old_key = bucket_key(&bucket)?;
+            let new_key = crypto_util::generate_key();
+
+            let keys = files::table
+                .inner_join(folders::table)
+                .filter(folders::bucket_id.eq(bucket_id))
+                .filter(files::encryption_key.is_not_null())
+                .filter(files::customer_key_fingerprint.is_null())
+                .select((files::id, files::encryption_key))
+                .load::<(Uuid, Option<Vec<u8>>)>(conn)
+                .await?;
+            let count = keys.len();
+            for (file_id, wrapped) in keys {
+                let data_key = crypto_util::unwrap_key(&old_key, &wrapped.unwrap_or_default()).map_err(key_error)?;
+                diesel::update(files::table.find(file_id))
+                    .set(files::encryption_key.eq(crypto_util::wrap_key(&new_key, &data_key).map_err(key_error)?))
+                    .execute(conn)
+                    .await?;
+            }
+            diesel::update(buckets::table.find(bucket_id))
+                .set((
+                    buckets::encryption_key.eq(crypto_util::wrap_key(master_key()?, &new_key).map_err(key_error)?),
+                    buckets::updated_at.eq(Utc::now().naive_utc())))
+                .execute(conn)
+                .await?;
+            Ok(count)
+        })
+    }).await
+}
+
+pub async fn run_command(command: &str, args: &[String]) -> std::io::Result<()> {
+    let result = match (command, args) {
+        ("rotate-master-key", []) => rotate_master_key().await
+            .map(|count| info!("Re-wrapped {count} bucket keys")),
+        ("rotate-bucket-key", [bucket_id]) => {
+            let bucket_id = Uuid::parse_str(bucket_id)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            rotate_bucket_key(bucket_id).await
+                .map(|count| info!("Rotated key of bucket {bucket_id}, re-wrapped {count} file keys"))
+        }
+        _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+            "Usage: blaze [rotate-master-key | rotate-bucket-key <bucket_id> | backfill-usage]")),
+    };
+    result.map_err(|e| std::io::Error::other(e.to_string()))
+}
@@ -0,0 +1,2 @@
+pub mod encryption_service;
+pub mod encrypted_file;
@@ -17,6 +17,14 @@ impl ApiResponse {
     pub fn new(status: StatusCode, message: String) -> ApiResponse{
         ApiResponse{ status, message }
     }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl From<RunError>  for ApiResponse {
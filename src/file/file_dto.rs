@@ -1,10 +1,15 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use crate::file::file_model::{File, ResponseHeaders};
+use crate::folder::folder_model::ConflictPolicy;
+use crate::metadata::metadata_model::Metadata;
 
 #[derive(Deserialize)]
 pub struct SearchFileDto {
     pub folder_id: Uuid,
     pub keyword: Option<String>,
+    // JSON object of metadata pairs every returned file must have
+    pub metadata: Option<String>,
     pub limit: i64,
     pub cursor: Option<Uuid>
 }
@@ -12,6 +17,7 @@ pub struct SearchFileDto {
 #[derive(Deserialize)]
 pub struct FileNameDTO {
     pub file_name: String,
+    pub conflict: Option<ConflictPolicy>,
 }
 
 #[derive(Deserialize)]
@@ -31,4 +37,43 @@ pub struct FileQueryDto {
     pub expiry: Option<u64>,
     pub secret_id: String,
     pub signature: String,
-}
\ No newline at end of file
+    #[serde(rename = "response-content-disposition")]
+    pub response_content_disposition: Option<String>,
+    #[serde(rename = "response-content-type")]
+    pub response_content_type: Option<String>,
+    pub conflict: Option<ConflictPolicy>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateFileHeadersDto {
+    pub file_id: Uuid,
+    #[serde(flatten)]
+    pub headers: ResponseHeaders,
+}
+#[derive(Deserialize)]
+pub struct MultipartUploadDto {
+    pub conflict: Option<ConflictPolicy>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadStatus {
+    CREATED,
+    SKIPPED,
+    FAILED,
+}
+
+#[derive(Serialize)]
+pub struct UploadResultDto {
+    pub path: String,
+    pub status: UploadStatus,
+    pub file: Option<File>,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct FileEntryDto {
+    #[serde(flatten)]
+    pub file: File,
+    pub metadata: Metadata,
+}
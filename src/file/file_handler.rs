@@ -1,27 +1,44 @@
+use crate::encryption::encryption_service;
 use crate::error::ApiResponse;
-use crate::file::file_dto::{FileDto, FileIdDto, FileNameDTO, FileQueryDto, SearchFileDto};
-use crate::file::file_model::File;
-use crate::file::file_service;
+use crate::file::file_dto::{FileDto, FileIdDto, FileNameDTO, FileQueryDto, SearchFileDto, UpdateFileHeadersDto, MultipartUploadDto, UploadResultDto, FileEntryDto};
+use crate::file::file_model::{File, Preconditions};
+use crate::file::file_service::{self, PutOptions};
+use crate::folder::folder_model::ConflictPolicy;
+use crate::metadata::metadata_service;
 use crate::user::user_model::User;
+use actix_multipart::Multipart;
 use actix_web::web::{Bytes, Json, Path, PayloadConfig, Query, ServiceConfig};
-use actix_web::{delete, get, put, web, HttpMessage, HttpRequest, Responder};
+use actix_web::{delete, get, post, put, web, HttpMessage, HttpRequest, Responder};
 use uuid::Uuid;
 
 #[put("{folder_id}")]
 async fn upload(body: web::Bytes, folder_id: Path<Uuid>, file_name: Query<FileNameDTO>, request: HttpRequest) -> Result<(), ApiResponse> {
     let extensions = request.extensions();
     let user = extensions.get::<User>().unwrap();
-    file_service::upload(body, folder_id.into_inner(), file_name.into_inner().file_name, user).await?;
+    let preconditions = Preconditions::from_request(&request)?;
+    let metadata = metadata_service::from_headers(request.headers())?;
+    let FileNameDTO { file_name, conflict } = file_name.into_inner();
+    file_service::upload(body, folder_id.into_inner(), file_name, conflict, preconditions, metadata, user).await?;
 
     Ok(())
 }
 
+#[post("{folder_id}")]
+async fn upload_multipart(payload: Multipart, folder_id: Path<Uuid>, query: Query<MultipartUploadDto>, request: HttpRequest) -> Result<Json<Vec<UploadResultDto>>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let metadata = metadata_service::from_headers(request.headers())?;
+    let MultipartUploadDto { conflict } = query.into_inner();
+    let results = file_service::upload_multipart(payload, folder_id.into_inner(), conflict, metadata, user).await?;
+    Ok(Json(results))
+}
+
 #[get("")]
-async fn search_file(query: Query<SearchFileDto>, request: HttpRequest) -> Result<Json<Vec<File>>, ApiResponse> {
+async fn search_file(query: Query<SearchFileDto>, request: HttpRequest) -> Result<Json<Vec<FileEntryDto>>, ApiResponse> {
     let extensions = request.extensions();
     let user = extensions.get::<User>().unwrap();
-    let SearchFileDto { folder_id, keyword, limit, cursor } = query.into_inner();
-    let files = file_service::search_file(folder_id, keyword, limit, cursor, user).await?;
+    let SearchFileDto { folder_id, keyword, metadata, limit, cursor } = query.into_inner();
+    let files = file_service::search_file(folder_id, keyword, metadata, limit, cursor, user).await?;
     
     Ok(Json(files))
 }
@@ -35,6 +52,15 @@ async fn get_file(dto: Path<FileIdDto>, request: HttpRequest)-> Result<impl Resp
     Ok(file)
 }
 
+#[put("headers")]
+async fn update_headers(dto: Json<UpdateFileHeadersDto>, request: HttpRequest) -> Result<Json<File>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let UpdateFileHeadersDto { file_id, headers } = dto.into_inner();
+    let file = file_service::update_headers(file_id, headers, user).await?;
+    Ok(Json(file))
+}
+
 #[delete("{file_id}")]
 async fn delete_file(dto: Path<FileIdDto>, request: HttpRequest)-> Result<(), ApiResponse> {
     let extension = request.extensions();
@@ -45,15 +71,24 @@ async fn delete_file(dto: Path<FileIdDto>, request: HttpRequest)-> Result<(), Ap
 }
 
 #[get("{organization_name}/{bucket_name}/{file_path:.*}")]
-pub async fn serve_file(dto: Path<FileDto>, query: Query<FileQueryDto>) -> Result<actix_files::NamedFile, ApiResponse> {
+pub async fn serve_file(dto: Path<FileDto>, query: Query<FileQueryDto>, request: HttpRequest) -> Result<impl Responder, ApiResponse> {
     let FileDto { organization_name, bucket_name, file_path } = dto.into_inner();
-    file_service::serve_file(organization_name, bucket_name, file_path, query.into_inner()).await
+    let customer_key = encryption_service::customer_key_from_headers(request.headers())?;
+    file_service::serve_file(organization_name, bucket_name, file_path, query.into_inner(), customer_key).await
 }
 
 #[put("{organization_name}/{bucket_name}/{file_path:.*}")]
-pub async fn save_file(bytes: Bytes, dto: Path<FileDto>, query: Query<FileQueryDto>) -> Result<(), ApiResponse> {
+pub async fn save_file(bytes: Bytes, dto: Path<FileDto>, query: Query<FileQueryDto>, request: HttpRequest) -> Result<(), ApiResponse> {
     let FileDto { organization_name, bucket_name, file_path } = dto.into_inner();
-    file_service::save_file(bytes, organization_name, bucket_name, file_path, query.into_inner()).await
+    let query = query.into_inner();
+    let options = PutOptions {
+        customer_key: encryption_service::customer_key_from_headers(request.headers())?,
+        preconditions: Preconditions::from_request(&request)?,
+        conflict: query.conflict.unwrap_or(ConflictPolicy::OVERWRITE),
+        metadata: metadata_service::from_headers(request.headers())?,
+        replicate: true,
+    };
+    file_service::save_file(bytes, organization_name, bucket_name, file_path, query, options).await
 }
 
 #[delete("{organization_name}/{bucket_name}/{file_path:.*}")]
@@ -63,7 +98,9 @@ pub async fn remove_file(dto: Path<FileDto>, query: Query<FileQueryDto>) -> Resu
 }
 
 pub fn file_routes(cfg: &mut ServiceConfig) {
+    cfg.service(update_headers);
     cfg.app_data(PayloadConfig::new(1 * 1024 * 1024 * 1024)).service(upload);
+    cfg.service(upload_multipart);
     cfg.service(search_file);
     cfg.service(delete_file);
     cfg.service(get_file);
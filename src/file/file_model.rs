@@ -1,10 +1,16 @@
+use actix_web::http::header::{self, EntityTag, Header, IfMatch, IfNoneMatch};
+use actix_web::http::StatusCode;
+use actix_web::HttpRequest;
 use chrono::{NaiveDateTime, Utc};
 use diesel::{Associations, Insertable, Queryable, Selectable};
-use serde::Serialize;
+use diesel_derive_enum::DbEnum;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use crate::error::ApiResponse;
 use crate::schema::files;
 use crate::user::user_model::User;
 use crate::folder::folder_model::Folder;
+use crate::replication::replication_model::ReplicationStatus;
 #[derive(Selectable, Queryable, Insertable, Associations, Serialize, Debug)]
 #[diesel(table_name = files)]
 #[diesel(belongs_to(User, foreign_key = created_by))]
@@ -16,17 +22,91 @@ pub struct File {
     pub created_by: Uuid,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub encryption_key: Option<Vec<u8>>,
+    pub customer_key_fingerprint: Option<String>,
+    pub size: i64,
+    pub storage_tier: StorageTier,
+    pub replication_status: Option<ReplicationStatus>,
+    pub cache_control: Option<String>,
+    pub content_disposition: Option<String>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+}
+
+// Headers a file is served with; unset values fall back to the file, then to its bucket
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct ResponseHeaders {
+    pub cache_control: Option<String>,
+    pub content_disposition: Option<String>,
+    pub content_type: Option<String>,
+}
+
+#[derive(DbEnum, Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[db_enum(existing_type_path = "crate::schema::sql_types::StorageTier")]
+pub enum StorageTier {
+    STANDARD,
+    COLD,
 }
 
 impl File {
-    pub fn new(name: String, folder_id: Uuid, created_by: Uuid) -> Self {
+    pub fn new(name: String, folder_id: Uuid, created_by: Uuid, size: i64, encryption_key: Option<Vec<u8>>) -> Self {
         File {
             id: Uuid::now_v7(),
             name,
             folder_id,
             created_by,
             created_at: Utc::now().naive_utc(),
-            updated_at: None
+            updated_at: None,
+            encryption_key,
+            customer_key_fingerprint: None,
+            size,
+            storage_tier: StorageTier::STANDARD,
+            replication_status: None,
+            cache_control: None,
+            content_disposition: None,
+            content_type: None,
+            etag: None,
+        }
+    }
+}
+
+// Conditional headers an upload is checked against before it may create or replace a file
+#[derive(Default)]
+pub struct Preconditions {
+    pub if_match: Option<IfMatch>,
+    pub if_none_match: Option<IfNoneMatch>,
+}
+
+impl Preconditions {
+    pub fn from_request(request: &HttpRequest) -> Result<Self, ApiResponse> {
+        let invalid = |name: &str| ApiResponse::new(StatusCode::BAD_REQUEST, format!("Invalid {name} header"));
+        let if_match = match request.headers().contains_key(header::IF_MATCH) {
+            true => Some(IfMatch::parse(request).map_err(|_| invalid("If-Match"))?),
+            false => None,
+        };
+        let if_none_match = match request.headers().contains_key(header::IF_NONE_MATCH) {
+            true => Some(IfNoneMatch::parse(request).map_err(|_| invalid("If-None-Match"))?),
+            false => None,
+        };
+        Ok(Preconditions { if_match, if_none_match })
+    }
+
+    // `existing` is the file currently stored under the name being written, if any
+    pub fn check(&self, existing: Option<&File>) -> Result<(), ApiResponse> {
+        let etag = existing.and_then(|file| file.etag.clone()).map(EntityTag::new_strong);
+        let matches = |items: &[EntityTag], strong: bool| etag.as_ref()
+            .is_some_and(|etag| items.iter().any(|item| if strong { item.strong_eq(etag) } else { item.weak_eq(etag) }));
+        let failed = match (&self.if_match, &self.if_none_match) {
+            (Some(IfMatch::Any), _) if existing.is_none() => true,
+            (Some(IfMatch::Items(items)), _) if !matches(items, true) => true,
+            (_, Some(IfNoneMatch::Any)) => existing.is_some(),
+            (_, Some(IfNoneMatch::Items(items))) => matches(items, false),
+            _ => false,
+        };
+        match failed {
+            true => Err(ApiResponse::new(StatusCode::PRECONDITION_FAILED, "The file does not match the request preconditions".to_string())),
+            false => Ok(()),
         }
     }
 }
\ No newline at end of file
@@ -1,37 +1,79 @@
 use crate::bucket::bucket_model::{Bucket, BucketVisibility};
 use crate::config::db_config;
+use crate::encryption::encrypted_file::EncryptedFile;
+use crate::encryption::encryption_service;
+use crate::encryption::encryption_service::CustomerKey;
 use crate::error::ApiResponse;
-use crate::file::file_dto::FileQueryDto;
-use crate::file::file_model::File;
+use crate::file::file_dto::{FileEntryDto, FileQueryDto, UploadResultDto, UploadStatus};
+use crate::file::object_writer::ObjectWriter;
+use crate::file::file_model::{File, Preconditions, ResponseHeaders, StorageTier};
+use crate::file::served_file::ServedFile;
+use crate::folder::folder_model::ConflictPolicy;
 use crate::folder::folder_model::Folder;
 use crate::folder::folder_service::folder_path;
 use crate::organization::organization_model::{Organization, OrganizationRole, OrganizationSecret, UserOrganization};
 use crate::organization::organization_service;
-use crate::schema::files;
+use crate::schema::{file_metadata, files};
 use crate::schema::organization_secrets;
 use crate::schema::{buckets, user_organizations};
 use crate::schema::{folders, organizations};
+use crate::replication::replication_service;
+use crate::search::search_service;
+use crate::metadata::metadata_model::Metadata;
+use crate::metadata::metadata_service;
+use crate::usage::usage_service;
+use crate::token::token_service;
 use crate::user::user_model::User;
 use crate::folder::folder_service;
+use crate::util::crypto_util::{StreamCipher, CHUNK_SIZE, HEADER_LEN, TAG_LEN};
 use actix_web::http::StatusCode;
 use actix_web::web::Bytes;
-use actix_web::Responder;
-use diesel::{ExpressionMethods, PgTextExpressionMethods, SelectableHelper};
+use actix_web::http::header::{self, ContentDisposition, DispositionType, HeaderName, HeaderValue};
+use actix_web::mime::Mime;
+use actix_web::Either;
+use actix_files::NamedFile;
+use actix_multipart::{Field, Multipart};
+use chrono::Utc;
+use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, TextExpressionMethods, PgTextExpressionMethods, SelectableHelper};
+use diesel::dsl::exists;
 use diesel::{JoinOnDsl, QueryDsl};
-use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use futures_util::StreamExt;
 use hmac::{Mac};
 use lazy_static::lazy_static;
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
+use std::env;
 use std::path::Path;
 use std::time::{SystemTime};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
 
+// How put_object writes an object; `replicate` queues the change for the bucket's replications
+pub struct PutOptions {
+    pub customer_key: Option<CustomerKey>,
+    pub preconditions: Preconditions,
+    pub conflict: ConflictPolicy,
+    pub metadata: Metadata,
+    pub replicate: bool,
+}
+
+impl Default for PutOptions {
+    fn default() -> Self {
+        PutOptions { customer_key: None, preconditions: Preconditions::default(), conflict: ConflictPolicy::OVERWRITE, metadata: Metadata::new(), replicate: false }
+    }
+}
+
+// Scratch space uploads are streamed into before they are moved to their object path, one directory per bucket
+pub const UPLOAD_DIR: &str = "files/.uploads";
+
 lazy_static! {
     static ref EDITABLE_ROLES: [OrganizationRole; 3] = [OrganizationRole::OWNER, OrganizationRole::ADMIN, OrganizationRole::EDITOR];
+    // Multipart bodies are streamed past PayloadConfig, these cap them instead
+    static ref MULTIPART_MAX_PART_BYTES: u64 = env::var("MULTIPART_MAX_PART_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(1024 * 1024 * 1024);
+    static ref MULTIPART_MAX_REQUEST_BYTES: u64 = env::var("MULTIPART_MAX_REQUEST_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(1024 * 1024 * 1024);
 }
-pub async fn upload(body: Bytes, folder_id: Uuid, file_name:String, user: &User) -> Result<(), ApiResponse> {
+pub async fn upload(body: Bytes, folder_id: Uuid, file_name:String, conflict: Option<ConflictPolicy>, preconditions: Preconditions, metadata: Metadata, user: &User) -> Result<(), ApiResponse> {
     let mut conn = db_config::get_connection().await?;
     let folder = folders::dsl::folders.find(folder_id)
         .first::<Folder>(&mut conn)
@@ -46,24 +88,150 @@ pub async fn upload(body: Bytes, folder_id: Uuid, file_name:String, user: &User)
         Some(user_organization) if EDITABLE_ROLES.contains(&user_organization.role) => (),
         _ => return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()))
     }
-    let file = diesel::insert_into(files::table)
-        .values(File::new(file_name, folder_id, user.id))
-        .get_result::<File>(&mut conn)
+    let data_key = encryption_service::new_data_key(&buc)?;
+    let new_file = File {
+        etag: Some(content_hash(&body)),
+        ..File::new(file_name, folder_id, user.id, body.len() as i64, data_key.as_ref().map(|data_key| data_key.wrapped.clone()))
+    };
+    let folder_path = folder_path(folder.id, &mut conn).await?;
+    let object_path = folder_path.trim_start_matches('/').to_string() + &new_file.name;
+    let bucket_id = buc.id;
+    // Uploads never replace an existing file unless asked to, an If-Match header counts as asking
+    let conflict = conflict.unwrap_or(if preconditions.if_match.is_some() { ConflictPolicy::OVERWRITE } else { ConflictPolicy::FAIL });
+    let scratch_path = write_scratch(bucket_id, &body, data_key.as_ref().map(|data_key| data_key.key.as_slice())).await?;
+    let target = StoreTarget { bucket_id, object_root: format!("files/{}/{}", organization.name, buc.name), scratch_path: scratch_path.clone() };
+    let stored = conn.transaction::<File, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let file = store_file(new_file, &target, &object_path, &preconditions, conflict, true, conn).await?;
+            metadata_service::replace_file_metadata(file.id, &metadata, conn).await?;
+            Ok(file)
+        })
+    }).await;
+    if stored.is_err() {
+        let _ = fs::remove_file(&scratch_path).await;
+    }
+    stored.map(|_| ())
+}
+
+// Stores every file part of a multipart upload below `folder_id`, at the relative path its filename carries
+// Request level metadata applies to every part, `x-blaze-meta-*` headers on a part add to it
+pub async fn upload_multipart(mut payload: Multipart, folder_id: Uuid, conflict: Option<ConflictPolicy>, metadata: Metadata, user: &User) -> Result<Vec<UploadResultDto>, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let folder = folders::table.find(folder_id)
+        .first::<Folder>(&mut conn)
         .await?;
-    let mut path = "files/".to_owned() + &organization.name + "/" + &buc.name;
-    path.push_str(&folder_path(folder.id, &mut conn).await?);
-    path.push_str(file.name.as_str());
+    let bucket = buckets::table.find(folder.bucket_id)
+        .first::<Bucket>(&mut conn)
+        .await?;
+    let (organization, user_organization) = organization_service::validate_access(bucket.organization_id, user.id, &mut conn).await
+        .map_err(|_| ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()))?;
+    match user_organization {
+        Some(user_organization) if EDITABLE_ROLES.contains(&user_organization.role) => (),
+        _ => return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()))
+    }
+    let base = folder_path(folder.id, &mut conn).await?.trim_matches('/').to_string();
     drop(conn);
 
-    // let path = Path::new(&path);
-    let _ = fs::File::create(path).await
-        .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot create file".to_string()))?
-        .write_all(&body).await
-        .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot write to file".to_string()))?;
-    Ok(())
+    let target = MultipartTarget { organization, bucket, base, conflict: conflict.unwrap_or(ConflictPolicy::FAIL), user };
+    let mut results = Vec::new();
+    let mut received = 0;
+    // A broken multipart body ends the upload, the parts stored before it are still reported
+    while let Some(Ok(mut field)) = payload.next().await {
+        let Some(relative_path) = field.content_disposition().and_then(|disposition| disposition.get_filename()).map(str::to_string) else {
+            continue;
+        };
+        let part_metadata = metadata_service::from_headers(field.headers()).map(|part_metadata| {
+            let mut merged = metadata.clone();
+            merged.extend(part_metadata);
+            merged
+        });
+        let stored = match part_metadata {
+            Ok(part_metadata) => store_part(&mut field, &relative_path, &part_metadata, &target, &mut received).await,
+            Err(e) => Err(e),
+        };
+        let result = match stored {
+            // The rest of an oversized body is not read
+            Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => return Err(e),
+            Ok(file) => UploadResultDto { path: relative_path, status: UploadStatus::CREATED, file: Some(file), reason: None },
+            Err(e) => {
+                let status = if e.status() == StatusCode::CONFLICT { UploadStatus::SKIPPED } else { UploadStatus::FAILED };
+                UploadResultDto { path: relative_path, status, file: None, reason: Some(e.message().to_string()) }
+            }
+        };
+        results.push(result);
+    }
+    Ok(results)
+}
+
+// Where the parts of one multipart upload are stored, `base` is the target folder's path in the bucket
+struct MultipartTarget<'a> {
+    organization: Organization,
+    bucket: Bucket,
+    base: String,
+    conflict: ConflictPolicy,
+    user: &'a User,
 }
 
-pub async fn search_file(folder_id: Uuid, keyword: Option<String>, limit: i64, cursor: Option<Uuid>, user: &User) -> Result<Vec<File>, ApiResponse> {
+// `received` counts the bytes of every part read so far, including the ones that failed
+async fn store_part(field: &mut Field, relative_path: &str, metadata: &Metadata, target: &MultipartTarget<'_>, received: &mut u64) -> Result<File, ApiResponse> {
+    let MultipartTarget { organization, bucket, base, conflict, user } = target;
+    if relative_path.split('/').any(|segment| segment.is_empty() || segment == "." || segment == ".." || segment.contains('\\')) {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Invalid relative path".to_string()));
+    }
+    let object_path = if base.is_empty() { relative_path.to_string() } else { format!("{base}/{relative_path}") };
+    let (parent, name) = object_path.rsplit_once('/').unwrap_or(("", &object_path));
+
+    // Parts are streamed to a scratch file first, their size and hash are only known once they are complete
+    let scratch_path = scratch_path(bucket.id).await;
+    let data_key = encryption_service::new_data_key(bucket)?;
+    let mut conn = db_config::get_connection().await?;
+    let remaining = usage_service::remaining_bytes(bucket.id, &mut conn).await?;
+    drop(conn);
+    let stored = async {
+        let mut writer = ObjectWriter::create(&scratch_path, data_key.as_ref().map(|data_key| data_key.key.as_slice())).await?;
+        let mut part_size = 0;
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+            part_size += chunk.len() as u64;
+            *received += chunk.len() as u64;
+            if part_size > *MULTIPART_MAX_PART_BYTES {
+                return Err(ApiResponse::new(StatusCode::PAYLOAD_TOO_LARGE, format!("A part may be at most {} bytes", *MULTIPART_MAX_PART_BYTES)));
+            }
+            if *received > *MULTIPART_MAX_REQUEST_BYTES {
+                return Err(ApiResponse::new(StatusCode::PAYLOAD_TOO_LARGE, format!("An upload may be at most {} bytes", *MULTIPART_MAX_REQUEST_BYTES)));
+            }
+            // store_file checks the quota again under lock, this only stops the write early
+            if remaining.is_some_and(|remaining| part_size > remaining as u64) {
+                return Err(ApiResponse::new(StatusCode::INSUFFICIENT_STORAGE, "Storage quota exceeded".to_string()));
+            }
+            writer.write(&chunk).await?;
+        }
+        let (size, etag) = writer.finish().await?;
+
+        let mut conn = db_config::get_connection().await?;
+        let folder_id = folder_service::create_folder_from_path(parent, bucket.id, user.id, &mut conn).await?;
+        let new_file = File {
+            etag: Some(etag),
+            ..File::new(name.to_string(), folder_id, user.id, size as i64, data_key.as_ref().map(|data_key| data_key.wrapped.clone()))
+        };
+        let target = StoreTarget { bucket_id: bucket.id, object_root: format!("files/{}/{}", organization.name, bucket.name), scratch_path: scratch_path.clone() };
+        let stored_path = object_path.clone();
+        conn.transaction::<File, ApiResponse, _>(|conn| {
+            Box::pin(async move {
+                let file = store_file(new_file, &target, &stored_path, &Preconditions::default(), *conflict, true, conn).await?;
+                metadata_service::replace_file_metadata(file.id, metadata, conn).await?;
+                Ok(file)
+            })
+        }).await
+    }.await;
+    if stored.is_err() {
+        let _ = fs::remove_file(&scratch_path).await;
+    }
+    stored
+}
+
+pub async fn search_file(folder_id: Uuid, keyword: Option<String>, metadata: Option<String>, limit: i64, cursor: Option<Uuid>, user: &User) -> Result<Vec<FileEntryDto>, ApiResponse> {
+    let filter = metadata_service::parse_filter(metadata.as_deref())?;
     let mut conn = db_config::get_connection().await?;
     let (_folder, user_organization) = folders::dsl::folders.find(folder_id)
         .left_join(buckets::table)
@@ -72,6 +240,7 @@ pub async fn search_file(folder_id: Uuid, keyword: Option<String>, limit: i64, c
         .select((Folder::as_select(), Option::<UserOrganization>::as_select()))
         .get_result::<(Folder, Option<UserOrganization>)>(&mut conn)
         .await?;
+    let user_organization = token_service::restrict(user_organization);
 
     if user_organization.is_none() {
         return Err(ApiResponse::new(StatusCode::UNAUTHORIZED, "User doesnt have access to this organization".to_string()));
@@ -87,13 +256,24 @@ pub async fn search_file(folder_id: Uuid, keyword: Option<String>, limit: i64, c
     if let Some(cursor) = cursor {
         query = query.filter(files::id.gt(cursor));
     }
+    for (key, value) in filter {
+        query = query.filter(exists(file_metadata::table
+            .filter(file_metadata::file_id.eq(files::id))
+            .filter(file_metadata::key.eq(key))
+            .filter(file_metadata::value.eq(value))));
+    }
     let files = query.limit(limit)
         .load::<File>(&mut conn)
         .await?;
+    let file_ids: Vec<Uuid> = files.iter().map(|file| file.id).collect();
+    let mut metadata = metadata_service::file_metadata(&file_ids, &mut conn).await?;
+    let files = files.into_iter()
+        .map(|file| FileEntryDto { metadata: metadata.remove(&file.id).unwrap_or_default(), file })
+        .collect();
     Ok(files)
 }
 
-pub async fn get_file(file_id: Uuid, user_id: Uuid) -> Result<impl Responder, ApiResponse> {
+pub async fn get_file(file_id: Uuid, user_id: Uuid) -> Result<ServedFile, ApiResponse> {
     let mut conn = db_config::get_connection().await?;
     let (file, _folder, bucket, organization, user_org) = files::table.find(file_id)
         .left_join(folders::table.on(folders::id.eq(files::folder_id)))
@@ -104,6 +284,7 @@ pub async fn get_file(file_id: Uuid, user_id: Uuid) -> Result<impl Responder, Ap
         .select((File::as_select(), Option::<Folder>::as_select(), Option::<Bucket>::as_select(), Option::<Organization>::as_select(), Option::<UserOrganization>::as_select()))
         .get_result::<(File, Option<Folder>, Option<Bucket>, Option<Organization>, Option<UserOrganization>)>(&mut conn)
         .await?;
+    let user_org = token_service::restrict(user_org);
     if user_org.is_none() || organization.is_none() || bucket.is_none() {
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "No access to this file".to_string()))
     }
@@ -113,10 +294,8 @@ pub async fn get_file(file_id: Uuid, user_id: Uuid) -> Result<impl Responder, Ap
     let mut path = "files/".to_owned() + &organization.name + "/" + &bucket.name;
     path.push_str(&folder_path(file.folder_id, &mut conn).await?);
     path.push_str(file.name.as_str());
-    let path = Path::new(&path);
 
-    actix_files::NamedFile::open_async(&path).await
-        .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot open file".to_string()))
+    open_file(&path, &bucket, Some(&file), None, ResponseHeaders::default()).await
 }
 
 pub async fn delete_file(file_id: Uuid, user_id: Uuid) -> Result<(), ApiResponse> {
@@ -130,15 +309,25 @@ pub async fn delete_file(file_id: Uuid, user_id: Uuid) -> Result<(), ApiResponse
         .select((File::as_select(), Option::<Bucket>::as_select(), Option::<Organization>::as_select(), Option::<UserOrganization>::as_select()))
         .get_result::<(File, Option<Bucket>, Option<Organization>, Option<UserOrganization>)>(&mut conn)
         .await?;
+    let user_org = token_service::restrict(user_org);
     if user_org.is_none() || organization.is_none() || bucket.is_none() {
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "No access to this file".to_string()))
     }
-    let _ = diesel::delete(files::table.find(file_id)).execute(&mut conn).await?;
     let bucket = bucket.unwrap();
     let organization = organization.unwrap();
+    let folder_path = folder_path(file.folder_id, &mut conn).await?;
+    let object_path = folder_path.trim_start_matches('/').to_string() + &file.name;
+    let bucket_id = bucket.id;
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let file = diesel::delete(files::table.find(file_id)).get_result::<File>(conn).await?;
+            usage_service::record(bucket_id, -file.size, -1, conn).await?;
+            replication_service::enqueue_delete(bucket_id, &object_path, conn).await
+        })
+    }).await?;
 
     let mut path = "files/".to_owned() + &organization.name + "/" + &bucket.name;
-    path.push_str(&folder_path(file.folder_id, &mut conn).await?);
+    path.push_str(&folder_path);
     drop(conn);
     path.push_str(file.name.as_str());
     let path = Path::new(&path);
@@ -146,48 +335,238 @@ pub async fn delete_file(file_id: Uuid, user_id: Uuid) -> Result<(), ApiResponse
         .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot remove file".to_string()))
 }
 
-pub async fn serve_file(organization_name: String, bucket_name: String, file_path: String, query: FileQueryDto) -> Result<actix_files::NamedFile, ApiResponse> {
+pub async fn serve_file(organization_name: String, bucket_name: String, file_path: String, query: FileQueryDto, customer_key: Option<CustomerKey>) -> Result<ServedFile, ApiResponse> {
     let mut conn = db_config::get_connection().await?;
     let (organization, bucket) = find_organization_and_bucket(&organization_name, &bucket_name, &mut conn).await?;
     let path = organization_name.to_string() + "/" + &bucket_name + "/" + &file_path;
-    if bucket.visibility == BucketVisibility::PRIVATE {
+    // Response overrides are only honoured when the link that carries them is signed, even on public buckets
+    let overrides = ResponseHeaders {
+        cache_control: None,
+        content_disposition: query.response_content_disposition.clone(),
+        content_type: query.response_content_type.clone(),
+    };
+    if bucket.visibility == BucketVisibility::PRIVATE || overrides.content_disposition.is_some() || overrides.content_type.is_some() {
         let _ = verify_signature(&path, query, organization, false, &mut conn).await?;
     }
-    actix_files::NamedFile::open_async("files/".to_string() + &path).await
-        .map_err(|_| ApiResponse::new(StatusCode::NOT_FOUND, "File not found".to_string()))
+    validate_response_headers(&overrides)?;
+    let file = find_file_by_path(&file_path, &bucket, &mut conn).await?;
+    drop(conn);
+    open_file(&("files/".to_string() + &path), &bucket, file.as_ref(), customer_key.as_ref(), overrides).await
 }
 
-pub async fn save_file(body: Bytes, organization_name: String, bucket_name: String, file_path: String, query: FileQueryDto) -> Result<(), ApiResponse> {
+pub async fn save_file(body: Bytes, organization_name: String, bucket_name: String, file_path: String, query: FileQueryDto, options: PutOptions) -> Result<(), ApiResponse> {
     let mut conn = db_config::get_connection().await?;
     let (organization, bucket) = find_organization_and_bucket(&organization_name, &bucket_name, &mut conn).await?;
 
     let path = organization_name.to_string() + "/" + &bucket_name + "/" + &file_path;
     let org_sec = verify_signature(&path, query, organization, true, &mut conn).await?;
+    drop(conn);
 
-    let path = Path::new(&file_path);
+    put_object(&organization_name, &bucket, &file_path, body, org_sec.created_by, options).await
+}
+
+// Creates or overwrites the object at `file_path`
+pub async fn put_object(organization_name: &str, bucket: &Bucket, file_path: &str, body: Bytes, created_by: Uuid, options: PutOptions) -> Result<(), ApiResponse> {
+    let PutOptions { customer_key, preconditions, conflict, metadata, replicate } = options;
+    let mut conn = db_config::get_connection().await?;
+    let path = Path::new(file_path);
 
     let parent = path.parent().map(|p| p.to_str().unwrap()).unwrap_or("");
-    let folder_id = folder_service::create_folder_from_path(parent, bucket.id, org_sec.created_by, &mut conn).await?;
+    let folder_id = folder_service::create_folder_from_path(parent, bucket.id, created_by, &mut conn).await?;
 
     let file = path.file_name().map(|f| f.to_str().unwrap()).unwrap_or("");
 
-    let _file = diesel::insert_into(files::table)
-        .values(File::new(file.to_string(), folder_id, org_sec.created_by))
-        .on_conflict_do_nothing()
-        .execute(&mut conn)
-        .await?;
+    // A customer provided key takes precedence over the bucket's own encryption
+    let data_key = match &customer_key {
+        Some(customer_key) => Some(encryption_service::new_customer_data_key(customer_key)?),
+        None => encryption_service::new_data_key(bucket)?,
+    };
+    let wrapped_key = data_key.as_ref().map(|data_key| data_key.wrapped.clone());
+    // An unkeyed hash would let anyone who sees the etag confirm a guess of the plaintext
+    let etag = match (&customer_key, &data_key) {
+        (Some(_), Some(data_key)) => keyed_content_hash(&data_key.key, &body),
+        _ => content_hash(&body),
+    };
+    let fingerprint = customer_key.map(|customer_key| customer_key.fingerprint);
+    let new_file = File {
+        customer_key_fingerprint: fingerprint,
+        etag: Some(etag),
+        ..File::new(file.to_string(), folder_id, created_by, body.len() as i64, wrapped_key)
+    };
+    let object_path = file_path.to_string();
+    let scratch_path = write_scratch(bucket.id, &body, data_key.as_ref().map(|data_key| data_key.key.as_slice())).await?;
+    let target = StoreTarget { bucket_id: bucket.id, object_root: format!("files/{}/{}", organization_name, bucket.name), scratch_path: scratch_path.clone() };
+    let stored = conn.transaction::<File, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let file = store_file(new_file, &target, &object_path, &preconditions, conflict, replicate, conn).await?;
+            metadata_service::replace_file_metadata(file.id, &metadata, conn).await?;
+            Ok(file)
+        })
+    }).await;
+    if stored.is_err() {
+        let _ = fs::remove_file(&scratch_path).await;
+    }
+    stored.map(|_| ())
+}
+
+// Where store_file finds the object's bytes and where it moves them, `object_root` is the bucket's directory
+struct StoreTarget {
+    bucket_id: Uuid,
+    object_root: String,
+    scratch_path: String,
+}
 
-    let actual_file_path = format!("files/{}/{}/{}", organization_name, bucket_name, file_path);
-    let path = Path::new(&actual_file_path);
-    if let Some(parent) = path.parent() {
+// Fresh scratch file of a bucket, ABORT_INCOMPLETE_UPLOADS rules clean up the ones left behind
+async fn scratch_path(bucket_id: Uuid) -> String {
+    let dir = scratch_dir(bucket_id);
+    let _ = fs::create_dir_all(&dir).await;
+    format!("{dir}/{}", Uuid::now_v7())
+}
+
+pub fn scratch_dir(bucket_id: Uuid) -> String {
+    format!("{UPLOAD_DIR}/{bucket_id}")
+}
+
+// Writes an object to a scratch file in UPLOAD_DIR, store_file moves it into place once its row is stored
+async fn write_scratch(bucket_id: Uuid, body: &[u8], data_key: Option<&[u8]>) -> Result<String, ApiResponse> {
+    let scratch_path = scratch_path(bucket_id).await;
+    if let Err(e) = write_file(&scratch_path, body, data_key).await {
+        let _ = fs::remove_file(&scratch_path).await;
+        return Err(e);
+    }
+    Ok(scratch_path)
+}
+
+// Inserts `new_file` once `preconditions` hold, settling a clash with an existing file of the same name by `conflict`,
+// and moves its bytes from the scratch file into place
+async fn store_file(mut new_file: File, target: &StoreTarget, object_path: &str, preconditions: &Preconditions, conflict: ConflictPolicy, replicate: bool, conn: &mut AsyncPgConnection) -> Result<File, ApiResponse> {
+    let existing = find_file_for_update(&new_file, conn).await?;
+    preconditions.check(existing.as_ref())?;
+    // Size of the row an overwrite replaced, usage only counts a new object when there was none
+    let mut replaced = None;
+    // Only an overwrite may replace a row, the others insert so a concurrent upload of the same name is noticed
+    let file = match (&existing, conflict) {
+        (Some(_), ConflictPolicy::FAIL) => return Err(ApiResponse::new(StatusCode::CONFLICT, "A file with this name already exists".to_string())),
+        (None, ConflictPolicy::FAIL) => insert_file(&new_file, conn).await?
+            .ok_or(ApiResponse::new(StatusCode::CONFLICT, "A file with this name already exists".to_string()))?,
+        (Some(existing), ConflictPolicy::OVERWRITE) => {
+            replaced = Some(existing.size);
+            upsert_file(&new_file, conn).await?
+        }
+        (None, ConflictPolicy::OVERWRITE) => match insert_file(&new_file, conn).await? {
+            Some(file) => file,
+            // Another upload created the file since the lookup, it is overwritten like one that was there before
+            None => {
+                let raced = find_file_for_update(&new_file, conn).await?;
+                preconditions.check(raced.as_ref())?;
+                replaced = raced.map(|raced| raced.size);
+                upsert_file(&new_file, conn).await?
+            }
+        },
+        (_, ConflictPolicy::RENAME) => {
+            let name = new_file.name.clone();
+            let mut stored = None;
+            // Another upload can take the picked name before the insert, then pick again
+            for attempt in 0..folder_service::RENAME_ATTEMPTS {
+                if attempt > 0 || existing.is_some() {
+                    let taken = files::table
+                        .filter(files::folder_id.eq(new_file.folder_id))
+                        .filter(files::name.like(folder_service::name_pattern(&name)))
+                        .select(files::name)
+                        .load::<String>(conn)
+                        .await?;
+                    new_file.name = folder_service::available_name(&name, &taken);
+                }
+                stored = insert_file(&new_file, conn).await?;
+                if stored.is_some() {
+                    break;
+                }
+            }
+            stored.ok_or(ApiResponse::new(StatusCode::CONFLICT, "Cannot find a free name for the file".to_string()))?
+        }
+    };
+    let bucket_id = target.bucket_id;
+    match replaced {
+        Some(replaced) => usage_service::record(bucket_id, file.size - replaced, 0, conn).await?,
+        None => usage_service::record(bucket_id, file.size, 1, conn).await?,
+    }
+    // A renamed file is written next to the requested path rather than over it
+    let object_path = sibling_path(object_path, &file.name);
+    if replicate {
+        replication_service::enqueue_put(bucket_id, file.id, &object_path, conn).await?;
+    }
+    // Moved while the row is still locked, so concurrent writers cannot leave bytes under another write's data key
+    let actual_file_path = format!("{}/{object_path}", target.object_root);
+    if let Some(parent) = Path::new(&actual_file_path).parent() {
         let _ = fs::create_dir_all(parent).await;
     }
-    let _ = fs::remove_file(&actual_file_path).await;
-    let _ = fs::File::create(&actual_file_path).await
-        .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot create file".to_string()))?
-        .write_all(&body).await
+    fs::rename(&target.scratch_path, &actual_file_path).await
         .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot write to file".to_string()))?;
-    Ok(())
+    // Only queued once the bytes are in place, the indexer sees the entry when the transaction commits
+    search_service::enqueue(file.id, conn).await?;
+    Ok(file)
+}
+
+async fn find_file_for_update(new_file: &File, conn: &mut AsyncPgConnection) -> Result<Option<File>, ApiResponse> {
+    let file = files::table
+        .filter(files::folder_id.eq(new_file.folder_id))
+        .filter(files::name.eq(&new_file.name))
+        .select(File::as_select())
+        .for_update()
+        .first::<File>(conn)
+        .await
+        .optional()?;
+    Ok(file)
+}
+
+async fn insert_file(new_file: &File, conn: &mut AsyncPgConnection) -> Result<Option<File>, ApiResponse> {
+    let file = diesel::insert_into(files::table)
+        .values(new_file)
+        .on_conflict_do_nothing()
+        .get_result::<File>(conn)
+        .await
+        .optional()?;
+    Ok(file)
+}
+
+async fn upsert_file(new_file: &File, conn: &mut AsyncPgConnection) -> Result<File, ApiResponse> {
+    let file = diesel::insert_into(files::table)
+        .values(new_file)
+        .on_conflict((files::folder_id, files::name))
+        .do_update()
+        .set((
+            files::encryption_key.eq(&new_file.encryption_key),
+            files::customer_key_fingerprint.eq(&new_file.customer_key_fingerprint),
+            files::size.eq(new_file.size),
+            files::etag.eq(&new_file.etag),
+            files::cache_control.eq(&new_file.cache_control),
+            files::content_disposition.eq(&new_file.content_disposition),
+            files::content_type.eq(&new_file.content_type),
+            files::storage_tier.eq(StorageTier::STANDARD),
+            files::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<File>(conn)
+        .await?;
+    Ok(file)
+}
+
+// `path` with its last segment replaced by `name`
+fn sibling_path(path: &str, name: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((parent, _)) => format!("{parent}/{name}"),
+        None => name.to_string(),
+    }
+}
+
+// Strong entity tag of an object, the hex SHA-256 of its plaintext
+fn content_hash(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+// Entity tag of an object stored under a customer provided key, the hex HMAC-SHA256 of its plaintext keyed with its data key
+fn keyed_content_hash(data_key: &[u8], body: &[u8]) -> String {
+    let mut mac = hmac::Hmac::<Sha256>::new_from_slice(data_key).unwrap();
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
 }
 
 pub async fn remove_file(organization_name: String, bucket_name: String, file_path: String, query: FileQueryDto) -> Result<(), ApiResponse> {
@@ -195,32 +574,163 @@ pub async fn remove_file(organization_name: String, bucket_name: String, file_pa
     let (organization, bucket) = find_organization_and_bucket(&organization_name, &bucket_name, &mut conn).await?;
     let path = organization_name.to_string() + "/" + &bucket_name + "/" + &file_path;
     let _org_sec = verify_signature(&path, query, organization, true, &mut conn).await?;
+    drop(conn);
 
-    let path = Path::new(&file_path);
+    match delete_object(&organization_name, &bucket, &file_path, true).await? {
+        Some(_) => Ok(()),
+        None => Err(ApiResponse::new(StatusCode::NOT_FOUND, "File not found".to_string())),
+    }
+}
 
-    let parent = path.parent().map(|p| p.to_str().unwrap()).unwrap_or("");
-    let folder: Option<Uuid> = folder_service::get_folder_from_path(parent, bucket, &mut conn).await?;
+// Deletes the object at `file_path` if it exists; `replicate` queues the change for the bucket's replications
+pub async fn delete_object(organization_name: &str, bucket: &Bucket, file_path: &str, replicate: bool) -> Result<Option<File>, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let Some(file) = find_file_by_path(file_path, bucket, &mut conn).await? else {
+        return Ok(None);
+    };
 
-    let folder = match folder {
-        Some(folder_id) => folder_id,
-        None => return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Folder not found".to_string()))
+    let bucket_id = bucket.id;
+    let object_path = file_path.to_string();
+    let file = conn.transaction::<File, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let file = diesel::delete(files::table.find(file.id))
+                .get_result::<File>(conn)
+                .await
+                .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot delete file".to_string()))?;
+            usage_service::record(bucket_id, -file.size, -1, conn).await?;
+            if replicate {
+                replication_service::enqueue_delete(bucket_id, &object_path, conn).await?;
+            }
+            Ok(file)
+        })
+    }).await?;
+    drop(conn);
+    let actual_file_path = format!("files/{}/{}/{}", organization_name, bucket.name, file_path);
+    let _ = tokio::fs::remove_file(actual_file_path).await;
+    Ok(Some(file))
+}
+
+// Reads a whole object into memory, decrypting it with the bucket key when needed
+pub async fn read_object(path: &str, bucket: &Bucket, file: &File) -> Result<Vec<u8>, ApiResponse> {
+    let read_error = |_| ApiResponse::new(StatusCode::NOT_FOUND, "File not found".to_string());
+    let body = fs::read(path).await.map_err(read_error)?;
+    let Some(wrapped_key) = &file.encryption_key else {
+        return Ok(body);
     };
+    if file.customer_key_fingerprint.is_some() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "This file is encrypted with a customer provided key".to_string()));
+    }
 
-    let file = path.file_name().map(|f| f.to_str().unwrap()).unwrap_or("");
-    let file = diesel::delete(files::table)
-        .filter(files::folder_id.eq(folder))
-        .filter(files::name.eq(file))
-        .execute(&mut conn)
+    let data_key = encryption_service::data_key(bucket, wrapped_key)?;
+    let decrypt_error = || ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot decrypt file".to_string());
+    let header_len = HEADER_LEN as usize;
+    if body.len() < header_len {
+        return Err(decrypt_error());
+    }
+    let cipher = StreamCipher::from_header(&data_key, &body[..header_len]).map_err(|_| decrypt_error())?;
+    let chunks: Vec<&[u8]> = body[header_len..].chunks((CHUNK_SIZE + TAG_LEN) as usize).collect();
+    let mut plain = Vec::with_capacity(body.len());
+    for (index, chunk) in chunks.iter().enumerate() {
+        plain.extend(cipher.open(index as u64, index + 1 == chunks.len(), chunk).map_err(|_| decrypt_error())?);
+    }
+    Ok(plain)
+}
+
+// Opens a stored object for serving, or None when the bucket has no file at `file_path`
+pub async fn open_object(organization_name: &str, bucket: &Bucket, file_path: &str) -> Result<Option<ServedFile>, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let file = find_file_by_path(file_path, bucket, &mut conn).await?;
+    drop(conn);
+    let Some(file) = file else {
+        return Ok(None);
+    };
+    let path = format!("files/{}/{}/{}", organization_name, bucket.name, file_path);
+    open_file(&path, bucket, Some(&file), None, ResponseHeaders::default()).await.map(Some)
+}
+
+pub async fn find_file_by_path(file_path: &str, bucket: &Bucket, conn: &mut AsyncPgConnection) -> Result<Option<File>, ApiResponse> {
+    let path = Path::new(file_path);
+    let parent = path.parent().map(|p| p.to_str().unwrap()).unwrap_or("");
+    let name = path.file_name().map(|f| f.to_str().unwrap()).unwrap_or("");
+    let folder_id = match folder_service::get_folder_from_path(parent, bucket, conn).await? {
+        Some(folder_id) => folder_id,
+        None => return Ok(None),
+    };
+    let file = files::table
+        .filter(files::folder_id.eq(folder_id))
+        .filter(files::name.eq(name))
+        .select(File::as_select())
+        .first::<File>(conn)
         .await
-        .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot delete file".to_string()))?;
-    if file < 1 {
-        return Err(ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot delete file".to_string()));
+        .optional()?;
+    Ok(file)
+}
+
+async fn write_file(path: &str, body: &[u8], data_key: Option<&[u8]>) -> Result<(), ApiResponse> {
+    let mut file = fs::File::create(path).await
+        .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot create file".to_string()))?;
+    let write_error = |_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot write to file".to_string());
+    let Some(data_key) = data_key else {
+        return file.write_all(body).await.map_err(write_error);
+    };
+
+    let cipher = StreamCipher::new(data_key);
+    file.write_all(&cipher.header()).await.map_err(write_error)?;
+    let chunks: Vec<&[u8]> = if body.is_empty() { vec![body] } else { body.chunks(CHUNK_SIZE as usize).collect() };
+    for (index, chunk) in chunks.iter().enumerate() {
+        let sealed = cipher.seal(index as u64, index + 1 == chunks.len(), chunk)
+            .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot encrypt file".to_string()))?;
+        file.write_all(&sealed).await.map_err(write_error)?;
     }
-    let actual_file_path = format!("files/{}/{}/{}", organization_name, bucket_name, file_path);
-    let _ = tokio::fs::remove_file(actual_file_path).await;
     Ok(())
 }
 
+async fn open_file(path: &str, bucket: &Bucket, file: Option<&File>, customer_key: Option<&CustomerKey>, overrides: ResponseHeaders) -> Result<ServedFile, ApiResponse> {
+    let opened = open_file_content(path, bucket, file, customer_key).await?;
+    let stored = file.map(|file| ResponseHeaders {
+        cache_control: file.cache_control.clone(),
+        content_disposition: file.content_disposition.clone(),
+        content_type: file.content_type.clone(),
+    }).unwrap_or_default();
+    let headers = [
+        (header::CACHE_CONTROL, overrides.cache_control.or(stored.cache_control).or(bucket.cache_control.clone())),
+        (header::CONTENT_DISPOSITION, overrides.content_disposition.or(stored.content_disposition).or(bucket.content_disposition.clone())),
+        (header::CONTENT_TYPE, overrides.content_type.or(stored.content_type).or(bucket.content_type.clone())),
+    ];
+    let mut headers: Vec<(HeaderName, String)> = headers.into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect();
+    if let Some(file) = file {
+        let mut conn = db_config::get_connection().await?;
+        let metadata = metadata_service::file_metadata(&[file.id], &mut conn).await?.remove(&file.id).unwrap_or_default();
+        headers.extend(metadata_service::to_headers(&metadata));
+    }
+    Ok(ServedFile::new(opened, headers, file))
+}
+
+async fn open_file_content(path: &str, bucket: &Bucket, file: Option<&File>, customer_key: Option<&CustomerKey>) -> Result<Either<NamedFile, EncryptedFile>, ApiResponse> {
+    let encryption = file.and_then(|file| file.encryption_key.as_ref().map(|key| (key, file.customer_key_fingerprint.as_ref())));
+    match encryption {
+        Some((wrapped_key, fingerprint)) => {
+            let data_key = match fingerprint {
+                Some(fingerprint) => encryption_service::customer_data_key(fingerprint, wrapped_key, customer_key)?,
+                None => encryption_service::data_key(bucket, wrapped_key)?,
+            };
+            let file = EncryptedFile::open(path, &data_key).await
+                .map_err(|_| ApiResponse::new(StatusCode::NOT_FOUND, "File not found".to_string()))?;
+            Ok(Either::Right(file))
+        }
+        None if customer_key.is_some() => {
+            Err(ApiResponse::new(StatusCode::BAD_REQUEST, "This file is not encrypted with a customer provided key".to_string()))
+        }
+        None => {
+            let file = NamedFile::open_async(path).await
+                .map_err(|_| ApiResponse::new(StatusCode::NOT_FOUND, "File not found".to_string()))?;
+            Ok(Either::Left(file))
+        }
+    }
+}
+
 async fn find_organization_and_bucket(organization_name: &str, bucket_name: &str, conn: &mut AsyncPgConnection) -> Result<(Organization, Bucket), ApiResponse> {
     let (org, bucket) = organizations::table
         .left_join(buckets::table.on(buckets::organization_id.eq(organizations::id)))
@@ -237,7 +747,7 @@ async fn find_organization_and_bucket(organization_name: &str, bucket_name: &str
 
 
 async fn verify_signature(path: &str, query: FileQueryDto, org: Organization, is_upload: bool, conn: &mut AsyncPgConnection) -> Result<OrganizationSecret, ApiResponse> {
-    let FileQueryDto { expiry, secret_id, signature} = query;
+    let FileQueryDto { expiry, secret_id, signature, response_content_disposition, response_content_type, .. } = query;
     let org_secret = organization_secrets::table
         .filter(organization_secrets::id.eq(&secret_id))
         .filter(organization_secrets::organization_id.eq(org.id))
@@ -245,10 +755,13 @@ async fn verify_signature(path: &str, query: FileQueryDto, org: Organization, is
         .first::<OrganizationSecret>(conn)
         .await?;
 
+    // Response overrides are appended unencoded, in this order, when present
     let s = format!(
-        "{path}?{}secret_id={secret_id}{}",
+        "{path}?{}secret_id={secret_id}{}{}{}",
         if is_upload { "upload=true&" } else { "" },
         expiry.map(|e| format!("&expiry={e}")).unwrap_or_default(),
+        response_content_disposition.map(|d| format!("&response-content-disposition={d}")).unwrap_or_default(),
+        response_content_type.map(|t| format!("&response-content-type={t}")).unwrap_or_default(),
     );
 
 
@@ -271,4 +784,48 @@ async fn verify_signature(path: &str, query: FileQueryDto, org: Organization, is
         }
     }
     Ok(org_secret)
-}
\ No newline at end of file
+}
+pub async fn update_headers(file_id: Uuid, headers: ResponseHeaders, user: &User) -> Result<File, ApiResponse> {
+    validate_response_headers(&headers)?;
+    let mut conn = db_config::get_connection().await?;
+    let (_file, user_organization) = files::table.find(file_id)
+        .inner_join(folders::table.on(folders::id.eq(files::folder_id)))
+        .inner_join(buckets::table.on(buckets::id.eq(folders::bucket_id)))
+        .left_join(user_organizations::table.on(user_organizations::organization_id.eq(buckets::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select((File::as_select(), Option::<UserOrganization>::as_select()))
+        .first::<(File, Option<UserOrganization>)>(&mut conn)
+        .await?;
+    match token_service::restrict(user_organization) {
+        Some(user_organization) if EDITABLE_ROLES.contains(&user_organization.role) => (),
+        _ => return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()))
+    }
+    let ResponseHeaders { cache_control, content_disposition, content_type } = headers;
+    let file = diesel::update(files::table.find(file_id))
+        .set((
+            files::cache_control.eq(cache_control),
+            files::content_disposition.eq(content_disposition),
+            files::content_type.eq(content_type),
+            files::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<File>(&mut conn)
+        .await?;
+    Ok(file)
+}
+
+pub fn validate_response_headers(headers: &ResponseHeaders) -> Result<(), ApiResponse> {
+    let invalid = |name: &str| ApiResponse::new(StatusCode::BAD_REQUEST, format!("Invalid {name}"));
+    if let Some(cache_control) = &headers.cache_control {
+        HeaderValue::from_str(cache_control).map_err(|_| invalid("Cache-Control"))?;
+    }
+    if let Some(content_disposition) = &headers.content_disposition {
+        let value = HeaderValue::from_str(content_disposition).map_err(|_| invalid("Content-Disposition"))?;
+        let disposition = ContentDisposition::from_raw(&value).map_err(|_| invalid("Content-Disposition"))?;
+        if !matches!(disposition.disposition, DispositionType::Inline | DispositionType::Attachment) {
+            return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Content-Disposition must be inline or attachment".to_string()));
+        }
+    }
+    if let Some(content_type) = &headers.content_type {
+        content_type.parse::<Mime>().map_err(|_| invalid("Content-Type"))?;
+        HeaderValue::from_str(content_type).map_err(|_| invalid("Content-Type"))?;
+    }
+    Ok(())
+}
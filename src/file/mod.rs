@@ -1,4 +1,6 @@
-mod file_model;
+pub mod file_model;
 pub mod file_handler;
 pub mod file_service;
+pub mod served_file;
+mod object_writer;
 mod file_dto;
\ No newline at end of file
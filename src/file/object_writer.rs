@@ -0,0 +1,67 @@
+use crate::error::ApiResponse;
+use crate::util::crypto_util::{StreamCipher, CHUNK_SIZE};
+use actix_web::http::StatusCode;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+// Writes an object to disk as its bytes arrive, encrypting it chunk by chunk when a data key is given
+// and hashing the plaintext for the object's entity tag
+pub struct ObjectWriter {
+    file: fs::File,
+    cipher: Option<StreamCipher>,
+    pending: Vec<u8>,
+    chunk: u64,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl ObjectWriter {
+    pub async fn create(path: impl AsRef<Path>, data_key: Option<&[u8]>) -> Result<ObjectWriter, ApiResponse> {
+        let mut file = fs::File::create(path).await
+            .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot create file".to_string()))?;
+        let cipher = data_key.map(StreamCipher::new);
+        if let Some(cipher) = &cipher {
+            file.write_all(&cipher.header()).await.map_err(write_error)?;
+        }
+        Ok(ObjectWriter { file, cipher, pending: Vec::new(), chunk: 0, hasher: Sha256::new(), size: 0 })
+    }
+
+    pub async fn write(&mut self, bytes: &[u8]) -> Result<(), ApiResponse> {
+        self.hasher.update(bytes);
+        self.size += bytes.len() as u64;
+        if self.cipher.is_none() {
+            return self.file.write_all(bytes).await.map_err(write_error);
+        }
+        // A chunk is only sealed once more data follows it, the last one is sealed with its own nonce flag
+        self.pending.extend_from_slice(bytes);
+        while self.pending.len() > CHUNK_SIZE as usize {
+            let rest = self.pending.split_off(CHUNK_SIZE as usize);
+            let chunk = std::mem::replace(&mut self.pending, rest);
+            self.seal(&chunk, false).await?;
+        }
+        Ok(())
+    }
+
+    // Flushes the object and returns its plaintext size and content hash
+    pub async fn finish(mut self) -> Result<(u64, String), ApiResponse> {
+        if self.cipher.is_some() {
+            let chunk = std::mem::take(&mut self.pending);
+            self.seal(&chunk, true).await?;
+        }
+        self.file.flush().await.map_err(write_error)?;
+        Ok((self.size, hex::encode(self.hasher.finalize())))
+    }
+
+    async fn seal(&mut self, chunk: &[u8], last: bool) -> Result<(), ApiResponse> {
+        let sealed = self.cipher.as_ref().unwrap().seal(self.chunk, last, chunk)
+            .map_err(|_| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot encrypt file".to_string()))?;
+        self.chunk += 1;
+        self.file.write_all(&sealed).await.map_err(write_error)
+    }
+}
+
+fn write_error(_: std::io::Error) -> ApiResponse {
+    ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Cannot write to file".to_string())
+}
@@ -0,0 +1,93 @@
+use crate::encryption::encrypted_file::EncryptedFile;
+use crate::file::file_model::File;
+use actix_files::NamedFile;
+use actix_web::body::BoxBody;
+use actix_web::http::header::{self, EntityTag, Header, HeaderName, HeaderValue, HttpDate, IfModifiedSince, IfNoneMatch};
+use actix_web::http::Method;
+use actix_web::{Either, HttpRequest, HttpResponse, Responder};
+use std::time::SystemTime;
+
+// A stored object ready to be sent, along with its response headers and the validators
+// conditional downloads are checked against
+pub struct ServedFile {
+    content: Either<NamedFile, EncryptedFile>,
+    headers: Vec<(HeaderName, String)>,
+    etag: Option<EntityTag>,
+    last_modified: Option<HttpDate>,
+}
+
+impl ServedFile {
+    pub fn new(content: Either<NamedFile, EncryptedFile>, headers: Vec<(HeaderName, String)>, file: Option<&File>) -> Self {
+        // Files stored before content hashes were recorded keep NamedFile's own mtime based validators
+        let Some((file, etag)) = file.and_then(|file| file.etag.as_ref().map(|etag| (file, etag))) else {
+            return ServedFile { content, headers, etag: None, last_modified: None };
+        };
+        let content = match content {
+            Either::Left(named) => Either::Left(named.use_etag(false).use_last_modified(false)),
+            encrypted => encrypted,
+        };
+        let modified_at = file.updated_at.unwrap_or(file.created_at).and_utc();
+        ServedFile {
+            content,
+            headers,
+            etag: Some(EntityTag::new_strong(etag.clone())),
+            last_modified: Some(HttpDate::from(SystemTime::from(modified_at))),
+        }
+    }
+
+    fn is_not_modified(&self, req: &HttpRequest) -> bool {
+        let Some(etag) = &self.etag else {
+            return false;
+        };
+        if !matches!(*req.method(), Method::GET | Method::HEAD) {
+            return false;
+        }
+        // If-Modified-Since is only looked at when the client has no entity tag to send
+        if req.headers().contains_key(header::IF_NONE_MATCH) {
+            return match IfNoneMatch::parse(req) {
+                Ok(IfNoneMatch::Any) => true,
+                Ok(IfNoneMatch::Items(items)) => items.iter().any(|item| item.weak_eq(etag)),
+                Err(_) => false,
+            };
+        }
+        match (IfModifiedSince::parse(req), self.last_modified) {
+            (Ok(IfModifiedSince(since)), Some(last_modified)) => last_modified <= since,
+            _ => false,
+        }
+    }
+
+    fn validators(&self) -> Vec<(HeaderName, String)> {
+        let mut validators = Vec::new();
+        if let Some(etag) = &self.etag {
+            validators.push((header::ETAG, etag.to_string()));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            validators.push((header::LAST_MODIFIED, last_modified.to_string()));
+        }
+        validators
+    }
+}
+
+impl Responder for ServedFile {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+        if self.is_not_modified(req) {
+            let mut res = HttpResponse::NotModified();
+            let cache_control = self.headers.iter().filter(|(name, _)| *name == header::CACHE_CONTROL).cloned();
+            for (name, value) in self.validators().into_iter().chain(cache_control) {
+                res.insert_header((name, value));
+            }
+            return res.finish();
+        }
+
+        let validators = self.validators();
+        let mut res = self.content.respond_to(req).map_into_boxed_body();
+        for (name, value) in self.headers.into_iter().chain(validators) {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                res.headers_mut().insert(name, value);
+            }
+        }
+        res
+    }
+}
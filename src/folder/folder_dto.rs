@@ -1,6 +1,6 @@
-use crate::folder::folder_model::Folder;
+use crate::folder::folder_model::{ConflictPolicy, Folder};
 use chrono::NaiveDateTime;
-use diesel::sql_types::{Text, Timestamp, Uuid as SqlUuid};
+use diesel::sql_types::{Jsonb, Text, Timestamp, Uuid as SqlUuid};
 use diesel::QueryableByName;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -10,6 +10,12 @@ pub struct CreateFolderDTO {
     pub name: String,
     pub bucket_id: Uuid,
     pub parent_id: Uuid,
+    #[serde(default = "default_folder_conflict")]
+    pub conflict: ConflictPolicy,
+}
+
+fn default_folder_conflict() -> ConflictPolicy {
+    ConflictPolicy::FAIL
 }
 
 #[derive(Deserialize)]
@@ -17,6 +23,8 @@ pub struct SearchFolderDto {
     pub bucket_id: Uuid,
     pub folder_id: Option<Uuid>,
     pub keyword: Option<String>,
+    // JSON object of metadata pairs every returned entry must have
+    pub metadata: Option<String>,
     pub limit: i64,
     pub cursor: Option<Uuid>,
     #[serde(default)]
@@ -60,4 +68,7 @@ pub struct Entry {
 
     #[diesel(sql_type = Text)]
     pub user_username: String,
+
+    #[diesel(sql_type = Jsonb)]
+    pub metadata: serde_json::Value,
 }
\ No newline at end of file
@@ -8,8 +8,8 @@ use actix_web::{delete, get, post, HttpMessage, HttpRequest, Responder};
 
 #[post("")]
 pub async fn create(dto: Json<CreateFolderDTO>, request: HttpRequest) -> Result<Json<Folder>, ApiResponse> {
-    let CreateFolderDTO { name, bucket_id, parent_id } = dto.into_inner();
-    let folder = folder_service::create(name, bucket_id, parent_id, request.extensions().get::<User>().unwrap()).await?;
+    let CreateFolderDTO { name, bucket_id, parent_id, conflict } = dto.into_inner();
+    let folder = folder_service::create(name, bucket_id, parent_id, conflict, request.extensions().get::<User>().unwrap()).await?;
     Ok(Json(folder))
 }
 
@@ -17,8 +17,7 @@ pub async fn create(dto: Json<CreateFolderDTO>, request: HttpRequest) -> Result<
 async fn get(dto: Query<SearchFolderDto>, request: HttpRequest) -> Result<impl Responder, ApiResponse> {
     let extensions = request.extensions();
     let user = extensions.get::<User>().unwrap();
-    let SearchFolderDto { bucket_id, folder_id, cursor, cursor_kind, limit, keyword } = dto.into_inner();
-    let (folder, items) = folder_service::find(bucket_id, folder_id, keyword, limit, cursor, cursor_kind, user).await?;
+    let (folder, items) = folder_service::find(dto.into_inner(), user).await?;
 
     Ok(Json(FolderResponseDto { folder, items }))
 }
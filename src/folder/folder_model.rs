@@ -4,7 +4,7 @@ use crate::user::user_model::User;
 use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::QueryableByName;
 use diesel::{Associations, FromSqlRow, Insertable, Queryable, Selectable};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 // use diesel::alias;
 
@@ -37,6 +37,15 @@ impl Folder {
     }
 }
 
+// What happens when a file or folder is created under a name that is already taken
+#[derive(Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    FAIL,
+    OVERWRITE,
+    RENAME,
+}
+
 #[derive(QueryableByName)]
 pub struct FolderId {
     #[diesel(sql_type = diesel::sql_types::Uuid)]
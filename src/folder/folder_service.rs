@@ -1,29 +1,37 @@
 use crate::bucket::bucket_model::Bucket;
 use crate::config::db_config;
 use crate::error::ApiResponse;
-use crate::folder::folder_dto::Entry;
-use crate::folder::folder_model::{Folder, FolderId};
+use crate::folder::folder_dto::{Entry, SearchFolderDto};
+use crate::folder::folder_model::{ConflictPolicy, Folder, FolderId};
 use crate::organization::organization_model::{Organization, OrganizationRole, UserOrganization};
 use crate::organization::organization_service;
 use crate::schema::folders;
 use crate::schema::user_organizations;
 use crate::schema::{buckets, organizations};
+use crate::metadata::metadata_service;
+use crate::replication::replication_service;
+use crate::usage::usage_service;
+use crate::token::token_service;
 use crate::user::user_model::User;
 use actix_web::http::StatusCode;
-use diesel::sql_types::{BigInt, Nullable, Text};
+use diesel::sql_types::{BigInt, Jsonb, Nullable, Text};
 use diesel::sql_types::{Uuid as SqlUuid};
-use diesel::{sql_query, ExpressionMethods, JoinOnDsl, QueryDsl, SelectableHelper};
-use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use diesel::{sql_query, ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, SelectableHelper, TextExpressionMethods};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
 use lazy_static::lazy_static;
 use std::option::Option;
 use std::path::Path;
 use tokio::fs;
 use uuid::Uuid;
 
+// How often a renamed file or folder is retried when a concurrent create takes the name it picked
+pub const RENAME_ATTEMPTS: usize = 5;
+
 lazy_static! {
     pub static ref EDITABLE_ROLES: [OrganizationRole; 3] = [OrganizationRole::OWNER, OrganizationRole::ADMIN, OrganizationRole::EDITOR];
 }
-pub async fn create(name: String, bucket_id: Uuid, parent_id: Uuid, user: &User) -> Result<Folder, ApiResponse> {
+// `conflict` decides what happens when the parent already has a folder with this name; overwriting reuses that folder
+pub async fn create(name: String, bucket_id: Uuid, parent_id: Uuid, conflict: ConflictPolicy, user: &User) -> Result<Folder, ApiResponse> {
     let mut conn = db_config::get_connection().await?;
     let buc = buckets::table.find(bucket_id)
         .first::<Bucket>(&mut conn)
@@ -41,10 +49,49 @@ pub async fn create(name: String, bucket_id: Uuid, parent_id: Uuid, user: &User)
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Folder is not in this bucket".to_string()));
     }
 
-    let folder = diesel::insert_into(folders::dsl::folders)
-        .values(Folder::new(name, bucket_id, Some(parent_id), user.id))
-        .get_result::<Folder>(&mut conn)
-        .await?;
+    let existing = folders::table
+        .filter(folders::parent_id.eq(parent_id))
+        .filter(folders::name.eq(&name))
+        .first::<Folder>(&mut conn)
+        .await
+        .optional()?;
+    // The unique (bucket_id, parent_id, name) constraint settles concurrent creates, the check above only spares an insert
+    let folder = match (existing, conflict) {
+        (Some(_), ConflictPolicy::FAIL) => return Err(ApiResponse::new(StatusCode::CONFLICT, "A folder with this name already exists".to_string())),
+        (Some(existing), ConflictPolicy::OVERWRITE) => return Ok(existing),
+        (None, ConflictPolicy::FAIL) => insert_folder(Folder::new(name, bucket_id, Some(parent_id), user.id), &mut conn).await?
+            .ok_or(ApiResponse::new(StatusCode::CONFLICT, "A folder with this name already exists".to_string()))?,
+        (None, ConflictPolicy::OVERWRITE) => match insert_folder(Folder::new(name.clone(), bucket_id, Some(parent_id), user.id), &mut conn).await? {
+            Some(folder) => folder,
+            None => return folders::table
+                .filter(folders::parent_id.eq(parent_id))
+                .filter(folders::name.eq(&name))
+                .first::<Folder>(&mut conn)
+                .await
+                .map_err(ApiResponse::from),
+        },
+        (existing, ConflictPolicy::RENAME) => {
+            let mut stored = None;
+            for attempt in 0..RENAME_ATTEMPTS {
+                let candidate = if attempt > 0 || existing.is_some() {
+                    let taken = folders::table
+                        .filter(folders::parent_id.eq(parent_id))
+                        .filter(folders::name.like(name_pattern(&name)))
+                        .select(folders::name)
+                        .load::<String>(&mut conn)
+                        .await?;
+                    available_name(&name, &taken)
+                } else {
+                    name.clone()
+                };
+                stored = insert_folder(Folder::new(candidate, bucket_id, Some(parent_id), user.id), &mut conn).await?;
+                if stored.is_some() {
+                    break;
+                }
+            }
+            stored.ok_or(ApiResponse::new(StatusCode::CONFLICT, "Cannot find a free name for the folder".to_string()))?
+        }
+    };
 
     let mut path = "files/".to_owned() + &organization.name + "/" + &buc.name;
     path.push_str(&folder_path(folder.id, &mut conn).await?);
@@ -55,6 +102,35 @@ pub async fn create(name: String, bucket_id: Uuid, parent_id: Uuid, user: &User)
     Ok(folder)
 }
 
+async fn insert_folder(new_folder: Folder, conn: &mut AsyncPgConnection) -> Result<Option<Folder>, ApiResponse> {
+    let folder = diesel::insert_into(folders::table)
+        .values(new_folder)
+        .on_conflict_do_nothing()
+        .get_result::<Folder>(conn)
+        .await
+        .optional()?;
+    Ok(folder)
+}
+
+// LIKE pattern matching `name` and every numbered variant available_name can derive from it
+pub fn name_pattern(name: &str) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(name);
+    let escaped = stem.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("{escaped}%")
+}
+
+// First of `name`, `name (1)`, `name (2)`, ... that is not in `taken`, keeping the extension last
+pub fn available_name(name: &str, taken: &[String]) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(name);
+    let extension = path.extension().and_then(|extension| extension.to_str()).map(|extension| format!(".{extension}")).unwrap_or_default();
+    (0..)
+        .map(|n| if n == 0 { name.to_string() } else { format!("{stem} ({n}){extension}") })
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
 pub async fn folder_path(folder_id: Uuid, conn: &mut AsyncPgConnection) -> Result<String, ApiResponse> {
     let query = r#"
     WITH RECURSIVE folder_chain AS (
@@ -88,7 +164,7 @@ pub async fn create_folder_from_path(path: &str, bucket_id: Uuid, created_by: Uu
     Ok(folder.id)
 }
 
-pub async fn get_folder_from_path(path: &str, bucket: Bucket, conn: &mut AsyncPgConnection) -> Result<Option<Uuid>, ApiResponse> {
+pub async fn get_folder_from_path(path: &str, bucket: &Bucket, conn: &mut AsyncPgConnection) -> Result<Option<Uuid>, ApiResponse> {
     let query = r#"SELECT folder_exists_for_path($1, $2) as id;"#;
     let folder: Option<FolderId> = sql_query(query)
         .bind::<diesel::sql_types::Uuid, _>(bucket.id)
@@ -102,7 +178,10 @@ pub async fn get_folder_from_path(path: &str, bucket: Bucket, conn: &mut AsyncPg
     }
 }
 
-pub async fn find(bucket_id: Uuid, folder_id: Option<Uuid>,keyword: Option<String>, limit: i64, cursor: Option<Uuid>, cursor_kind: String, user: &User) -> Result<(Folder, Vec<Entry>), ApiResponse> {
+pub async fn find(query: SearchFolderDto, user: &User) -> Result<(Folder, Vec<Entry>), ApiResponse> {
+    let SearchFolderDto { bucket_id, folder_id, keyword, metadata, limit, cursor, cursor_kind } = query;
+    let filter = serde_json::to_value(metadata_service::parse_filter(metadata.as_deref())?)
+        .map_err(|e| ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()))?;
     let mut conn = db_config::get_connection().await?;
     let (_, user_org) = buckets::dsl::buckets.find(bucket_id)
         .left_join(user_organizations::table.on(user_organizations::organization_id.eq(buckets::organization_id)))
@@ -110,6 +189,7 @@ pub async fn find(bucket_id: Uuid, folder_id: Option<Uuid>,keyword: Option<Strin
         .select((Bucket::as_select(), Option::<UserOrganization>::as_select()))
         .first::<(Bucket, Option<UserOrganization>)>(&mut conn)
         .await?;
+    let user_org = token_service::restrict(user_org);
 
     if user_org.is_none() {
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()))
@@ -132,21 +212,25 @@ pub async fn find(bucket_id: Uuid, folder_id: Option<Uuid>,keyword: Option<Strin
     let folder_cursor_condition = if cursor_kind == "folder" { format!("AND public.folders.id < '{cursor_id}'") } else if cursor_kind == "file" { "AND FALSE".to_string() } else { "".to_string() };
     let file_cursor_condition = if cursor_kind == "file" { format!("AND public.files.id < '{cursor_id}'") } else { "".to_string() };
 let query = format!(r#"
-        SELECT public.folders.id as id, public.folders.name as name, 'folder' as kind, public.folders.created_at as created_at, created_by, users.name as user_name, users.username as user_username, users.email as user_email
+        SELECT public.folders.id as id, public.folders.name as name, 'folder' as kind, public.folders.created_at as created_at, created_by, users.name as user_name, users.username as user_username, users.email as user_email, meta.metadata as metadata
 FROM folders
 LEFT JOIN users ON users.id = folders.created_by
+CROSS JOIN LATERAL (SELECT COALESCE(jsonb_object_agg(key, value), '{{}}'::jsonb) as metadata FROM folder_metadata WHERE folder_metadata.folder_id = folders.id) meta
     WHERE parent_id = $2
   AND public.folders.name ILIKE $3
+  AND meta.metadata @> $5
   {folder_cursor_condition}
 
 UNION ALL
 
-SELECT public.files.id as id, public.files.name as name, 'file' as kind, public.files.created_at as created_at, created_by, users.name as user_name, users.username as user_username, users.email as user_email
+SELECT public.files.id as id, public.files.name as name, 'file' as kind, public.files.created_at as created_at, created_by, users.name as user_name, users.username as user_username, users.email as user_email, meta.metadata as metadata
 FROM files
 LEFT JOIN users ON users.id = files.created_by
+CROSS JOIN LATERAL (SELECT COALESCE(jsonb_object_agg(key, value), '{{}}'::jsonb) as metadata FROM file_metadata WHERE file_metadata.file_id = files.id) meta
 
-WHERE folder_id = $2
+WHERE files.folder_id = $2
   AND public.files.name ILIKE $3
+  AND meta.metadata @> $5
   {file_cursor_condition}
 
 ORDER BY kind DESC, id DESC
@@ -157,6 +241,7 @@ LIMIT $4
         .bind::<Nullable<SqlUuid>, _>(Some(folder.id))
         .bind::<Text, _>(keyword_pattern)
         .bind::<BigInt, _>(limit)
+        .bind::<Jsonb, _>(filter)
         .load::<Entry>(&mut conn)
         .await?;
 
@@ -173,6 +258,7 @@ pub async fn delete_folder(folder_id: Uuid,user_id: Uuid) -> Result<(), ApiRespo
         .select((Folder::as_select(), Option::<Bucket>::as_select(), Option::<Organization>::as_select(), Option::<UserOrganization>::as_select()))
         .get_result::<(Folder, Option<Bucket>, Option<Organization>, Option<UserOrganization>)>(&mut conn)
         .await?;
+    let user_org = token_service::restrict(user_org);
     if user_org.is_none() || organization.is_none() || bucket.is_none() {
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "No access to this file".to_string()))
     }
@@ -181,7 +267,16 @@ pub async fn delete_folder(folder_id: Uuid,user_id: Uuid) -> Result<(), ApiRespo
 
     let mut path = "files/".to_owned() + &organization.name + "/" + &bucket.name;
     path.push_str(&folder_path(folder.id, &mut conn).await?);
-    let _ = diesel::delete(folders::table.find(folder_id)).execute(&mut conn).await?;
+    let bucket_id = bucket.id;
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let totals = usage_service::folder_totals(folder_id, conn).await?;
+            usage_service::record(bucket_id, -totals.bytes, -totals.objects, conn).await?;
+            replication_service::enqueue_folder_delete(bucket_id, folder_id, conn).await?;
+            let _ = diesel::delete(folders::table.find(folder_id)).execute(conn).await?;
+            Ok(())
+        })
+    }).await?;
     drop(conn);
     let path = Path::new(&path);
     let _ = fs::remove_dir_all(path).await.unwrap();
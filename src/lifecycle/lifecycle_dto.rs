@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator_derive::Validate;
+use crate::lifecycle::lifecycle_model::{LifecycleAction, LifecycleRule};
+
+#[derive(Deserialize, Validate)]
+pub struct CreateLifecycleRuleDto {
+    pub bucket_id: Uuid,
+    #[serde(default)]
+    #[validate(length(max = 1024))]
+    pub prefix: String,
+    pub action: LifecycleAction,
+    #[validate(range(min = 1, max = 36500))]
+    pub age_days: i32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateLifecycleRuleDto {
+    pub rule_id: Uuid,
+    #[validate(length(max = 1024))]
+    pub prefix: Option<String>,
+    pub action: Option<LifecycleAction>,
+    #[validate(range(min = 1, max = 36500))]
+    pub age_days: Option<i32>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct LifecycleRuleIdDto {
+    pub rule_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct LifecycleRuleDto {
+    #[serde(flatten)]
+    pub rule: LifecycleRule,
+    pub effect: &'static str,
+}
+
+impl From<LifecycleRule> for LifecycleRuleDto {
+    fn from(rule: LifecycleRule) -> Self {
+        let effect = rule.action.effect();
+        LifecycleRuleDto { rule, effect }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
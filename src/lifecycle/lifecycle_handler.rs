@@ -0,0 +1,57 @@
+use crate::error::ApiResponse;
+use crate::lifecycle::lifecycle_dto::{CreateLifecycleRuleDto, LifecycleRuleDto, LifecycleRuleIdDto, UpdateLifecycleRuleDto};
+use crate::lifecycle::lifecycle_service;
+use crate::user::user_model::User;
+use actix_web::http::StatusCode;
+use actix_web::web::{Json, Path, ServiceConfig};
+use actix_web::{delete, get, post, put, HttpMessage, HttpRequest};
+use uuid::Uuid;
+use validator::Validate;
+
+#[get("bucket/{bucket_id}")]
+async fn list(bucket_id: Path<Uuid>, request: HttpRequest) -> Result<Json<Vec<LifecycleRuleDto>>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let rules = lifecycle_service::list(bucket_id.into_inner(), user).await?;
+    Ok(Json(rules.into_iter().map(LifecycleRuleDto::from).collect()))
+}
+
+#[post("")]
+async fn create(dto: Json<CreateLifecycleRuleDto>, request: HttpRequest) -> Result<Json<LifecycleRuleDto>, ApiResponse> {
+    if let Err(e) = dto.validate() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let CreateLifecycleRuleDto { bucket_id, prefix, action, age_days, enabled } = dto.into_inner();
+    let rule = lifecycle_service::create(bucket_id, prefix, action, age_days, enabled, user).await?;
+    Ok(Json(rule.into()))
+}
+
+#[put("")]
+async fn update(dto: Json<UpdateLifecycleRuleDto>, request: HttpRequest) -> Result<Json<LifecycleRuleDto>, ApiResponse> {
+    if let Err(e) = dto.validate() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let UpdateLifecycleRuleDto { rule_id, prefix, action, age_days, enabled } = dto.into_inner();
+    let rule = lifecycle_service::update(rule_id, prefix, action, age_days, enabled, user).await?;
+    Ok(Json(rule.into()))
+}
+
+#[delete("{rule_id}")]
+async fn delete(dto: Path<LifecycleRuleIdDto>, request: HttpRequest) -> Result<Json<LifecycleRuleDto>, ApiResponse> {
+    let LifecycleRuleIdDto { rule_id } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let rule = lifecycle_service::delete(rule_id, user).await?;
+    Ok(Json(rule.into()))
+}
+
+pub fn lifecycle_routes(cfg: &mut ServiceConfig) {
+    cfg.service(list);
+    cfg.service(create);
+    cfg.service(update);
+    cfg.service(delete);
+}
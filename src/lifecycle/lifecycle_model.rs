@@ -0,0 +1,86 @@
+use crate::bucket::bucket_model::Bucket;
+use crate::schema::lifecycle_rules;
+use crate::user::user_model::User;
+use chrono::{NaiveDateTime, Utc};
+use diesel::sql_types::{BigInt, Text};
+use diesel::{AsChangeset, Associations, Identifiable, Insertable, Queryable, QueryableByName, Selectable};
+use diesel_derive_enum::DbEnum;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Insertable, Identifiable, Selectable, Queryable, Associations, Serialize, Debug)]
+#[diesel(belongs_to(User, foreign_key = created_by))]
+#[diesel(belongs_to(Bucket))]
+#[diesel(table_name = lifecycle_rules)]
+pub struct LifecycleRule {
+    pub id: Uuid,
+    pub bucket_id: Uuid,
+    pub prefix: String,
+    pub action: LifecycleAction,
+    pub age_days: i32,
+    pub enabled: bool,
+    pub created_by: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub last_run_at: Option<NaiveDateTime>,
+}
+
+// EXPIRE deletes matching files, TRANSITION labels them with the cold storage tier without moving their bytes,
+// ABORT_INCOMPLETE_UPLOADS removes the bucket's scratch files of uploads that never completed and ignores the prefix
+#[derive(DbEnum, Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[db_enum(existing_type_path = "crate::schema::sql_types::LifecycleAction")]
+#[allow(non_camel_case_types)]
+pub enum LifecycleAction {
+    EXPIRE,
+    TRANSITION,
+    ABORT_INCOMPLETE_UPLOADS,
+}
+
+impl LifecycleAction {
+    // What a rule does, returned with every rule so clients do not read more into TRANSITION than it does
+    pub fn effect(&self) -> &'static str {
+        match self {
+            LifecycleAction::EXPIRE => "Deletes matching files",
+            LifecycleAction::TRANSITION => "Labels matching files with the cold storage tier, their bytes stay where they are",
+            LifecycleAction::ABORT_INCOMPLETE_UPLOADS => "Removes scratch files of uploads to the bucket that never completed",
+        }
+    }
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = lifecycle_rules)]
+pub struct LifecycleRuleChangeset {
+    pub prefix: Option<String>,
+    pub action: Option<LifecycleAction>,
+    pub age_days: Option<i32>,
+    pub enabled: Option<bool>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl LifecycleRule {
+    pub fn new(bucket_id: Uuid, prefix: String, action: LifecycleAction, age_days: i32, enabled: bool, user_id: Uuid) -> Self {
+        LifecycleRule {
+            id: Uuid::now_v7(),
+            bucket_id,
+            prefix,
+            action,
+            age_days,
+            enabled,
+            created_by: user_id,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            last_run_at: None,
+        }
+    }
+}
+
+// A file matched by a rule, with its path relative to the bucket root
+#[derive(QueryableByName, Debug)]
+pub struct LifecycleCandidate {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub path: String,
+    #[diesel(sql_type = BigInt)]
+    pub size: i64,
+}
@@ -0,0 +1,304 @@
+use crate::bucket::bucket_model::Bucket;
+use crate::config::db_config;
+use crate::error::ApiResponse;
+use crate::file::file_model::{File, StorageTier};
+use crate::file::file_service::{self, UPLOAD_DIR};
+use crate::folder::folder_service::EDITABLE_ROLES;
+use crate::lifecycle::lifecycle_model::{LifecycleAction, LifecycleCandidate, LifecycleRule, LifecycleRuleChangeset};
+use crate::organization::organization_model::{Organization, UserOrganization};
+use crate::schema::{buckets, files, lifecycle_rules, organizations, user_organizations};
+use crate::usage::usage_service;
+use crate::token::token_service;
+use crate::user::user_model::User;
+use actix_web::http::StatusCode;
+use chrono::{Duration, Utc};
+use diesel::sql_types::{BigInt, Text, Timestamp};
+use diesel::{sql_query, BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use std::env;
+use uuid::Uuid;
+
+lazy_static! {
+    static ref LIFECYCLE_INTERVAL: u64 = env::var("LIFECYCLE_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(3600);
+    static ref LIFECYCLE_BATCH_SIZE: i64 = env::var("LIFECYCLE_BATCH_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(500);
+    // Scratch files of uploads that never completed, e.g. because the server went down mid upload
+    static ref INCOMPLETE_UPLOAD_DAYS: u64 = env::var("LIFECYCLE_INCOMPLETE_UPLOAD_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(7);
+}
+
+pub async fn list(bucket_id: Uuid, user: &User) -> Result<Vec<LifecycleRule>, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_editable_bucket(bucket_id, user, &mut conn).await?;
+    let rules = lifecycle_rules::table
+        .filter(lifecycle_rules::bucket_id.eq(bucket_id))
+        .order(lifecycle_rules::id)
+        .select(LifecycleRule::as_select())
+        .load::<LifecycleRule>(&mut conn)
+        .await?;
+    Ok(rules)
+}
+
+pub async fn create(bucket_id: Uuid, prefix: String, action: LifecycleAction, age_days: i32, enabled: bool, user: &User) -> Result<LifecycleRule, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_editable_bucket(bucket_id, user, &mut conn).await?;
+    let prefix = normalize_prefix(prefix);
+    check_prefix(action, &prefix)?;
+    let rule = diesel::insert_into(lifecycle_rules::table)
+        .values(LifecycleRule::new(bucket_id, prefix, action, age_days, enabled, user.id))
+        .get_result::<LifecycleRule>(&mut conn)
+        .await?;
+    Ok(rule)
+}
+
+pub async fn update(rule_id: Uuid, prefix: Option<String>, action: Option<LifecycleAction>, age_days: Option<i32>, enabled: Option<bool>, user: &User) -> Result<LifecycleRule, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let rule = find_rule(rule_id, &mut conn).await?;
+    let _ = find_editable_bucket(rule.bucket_id, user, &mut conn).await?;
+    let prefix = prefix.map(normalize_prefix);
+    check_prefix(action.unwrap_or(rule.action), prefix.as_ref().unwrap_or(&rule.prefix))?;
+    let changeset = LifecycleRuleChangeset {
+        prefix,
+        action,
+        age_days,
+        enabled,
+        updated_at: Some(Utc::now().naive_utc()),
+    };
+    let rule = diesel::update(lifecycle_rules::table.find(rule_id))
+        .set(changeset)
+        .get_result::<LifecycleRule>(&mut conn)
+        .await?;
+    Ok(rule)
+}
+
+pub async fn delete(rule_id: Uuid, user: &User) -> Result<LifecycleRule, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let rule = find_rule(rule_id, &mut conn).await?;
+    let _ = find_editable_bucket(rule.bucket_id, user, &mut conn).await?;
+    let rule = diesel::delete(lifecycle_rules::table.find(rule_id))
+        .get_result::<LifecycleRule>(&mut conn)
+        .await?;
+    Ok(rule)
+}
+
+async fn find_rule(rule_id: Uuid, conn: &mut AsyncPgConnection) -> Result<LifecycleRule, ApiResponse> {
+    lifecycle_rules::table.find(rule_id)
+        .select(LifecycleRule::as_select())
+        .first::<LifecycleRule>(conn)
+        .await
+        .map_err(|_| ApiResponse::new(StatusCode::NOT_FOUND, "Lifecycle rule not found".to_string()))
+}
+
+async fn find_editable_bucket(bucket_id: Uuid, user: &User, conn: &mut AsyncPgConnection) -> Result<Bucket, ApiResponse> {
+    let (bucket, user_organization) = buckets::table.find(bucket_id)
+        .left_join(user_organizations::table.on(buckets::organization_id.eq(user_organizations::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select((Bucket::as_select(), Option::<UserOrganization>::as_select()))
+        .first::<(Bucket, Option<UserOrganization>)>(conn)
+        .await?;
+    match token_service::restrict(user_organization) {
+        Some(user_organization) if EDITABLE_ROLES.contains(&user_organization.role) => Ok(bucket),
+        _ => Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to manage lifecycle rules".to_string()))
+    }
+}
+
+// Prefixes are matched against paths relative to the bucket root, e.g. `logs/`
+fn normalize_prefix(prefix: String) -> String {
+    prefix.trim_start_matches('/').to_string()
+}
+
+// Scratch files of incomplete uploads have no path yet, a prefix on their rule would never match what it removes
+fn check_prefix(action: LifecycleAction, prefix: &str) -> Result<(), ApiResponse> {
+    if action == LifecycleAction::ABORT_INCOMPLETE_UPLOADS && !prefix.is_empty() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Rules that abort incomplete uploads cannot have a prefix".to_string()));
+    }
+    Ok(())
+}
+
+// Runs every enabled rule once per LIFECYCLE_INTERVAL_SECS for as long as the server is up
+pub fn start_worker() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(*LIFECYCLE_INTERVAL));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_rules().await {
+                error!("Lifecycle worker failed: {e}");
+            }
+            remove_incomplete_uploads().await;
+        }
+    });
+}
+
+async fn run_rules() -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let rules = lifecycle_rules::table
+        .filter(lifecycle_rules::enabled.eq(true))
+        .order(lifecycle_rules::id)
+        .select(LifecycleRule::as_select())
+        .load::<LifecycleRule>(&mut conn)
+        .await?;
+    drop(conn);
+
+    for rule in rules {
+        if let Err(e) = run_rule(&rule).await {
+            error!("Lifecycle rule {} on bucket {} failed: {e}", rule.id, rule.bucket_id);
+        }
+    }
+    Ok(())
+}
+
+async fn run_rule(rule: &LifecycleRule) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let (bucket, organization) = buckets::table.find(rule.bucket_id)
+        .inner_join(organizations::table.on(organizations::id.eq(buckets::organization_id)))
+        .select((Bucket::as_select(), Organization::as_select()))
+        .first::<(Bucket, Organization)>(&mut conn)
+        .await?;
+    // Rules from before age_days had an upper bound could overflow the date
+    let Some(cutoff) = Duration::try_days(rule.age_days as i64).and_then(|age| Utc::now().naive_utc().checked_sub_signed(age)) else {
+        warn!("Lifecycle rule {} skipped, {} days is out of range", rule.id, rule.age_days);
+        return Ok(());
+    };
+
+    let (count, bytes) = match rule.action {
+        LifecycleAction::ABORT_INCOMPLETE_UPLOADS => {
+            let max_age = std::time::Duration::from_secs(rule.age_days as u64 * 24 * 60 * 60);
+            remove_scratch_files(&file_service::scratch_dir(bucket.id), max_age).await
+        }
+        _ => apply_to_files(rule, &bucket, &organization, cutoff, &mut conn).await?,
+    };
+
+    diesel::update(lifecycle_rules::table.find(rule.id))
+        .set(lifecycle_rules::last_run_at.eq(Utc::now().naive_utc()))
+        .execute(&mut conn)
+        .await?;
+    if count > 0 {
+        let verb = match rule.action {
+            LifecycleAction::EXPIRE => "expired",
+            LifecycleAction::TRANSITION => "labelled cold",
+            LifecycleAction::ABORT_INCOMPLETE_UPLOADS => "aborted",
+        };
+        info!("Lifecycle rule {} {verb} {count} files ({bytes} bytes) under '{}' in bucket {}/{}", rule.id, rule.prefix, organization.name, bucket.name);
+    }
+    Ok(())
+}
+
+// Expires or transitions the files a rule matches, returning how many files and bytes it touched
+async fn apply_to_files(rule: &LifecycleRule, bucket: &Bucket, organization: &Organization, cutoff: chrono::NaiveDateTime, conn: &mut AsyncPgConnection) -> Result<(usize, i64), ApiResponse> {
+    let (mut count, mut bytes) = (0, 0);
+    loop {
+        let candidates = find_candidates(rule, cutoff, conn).await?;
+        let batch_len = candidates.len() as i64;
+        if batch_len == 0 {
+            break;
+        }
+        let ids: Vec<Uuid> = candidates.iter().map(|candidate| candidate.id).collect();
+        match rule.action {
+            LifecycleAction::EXPIRE => {
+                let bucket_id = bucket.id;
+                let deleted = conn.transaction::<Vec<File>, ApiResponse, _>(|conn| {
+                    Box::pin(async move {
+                        let deleted = diesel::delete(files::table.filter(files::id.eq_any(ids)))
+                            .get_results::<File>(conn)
+                            .await?;
+                        let size = deleted.iter().map(|file| file.size).sum::<i64>();
+                        usage_service::record(bucket_id, -size, -(deleted.len() as i64), conn).await?;
+                        Ok(deleted)
+                    })
+                }).await?;
+                for candidate in candidates.iter().filter(|candidate| deleted.iter().any(|file| file.id == candidate.id)) {
+                    let path = format!("files/{}/{}/{}", organization.name, bucket.name, candidate.path);
+                    if let Err(e) = tokio::fs::remove_file(&path).await {
+                        warn!("Lifecycle rule {} could not remove {path}: {e}", rule.id);
+                    }
+                }
+                bytes += deleted.iter().map(|file| file.size).sum::<i64>();
+                count += deleted.len();
+            }
+            // Only the label changes, cold files are served from the same disk as standard ones
+            LifecycleAction::TRANSITION => {
+                diesel::update(files::table.filter(files::id.eq_any(ids)))
+                    .set(files::storage_tier.eq(StorageTier::COLD))
+                    .execute(conn)
+                    .await?;
+                bytes += candidates.iter().map(|candidate| candidate.size).sum::<i64>();
+                count += candidates.len();
+            }
+            LifecycleAction::ABORT_INCOMPLETE_UPLOADS => unreachable!("incomplete uploads are not files"),
+        }
+        if batch_len < *LIFECYCLE_BATCH_SIZE {
+            break;
+        }
+    }
+    Ok((count, bytes))
+}
+
+// Server wide backstop for scratch files no ABORT_INCOMPLETE_UPLOADS rule removed, e.g. of buckets without one
+async fn remove_incomplete_uploads() {
+    let Ok(mut entries) = tokio::fs::read_dir(UPLOAD_DIR).await else {
+        return;
+    };
+    let max_age = std::time::Duration::from_secs(*INCOMPLETE_UPLOAD_DAYS * 24 * 60 * 60);
+    // Scratch files from before they were kept per bucket sit in UPLOAD_DIR itself
+    let mut count = remove_scratch_files(UPLOAD_DIR, max_age).await.0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_type().await.is_ok_and(|file_type| file_type.is_dir()) {
+            count += remove_scratch_files(&entry.path().to_string_lossy(), max_age).await.0;
+        }
+    }
+    if count > 0 {
+        info!("Lifecycle worker removed {count} incomplete uploads");
+    }
+}
+
+// Removes the scratch files in `dir` last written more than `max_age` ago, returning how many files and bytes it removed
+async fn remove_scratch_files(dir: &str, max_age: std::time::Duration) -> (usize, i64) {
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return (0, 0);
+    };
+    let (mut count, mut bytes) = (0, 0);
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        let expired = metadata.is_file() && metadata.modified().is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > max_age));
+        if !expired {
+            continue;
+        }
+        match tokio::fs::remove_file(entry.path()).await {
+            Ok(()) => {
+                count += 1;
+                bytes += metadata.len() as i64;
+            }
+            Err(e) => warn!("Lifecycle worker could not remove {}: {e}", entry.path().display()),
+        }
+    }
+    (count, bytes)
+}
+
+async fn find_candidates(rule: &LifecycleRule, cutoff: chrono::NaiveDateTime, conn: &mut AsyncPgConnection) -> Result<Vec<LifecycleCandidate>, ApiResponse> {
+    // Cold files are already where a transition would put them
+    let tier_condition = if rule.action == LifecycleAction::TRANSITION { "AND files.storage_tier = 'standard'" } else { "" };
+    let query = format!(r#"
+    WITH RECURSIVE paths AS (
+        SELECT id, ''::TEXT AS path FROM folders WHERE bucket_id = $1 AND parent_id IS NULL
+        UNION ALL
+        SELECT f.id, p.path || f.name || '/' FROM folders f
+        INNER JOIN paths p ON f.parent_id = p.id
+    )
+    SELECT files.id AS id, paths.path || files.name AS path, files.size AS size
+    FROM files
+    INNER JOIN paths ON paths.id = files.folder_id
+    WHERE starts_with(paths.path || files.name, $2)
+      AND coalesce(files.updated_at, files.created_at) < $3
+      {tier_condition}
+    ORDER BY files.id
+    LIMIT $4
+"#);
+    let candidates = sql_query(query)
+        .bind::<diesel::sql_types::Uuid, _>(rule.bucket_id)
+        .bind::<Text, _>(&rule.prefix)
+        .bind::<Timestamp, _>(cutoff)
+        .bind::<BigInt, _>(*LIFECYCLE_BATCH_SIZE)
+        .load::<LifecycleCandidate>(conn)
+        .await?;
+    Ok(candidates)
+}
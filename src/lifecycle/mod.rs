@@ -0,0 +1,4 @@
+pub mod lifecycle_handler;
+pub mod lifecycle_service;
+mod lifecycle_dto;
+pub mod lifecycle_model;
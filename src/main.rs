@@ -10,14 +10,44 @@ mod bucket;
 pub mod folder;
 mod file;
 mod config;
+mod encryption;
+mod usage;
+mod lifecycle;
+mod replication;
+mod website;
+mod domain;
+mod metadata;
+mod search;
+mod mail;
+mod mfa;
+mod token;
 
-use crate::auth::auth_handler::{auth_routes, github_callback, google_callback};
-use crate::auth::auth_middleware::jwt_auth;
+use crate::auth::auth_handler::{auth_routes, github_callback, google_callback, jwks};
+use crate::auth::auth_middleware::{jwt_auth, session_only};
 use crate::bucket::bucket_handler::bucket_routes;
+use crate::bucket::bucket_middleware::bucket_cors;
+use crate::domain::domain_handler::domain_routes;
+use crate::encryption::encryption_service;
 use crate::file::file_handler::{file_routes, fs_routes};
 use crate::folder::folder_handler::folder_routes;
+use crate::lifecycle::lifecycle_handler::lifecycle_routes;
+use crate::lifecycle::lifecycle_service;
+use crate::mail::mail_service;
+use crate::metadata::metadata_handler::metadata_routes;
+use crate::mfa::mfa_handler::mfa_routes;
 use crate::organization::organization_handler::{organization_routes, sdk_routes};
+use crate::replication::replication_handler::replication_routes;
+use crate::replication::replication_service;
+use crate::search::search_handler::search_routes;
+use crate::search::search_service;
+use crate::token::token_handler::token_routes;
+use crate::usage::usage_handler::usage_routes;
+use crate::usage::usage_service;
 use crate::user::user_handler::user_routes;
+use crate::user::user_service;
+use crate::util::jwt_util;
+use crate::website::website_handler::{hosting_routes, website_routes};
+use crate::website::website_middleware::website_host;
 use actix_files as fs;
 use actix_web::dev::ServiceResponse;
 use actix_web::http::{header, StatusCode};
@@ -31,20 +61,47 @@ async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
     init_from_env(Env::default().default_filter_or("info"));
     db_config::init().await;
+    jwt_util::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some((command, args)) = args.split_first() {
+        return match (command.as_str(), args) {
+            ("backfill-usage", []) => usage_service::backfill().await
+                .map(|(sized, buckets)| info!("Sized {sized} files, recounted usage of {buckets} buckets"))
+                .map_err(|e| std::io::Error::other(e.to_string())),
+            _ => encryption_service::run_command(command, args).await,
+        };
+    }
+    mail_service::init();
+    lifecycle_service::start_worker();
+    replication_service::start_worker();
+    search_service::start_worker();
+    user_service::start_deletion_worker();
     info!("Starting http server: 127.0.0.1:8080");
     HttpServer::new(|| {
         App::new()
+            .wrap(from_fn(website_host))
             .wrap(Logger::default())
             .wrap(Compress::default())
             .service(web::scope("/oauth").service(google_callback).service(github_callback))
-            .service(web::scope("/f").configure(fs_routes))
+            .service(jwks)
+            .service(web::scope("/f").wrap(from_fn(bucket_cors)).configure(fs_routes))
+            .service(web::scope("/w").configure(hosting_routes))
             .service(web::scope("/api")
                 .service(web::scope("/user").configure(user_routes))
                 .service(web::scope("/auth").configure(auth_routes))
+                .service(web::scope("/token").wrap(from_fn(session_only)).wrap(from_fn(jwt_auth)).configure(token_routes))
+                .service(web::scope("/mfa").wrap(from_fn(session_only)).wrap(from_fn(jwt_auth)).configure(mfa_routes))
                 .service(web::scope("/organization").wrap(from_fn(jwt_auth)).configure(organization_routes))
                 .service(web::scope("/bucket").wrap(from_fn(jwt_auth)).configure(bucket_routes))
                 .service(web::scope("/folder").wrap(from_fn(jwt_auth)).configure(folder_routes))
-                .service(web::scope("/file").wrap(from_fn(jwt_auth)).configure(file_routes)))
+                .service(web::scope("/file").wrap(from_fn(jwt_auth)).configure(file_routes))
+                .service(web::scope("/usage").wrap(from_fn(jwt_auth)).configure(usage_routes))
+                .service(web::scope("/lifecycle").wrap(from_fn(jwt_auth)).configure(lifecycle_routes))
+                .service(web::scope("/replication").wrap(from_fn(jwt_auth)).configure(replication_routes))
+                .service(web::scope("/website").wrap(from_fn(jwt_auth)).configure(website_routes))
+                .service(web::scope("/domain").wrap(from_fn(jwt_auth)).configure(domain_routes))
+                .service(web::scope("/metadata").wrap(from_fn(jwt_auth)).configure(metadata_routes))
+                .service(web::scope("/search").wrap(from_fn(jwt_auth)).configure(search_routes)))
             .service(web::scope("/sdk").configure(sdk_routes))
             .service(fs::Files::new("/", "./static").index_file("index.html"))
             .wrap(ErrorHandlers::new().handler(StatusCode::INTERNAL_SERVER_ERROR, add_error_header))
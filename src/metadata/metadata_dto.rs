@@ -0,0 +1,4 @@
+use std::collections::BTreeMap;
+
+// JSON merge patch over an object's metadata, a string sets the key and null removes it
+pub type MetadataPatchDto = BTreeMap<String, Option<String>>;
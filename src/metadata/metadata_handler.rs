@@ -0,0 +1,47 @@
+use crate::error::ApiResponse;
+use crate::metadata::metadata_dto::MetadataPatchDto;
+use crate::metadata::metadata_model::Metadata;
+use crate::metadata::metadata_service;
+use crate::user::user_model::User;
+use actix_web::web::{Json, Path, ServiceConfig};
+use actix_web::{get, patch, HttpMessage, HttpRequest};
+use uuid::Uuid;
+
+#[get("file/{file_id}")]
+async fn get_file(file_id: Path<Uuid>, request: HttpRequest) -> Result<Json<Metadata>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let metadata = metadata_service::get_file(file_id.into_inner(), user).await?;
+    Ok(Json(metadata))
+}
+
+#[patch("file/{file_id}")]
+async fn patch_file(file_id: Path<Uuid>, dto: Json<MetadataPatchDto>, request: HttpRequest) -> Result<Json<Metadata>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let metadata = metadata_service::patch_file(file_id.into_inner(), dto.into_inner(), user).await?;
+    Ok(Json(metadata))
+}
+
+#[get("folder/{folder_id}")]
+async fn get_folder(folder_id: Path<Uuid>, request: HttpRequest) -> Result<Json<Metadata>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let metadata = metadata_service::get_folder(folder_id.into_inner(), user).await?;
+    Ok(Json(metadata))
+}
+
+#[patch("folder/{folder_id}")]
+async fn patch_folder(folder_id: Path<Uuid>, dto: Json<MetadataPatchDto>, request: HttpRequest) -> Result<Json<Metadata>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let metadata = metadata_service::patch_folder(folder_id.into_inner(), dto.into_inner(), user).await?;
+    Ok(Json(metadata))
+}
+
+pub fn metadata_routes(cfg: &mut ServiceConfig) {
+    cfg.service(get_file);
+    cfg.service(patch_file);
+    cfg.service(get_folder);
+    cfg.service(patch_folder);
+}
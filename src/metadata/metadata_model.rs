@@ -0,0 +1,27 @@
+use crate::file::file_model::File;
+use crate::folder::folder_model::Folder;
+use crate::schema::{file_metadata, folder_metadata};
+use diesel::{Associations, Insertable, Queryable, Selectable};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+// User defined key/value pairs attached to a file or folder, keys are lowercase
+pub type Metadata = BTreeMap<String, String>;
+
+#[derive(Insertable, Selectable, Queryable, Associations, Debug)]
+#[diesel(belongs_to(File))]
+#[diesel(table_name = file_metadata)]
+pub struct FileMetadata {
+    pub file_id: Uuid,
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Insertable, Selectable, Queryable, Associations, Debug)]
+#[diesel(belongs_to(Folder))]
+#[diesel(table_name = folder_metadata)]
+pub struct FolderMetadata {
+    pub folder_id: Uuid,
+    pub key: String,
+    pub value: String,
+}
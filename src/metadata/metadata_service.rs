@@ -0,0 +1,197 @@
+use crate::config::db_config;
+use crate::error::ApiResponse;
+use crate::folder::folder_service::EDITABLE_ROLES;
+use crate::metadata::metadata_dto::MetadataPatchDto;
+use crate::metadata::metadata_model::{FileMetadata, FolderMetadata, Metadata};
+use crate::organization::organization_model::{OrganizationRole, UserOrganization};
+use crate::schema::{buckets, file_metadata, files, folder_metadata, folders, user_organizations};
+use crate::token::token_service;
+use crate::user::user_model::User;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use diesel::{BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Uploads set metadata through `x-blaze-meta-<key>` headers and downloads send it back the same way
+pub const HEADER_PREFIX: &str = "x-blaze-meta-";
+const MAX_ENTRIES: usize = 64;
+const MAX_KEY_LEN: usize = 128;
+const MAX_VALUE_LEN: usize = 1024;
+
+pub fn from_headers(headers: &HeaderMap) -> Result<Metadata, ApiResponse> {
+    let mut metadata = Metadata::new();
+    for (name, value) in headers {
+        let Some(key) = name.as_str().strip_prefix(HEADER_PREFIX) else {
+            continue;
+        };
+        let value = value.to_str()
+            .map_err(|_| ApiResponse::new(StatusCode::BAD_REQUEST, format!("Metadata {key} must be visible ASCII")))?;
+        metadata.insert(key.to_string(), value.to_string());
+    }
+    validate(&metadata)?;
+    Ok(metadata)
+}
+
+pub fn to_headers(metadata: &Metadata) -> Vec<(HeaderName, String)> {
+    metadata.iter()
+        .filter_map(|(key, value)| HeaderName::try_from(format!("{HEADER_PREFIX}{key}")).ok().map(|name| (name, value.clone())))
+        .collect()
+}
+
+// Listing filters come as a JSON object, an entry matches when it has every pair
+pub fn parse_filter(filter: Option<&str>) -> Result<Metadata, ApiResponse> {
+    match filter {
+        Some(filter) => serde_json::from_str::<Metadata>(filter)
+            .map_err(|_| ApiResponse::new(StatusCode::BAD_REQUEST, "Metadata filter must be a JSON object of strings".to_string())),
+        None => Ok(Metadata::new()),
+    }
+}
+
+fn validate(metadata: &Metadata) -> Result<(), ApiResponse> {
+    if metadata.len() > MAX_ENTRIES {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("At most {MAX_ENTRIES} metadata entries are allowed")));
+    }
+    for (key, value) in metadata {
+        let valid_key = !key.is_empty() && key.len() <= MAX_KEY_LEN
+            && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.'));
+        if !valid_key {
+            return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("Invalid metadata key {key}, use up to {MAX_KEY_LEN} lowercase letters, digits, '-', '_' or '.'")));
+        }
+        // Values are echoed as response headers on download, so they have to be valid header values
+        if value.len() > MAX_VALUE_LEN || HeaderValue::from_str(value).is_err() {
+            return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("Invalid value for metadata {key}")));
+        }
+    }
+    Ok(())
+}
+
+pub async fn file_metadata(file_ids: &[Uuid], conn: &mut AsyncPgConnection) -> Result<HashMap<Uuid, Metadata>, ApiResponse> {
+    let rows = file_metadata::table
+        .filter(file_metadata::file_id.eq_any(file_ids))
+        .select(FileMetadata::as_select())
+        .load::<FileMetadata>(conn)
+        .await?;
+    let mut metadata = HashMap::<Uuid, Metadata>::new();
+    for row in rows {
+        metadata.entry(row.file_id).or_default().insert(row.key, row.value);
+    }
+    Ok(metadata)
+}
+
+async fn folder_metadata(folder_id: Uuid, conn: &mut AsyncPgConnection) -> Result<Metadata, ApiResponse> {
+    let rows = folder_metadata::table
+        .filter(folder_metadata::folder_id.eq(folder_id))
+        .select(FolderMetadata::as_select())
+        .load::<FolderMetadata>(conn)
+        .await?;
+    Ok(rows.into_iter().map(|row| (row.key, row.value)).collect())
+}
+
+// Replaces everything stored on the file, an overwritten object does not keep its old metadata
+pub async fn replace_file_metadata(file_id: Uuid, metadata: &Metadata, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    diesel::delete(file_metadata::table.filter(file_metadata::file_id.eq(file_id)))
+        .execute(conn)
+        .await?;
+    let rows: Vec<FileMetadata> = metadata.iter()
+        .map(|(key, value)| FileMetadata { file_id, key: key.clone(), value: value.clone() })
+        .collect();
+    diesel::insert_into(file_metadata::table)
+        .values(rows)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn replace_folder_metadata(folder_id: Uuid, metadata: &Metadata, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    diesel::delete(folder_metadata::table.filter(folder_metadata::folder_id.eq(folder_id)))
+        .execute(conn)
+        .await?;
+    let rows: Vec<FolderMetadata> = metadata.iter()
+        .map(|(key, value)| FolderMetadata { folder_id, key: key.clone(), value: value.clone() })
+        .collect();
+    diesel::insert_into(folder_metadata::table)
+        .values(rows)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_file(file_id: Uuid, user: &User) -> Result<Metadata, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = file_role(file_id, user, &mut conn).await?;
+    let mut metadata = file_metadata(&[file_id], &mut conn).await?;
+    Ok(metadata.remove(&file_id).unwrap_or_default())
+}
+
+pub async fn patch_file(file_id: Uuid, patch: MetadataPatchDto, user: &User) -> Result<Metadata, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let role = file_role(file_id, user, &mut conn).await?;
+    if !EDITABLE_ROLES.contains(&role) {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to change this file".to_string()));
+    }
+    conn.transaction::<Metadata, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let mut metadata = file_metadata(&[file_id], conn).await?.remove(&file_id).unwrap_or_default();
+            apply_patch(&mut metadata, patch)?;
+            replace_file_metadata(file_id, &metadata, conn).await?;
+            Ok(metadata)
+        })
+    }).await
+}
+
+pub async fn get_folder(folder_id: Uuid, user: &User) -> Result<Metadata, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = folder_role(folder_id, user, &mut conn).await?;
+    folder_metadata(folder_id, &mut conn).await
+}
+
+pub async fn patch_folder(folder_id: Uuid, patch: MetadataPatchDto, user: &User) -> Result<Metadata, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let role = folder_role(folder_id, user, &mut conn).await?;
+    if !EDITABLE_ROLES.contains(&role) {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to change this folder".to_string()));
+    }
+    conn.transaction::<Metadata, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let mut metadata = folder_metadata(folder_id, conn).await?;
+            apply_patch(&mut metadata, patch)?;
+            replace_folder_metadata(folder_id, &metadata, conn).await?;
+            Ok(metadata)
+        })
+    }).await
+}
+
+fn apply_patch(metadata: &mut Metadata, patch: MetadataPatchDto) -> Result<(), ApiResponse> {
+    for (key, value) in patch {
+        match value {
+            Some(value) => metadata.insert(key, value),
+            None => metadata.remove(&key),
+        };
+    }
+    validate(metadata)
+}
+
+async fn file_role(file_id: Uuid, user: &User, conn: &mut AsyncPgConnection) -> Result<OrganizationRole, ApiResponse> {
+    let user_organization = files::table.find(file_id)
+        .inner_join(folders::table.on(folders::id.eq(files::folder_id)))
+        .inner_join(buckets::table.on(buckets::id.eq(folders::bucket_id)))
+        .left_join(user_organizations::table.on(user_organizations::organization_id.eq(buckets::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select(Option::<UserOrganization>::as_select())
+        .first::<Option<UserOrganization>>(conn)
+        .await?;
+    token_service::restrict(user_organization).map(|user_organization| user_organization.role)
+        .ok_or_else(|| ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()))
+}
+
+async fn folder_role(folder_id: Uuid, user: &User, conn: &mut AsyncPgConnection) -> Result<OrganizationRole, ApiResponse> {
+    let user_organization = folders::table.find(folder_id)
+        .inner_join(buckets::table.on(buckets::id.eq(folders::bucket_id)))
+        .left_join(user_organizations::table.on(user_organizations::organization_id.eq(buckets::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select(Option::<UserOrganization>::as_select())
+        .first::<Option<UserOrganization>>(conn)
+        .await?;
+    token_service::restrict(user_organization).map(|user_organization| user_organization.role)
+        .ok_or_else(|| ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()))
+}
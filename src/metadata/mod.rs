@@ -0,0 +1,4 @@
+pub mod metadata_handler;
+pub mod metadata_service;
+mod metadata_dto;
+pub mod metadata_model;
@@ -0,0 +1,26 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+pub struct MfaStatusDto {
+    pub enabled: bool,
+    pub enabled_at: Option<NaiveDateTime>,
+    pub recovery_codes_left: i64,
+}
+
+// Shown once when enrollment starts, either value can be put into an authenticator app
+#[derive(Serialize)]
+pub struct EnrollmentDto {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyCodeDto {
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodesDto {
+    pub recovery_codes: Vec<String>,
+}
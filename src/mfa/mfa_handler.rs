@@ -0,0 +1,57 @@
+use crate::error::ApiResponse;
+use crate::mfa::mfa_dto::{EnrollmentDto, MfaStatusDto, RecoveryCodesDto, VerifyCodeDto};
+use crate::mfa::mfa_service;
+use crate::user::user_model::User;
+use actix_web::web::{Json, ServiceConfig};
+use actix_web::{delete, get, post, HttpMessage, HttpRequest, HttpResponse};
+
+#[get("")]
+async fn status(request: HttpRequest) -> Result<Json<MfaStatusDto>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let status = mfa_service::status(user).await?;
+    Ok(Json(status))
+}
+
+#[post("enrollment")]
+async fn start_enrollment(request: HttpRequest) -> Result<Json<EnrollmentDto>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let enrollment = mfa_service::start_enrollment(user).await?;
+    Ok(Json(enrollment))
+}
+
+#[post("enrollment/confirm")]
+async fn confirm_enrollment(dto: Json<VerifyCodeDto>, request: HttpRequest) -> Result<Json<RecoveryCodesDto>, ApiResponse> {
+    let VerifyCodeDto { code } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let recovery_codes = mfa_service::confirm_enrollment(code, user).await?;
+    Ok(Json(RecoveryCodesDto { recovery_codes }))
+}
+
+#[post("recovery_codes")]
+async fn regenerate_recovery_codes(dto: Json<VerifyCodeDto>, request: HttpRequest) -> Result<Json<RecoveryCodesDto>, ApiResponse> {
+    let VerifyCodeDto { code } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let recovery_codes = mfa_service::regenerate_recovery_codes(code, user).await?;
+    Ok(Json(RecoveryCodesDto { recovery_codes }))
+}
+
+#[delete("")]
+async fn disable(dto: Json<VerifyCodeDto>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let VerifyCodeDto { code } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    mfa_service::disable(code, user).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn mfa_routes(cfg: &mut ServiceConfig) {
+    cfg.service(status);
+    cfg.service(start_enrollment);
+    cfg.service(confirm_enrollment);
+    cfg.service(regenerate_recovery_codes);
+    cfg.service(disable);
+}
@@ -0,0 +1,29 @@
+use crate::schema::recovery_codes;
+use crate::user::user_model::User;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Associations, Identifiable, Insertable, Queryable, Selectable};
+use uuid::Uuid;
+
+// One-time fallback for a lost authenticator, only the hash of the code is kept
+#[derive(Identifiable, Selectable, Queryable, Insertable, Associations, Debug)]
+#[diesel(table_name = recovery_codes)]
+#[diesel(belongs_to(User))]
+pub struct RecoveryCode {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub code_hash: String,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl RecoveryCode {
+    pub fn new(user_id: Uuid, code_hash: String) -> Self {
+        RecoveryCode {
+            id: Uuid::now_v7(),
+            user_id,
+            code_hash,
+            used_at: None,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::config::db_config;
+use crate::error::ApiResponse;
+use crate::mfa::mfa_dto::{EnrollmentDto, MfaStatusDto};
+use crate::mfa::mfa_model::RecoveryCode;
+use crate::schema::{organizations, recovery_codes, user_organizations, users};
+use crate::user::user_model::User;
+use crate::util::jwt_util;
+use actix_web::http::StatusCode;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::env;
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+const STEP: u64 = 30;
+const CHALLENGE_AUDIENCE: &str = "mfa_challenge";
+
+lazy_static! {
+    static ref APPLICATION_NAME: String = env::var("APPLICATION_NAME").expect("APPLICATION_NAME must be set");
+    static ref MFA_CHALLENGE_EXPIRY: i64 = env::var("MFA_CHALLENGE_EXPIRY_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(5 * 60);
+    static ref RECOVERY_CODE_COUNT: usize = env::var("RECOVERY_CODE_COUNT").ok().and_then(|v| v.parse().ok()).unwrap_or(10);
+}
+
+pub async fn status(user: &User) -> Result<MfaStatusDto, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let recovery_codes_left = recovery_codes::table
+        .filter(recovery_codes::user_id.eq(user.id))
+        .filter(recovery_codes::used_at.is_null())
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await?;
+    Ok(MfaStatusDto { enabled: user.totp_enabled_at.is_some(), enabled_at: user.totp_enabled_at, recovery_codes_left })
+}
+
+// Starting again before confirming simply replaces the pending secret
+pub async fn start_enrollment(user: &User) -> Result<EnrollmentDto, ApiResponse> {
+    if user.totp_enabled_at.is_some() {
+        return Err(ApiResponse::new(StatusCode::CONFLICT, "Two-factor authentication is already enabled".to_string()));
+    }
+    let secret = Secret::generate_secret().to_bytes()
+        .map_err(|e| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let totp = totp(secret, user)?;
+    let mut conn = db_config::get_connection().await?;
+    diesel::update(users::table.find(user.id))
+        .filter(users::totp_enabled_at.is_null())
+        .set((
+            users::totp_secret.eq(totp.get_secret_base32()),
+            users::totp_last_step.eq(None::<i64>)))
+        .execute(&mut conn)
+        .await?;
+    Ok(EnrollmentDto { secret: totp.get_secret_base32(), otpauth_uri: totp.get_url() })
+}
+
+pub async fn confirm_enrollment(code: String, user: &User) -> Result<Vec<String>, ApiResponse> {
+    if user.totp_enabled_at.is_some() {
+        return Err(ApiResponse::new(StatusCode::CONFLICT, "Two-factor authentication is already enabled".to_string()));
+    }
+    let step = matching_step(&user_totp(user)?, None, &normalize(&code))
+        .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "Invalid two-factor code".to_string()))?;
+    let user_id = user.id;
+    let mut conn = db_config::get_connection().await?;
+    conn.transaction::<Vec<String>, ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let enabled = diesel::update(users::table.find(user_id))
+                .filter(users::totp_enabled_at.is_null())
+                .set((
+                    users::totp_enabled_at.eq(Utc::now().naive_utc()),
+                    users::totp_last_step.eq(step)))
+                .execute(conn)
+                .await?;
+            if enabled == 0 {
+                return Err(ApiResponse::new(StatusCode::CONFLICT, "Two-factor authentication is already enabled".to_string()));
+            }
+            replace_recovery_codes(user_id, conn).await
+        })
+    }).await
+}
+
+pub async fn disable(code: String, user: &User) -> Result<(), ApiResponse> {
+    if user.totp_enabled_at.is_none() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Two-factor authentication is not enabled".to_string()));
+    }
+    let mut conn = db_config::get_connection().await?;
+    if !verify(user, &code, &mut conn).await? {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Invalid two-factor code".to_string()));
+    }
+    let user_id = user.id;
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            diesel::update(users::table.find(user_id))
+                .set((
+                    users::totp_secret.eq(None::<String>),
+                    users::totp_enabled_at.eq(None::<NaiveDateTime>),
+                    users::totp_last_step.eq(None::<i64>)))
+                .execute(conn)
+                .await?;
+            diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+                .execute(conn)
+                .await?;
+            Ok(())
+        })
+    }).await
+}
+
+// Needs a code from the authenticator, a recovery code cannot be traded for a fresh set
+pub async fn regenerate_recovery_codes(code: String, user: &User) -> Result<Vec<String>, ApiResponse> {
+    if user.totp_enabled_at.is_none() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Two-factor authentication is not enabled".to_string()));
+    }
+    let mut conn = db_config::get_connection().await?;
+    if !verify_totp(user, &normalize(&code), &mut conn).await? {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Invalid two-factor code".to_string()));
+    }
+    let user_id = user.id;
+    conn.transaction::<Vec<String>, ApiResponse, _>(|conn| {
+        Box::pin(async move { replace_recovery_codes(user_id, conn).await })
+    }).await
+}
+
+// Short lived token proving the first factor, it is traded for a session once a code checks out
+pub fn issue_challenge(user: &User) -> Result<String, ApiResponse> {
+    jwt_util::issue_email_token(user.id, &user.email, CHALLENGE_AUDIENCE, *MFA_CHALLENGE_EXPIRY)
+        .map_err(|e| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn challenged_user(challenge: &str, conn: &mut AsyncPgConnection) -> Result<User, ApiResponse> {
+    let claims = jwt_util::decode_email_token(challenge, CHALLENGE_AUDIENCE)
+        .map_err(|_| ApiResponse::new(StatusCode::UNAUTHORIZED, "Login challenge is invalid or has expired".to_string()))?
+        .claims;
+    let user = users::table.find(claims.sub)
+        .first::<User>(conn)
+        .await
+        .optional()?
+        .ok_or(ApiResponse::new(StatusCode::UNAUTHORIZED, "Login challenge is invalid or has expired".to_string()))?;
+    Ok(user)
+}
+
+pub async fn verify_login_code(user: &User, code: &str, conn: &mut AsyncPgConnection) -> Result<bool, ApiResponse> {
+    // Without the check a pending enrollment secret would be accepted as a second factor
+    if user.totp_enabled_at.is_none() {
+        return Ok(false);
+    }
+    verify(user, code, conn).await
+}
+
+// Organizations the user belongs to but cannot use until two-factor authentication is enabled
+pub async fn blocked_organizations(user: &User) -> Result<Vec<Uuid>, ApiResponse> {
+    if user.totp_enabled_at.is_some() {
+        return Ok(Vec::new());
+    }
+    let mut conn = db_config::get_connection().await?;
+    let organization_ids = organizations::table
+        .inner_join(user_organizations::table)
+        .filter(user_organizations::user_id.eq(user.id))
+        .filter(organizations::require_two_factor.eq(true))
+        .select(organizations::id)
+        .load::<Uuid>(&mut conn)
+        .await?;
+    Ok(organization_ids)
+}
+
+// Accepts a code from the authenticator or an unused recovery code
+async fn verify(user: &User, code: &str, conn: &mut AsyncPgConnection) -> Result<bool, ApiResponse> {
+    let code = normalize(code);
+    if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+        return verify_totp(user, &code, conn).await;
+    }
+    let used = diesel::update(recovery_codes::table)
+        .filter(recovery_codes::user_id.eq(user.id))
+        .filter(recovery_codes::code_hash.eq(hash_recovery_code(&code)))
+        .filter(recovery_codes::used_at.is_null())
+        .set(recovery_codes::used_at.eq(Utc::now().naive_utc()))
+        .execute(conn)
+        .await?;
+    Ok(used == 1)
+}
+
+async fn verify_totp(user: &User, code: &str, conn: &mut AsyncPgConnection) -> Result<bool, ApiResponse> {
+    let Some(step) = matching_step(&user_totp(user)?, user.totp_last_step, code) else {
+        return Ok(false);
+    };
+    // Claimed in the update itself so two requests cannot both spend the same code
+    let claimed = diesel::update(users::table.find(user.id))
+        .filter(users::totp_last_step.is_null().or(users::totp_last_step.lt(step)))
+        .set(users::totp_last_step.eq(step))
+        .execute(conn)
+        .await?;
+    Ok(claimed == 1)
+}
+
+// One step either way leaves room for an authenticator whose clock drifted a little
+fn matching_step(totp: &TOTP, last_step: Option<i64>, code: &str) -> Option<i64> {
+    let current = Utc::now().timestamp() / STEP as i64;
+    (current - 1..=current + 1)
+        .filter(|step| last_step.is_none_or(|last_step| *step > last_step))
+        .find(|step| totp.check(code, *step as u64 * STEP))
+}
+
+fn user_totp(user: &User) -> Result<TOTP, ApiResponse> {
+    let secret = user.totp_secret.as_ref()
+        .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "Start two-factor enrollment first".to_string()))?;
+    let secret = Secret::Encoded(secret.clone()).to_bytes()
+        .map_err(|e| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    totp(secret, user)
+}
+
+fn totp(secret: Vec<u8>, user: &User) -> Result<TOTP, ApiResponse> {
+    TOTP::new(Algorithm::SHA1, 6, 0, STEP, secret, Some(APPLICATION_NAME.to_string()), user.email.clone())
+        .map_err(|e| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn replace_recovery_codes(user_id: Uuid, conn: &mut AsyncPgConnection) -> Result<Vec<String>, ApiResponse> {
+    let codes = (0..*RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = rand::rng()
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect::<Vec<String>>();
+    diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+        .execute(conn)
+        .await?;
+    diesel::insert_into(recovery_codes::table)
+        .values(codes.iter().map(|code| RecoveryCode::new(user_id, hash_recovery_code(code))).collect::<Vec<_>>())
+        .execute(conn)
+        .await?;
+    Ok(codes)
+}
+
+fn normalize(code: &str) -> String {
+    code.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+// Dashes and case are only there for readability
+fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.replace('-', "").to_ascii_lowercase()))
+}
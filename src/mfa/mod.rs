@@ -0,0 +1,4 @@
+pub mod mfa_handler;
+pub mod mfa_service;
+mod mfa_dto;
+pub mod mfa_model;
@@ -3,10 +3,12 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator_derive::Validate;
 use crate::organization::organization_model::OrganizationRole;
+use crate::util::validator_util::validate_organization_name;
 
 #[derive(Deserialize, Validate)]
 pub struct CreateOrganizationDTO {
     #[validate(length(min = 4, max = 255))]
+    #[validate(custom(function = "validate_organization_name"))]
     pub name: String
 }
 
@@ -29,6 +31,18 @@ pub struct DeleteUserDTO {
     pub organization_id: Uuid,
 }
 
+#[derive(Deserialize)]
+pub struct TransferOwnershipDto {
+    pub organization_id: Uuid,
+    pub user_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorRequirementDto {
+    pub organization_id: Uuid,
+    pub required: bool,
+}
+
 #[derive(Deserialize)]
 pub struct OrganizationIdDto {
     pub organization_id: Uuid
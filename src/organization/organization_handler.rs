@@ -1,12 +1,19 @@
 use actix_web::{delete, get, post, put, web::{Json, Query, ServiceConfig}, HttpMessage, HttpRequest, Responder};
+use actix_web::http::StatusCode;
+use actix_web::middleware::from_fn;
 use actix_web::web::Path;
 use uuid::Uuid;
+use validator::Validate;
 use crate::{error::ApiResponse, organization::{organization_dto::{CreateOrganizationDTO, SearchDto}, organization_service}, user::user_model::User};
-use crate::organization::organization_dto::{AddUserDTO, DeleteSecretDto, DeleteUserDTO, OrganizationIdDto, OrganizationUserRoleDto, PaginatedSecretSearchDto, SignatureDto};
+use crate::organization::organization_dto::{AddUserDTO, DeleteSecretDto, DeleteUserDTO, OrganizationIdDto, OrganizationUserRoleDto, PaginatedSecretSearchDto, SignatureDto, TransferOwnershipDto, TwoFactorRequirementDto};
+use crate::auth::auth_middleware::admin_token;
 use crate::organization::organization_model::{Organization, OrganizationSecret};
 
-#[post[""]]
+#[post("", wrap = "from_fn(admin_token)")]
 pub async fn create(dto: Json<CreateOrganizationDTO>, request: HttpRequest) -> Result<impl Responder, ApiResponse> {
+    if let Err(e) = dto.validate() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
     let CreateOrganizationDTO { name } = dto.into_inner();
     let org = organization_service::create(name, &request.extensions().get::<User>().unwrap()).await?;
     Ok(Json(org))
@@ -40,7 +47,7 @@ async fn list_users_in_organization(dto: Query<SearchDto>, organization_id: Path
     Ok(Json(users))
 }
 
-#[post("user")]
+#[post("user", wrap = "from_fn(admin_token)")]
 async fn add_user(dto: Json<AddUserDTO>, request: HttpRequest) -> Result<Json<User>, ApiResponse> {
     let AddUserDTO { user_id, organization_id, role } = dto.into_inner();
     let extensions = request.extensions();
@@ -49,7 +56,7 @@ async fn add_user(dto: Json<AddUserDTO>, request: HttpRequest) -> Result<Json<Us
     Ok(Json(added_user))
 }
 
-#[put("user")]
+#[put("user", wrap = "from_fn(admin_token)")]
 async fn update_user(dto: Json<AddUserDTO>, request: HttpRequest) -> Result<Json<User>, ApiResponse> {
     let AddUserDTO { user_id, organization_id, role } = dto.into_inner();
     let extensions = request.extensions();
@@ -58,7 +65,7 @@ async fn update_user(dto: Json<AddUserDTO>, request: HttpRequest) -> Result<Json
     Ok(Json(added_user))
 }
 
-#[delete("user")]
+#[delete("user", wrap = "from_fn(admin_token)")]
 async fn delete_user(dto: Json<DeleteUserDTO>, request: HttpRequest) -> Result<(), ApiResponse> {
     let DeleteUserDTO { user_id, organization_id } = dto.into_inner();
     let extensions = request.extensions();
@@ -67,7 +74,8 @@ async fn delete_user(dto: Json<DeleteUserDTO>, request: HttpRequest) -> Result<(
     Ok(())
 }
 
-#[get("secret")]
+// Secrets sign upload and delete URLs under /f, so even reading them needs an admin token
+#[get("secret", wrap = "from_fn(admin_token)")]
 async fn get_organization_secret(dto: Query<PaginatedSecretSearchDto>, request: HttpRequest) -> Result<Json<Vec<OrganizationSecret>>, ApiResponse> {
     let PaginatedSecretSearchDto { limit, page, organization_id } = dto.into_inner();
     let extensions = request.extensions();
@@ -76,7 +84,7 @@ async fn get_organization_secret(dto: Query<PaginatedSecretSearchDto>, request:
     Ok(Json(secrets))
 }
 
-#[post("secret")]
+#[post("secret", wrap = "from_fn(admin_token)")]
 async fn create_organization_secret(dto: Json<OrganizationIdDto>, request: HttpRequest) -> Result<Json<OrganizationSecret>, ApiResponse> {
     let OrganizationIdDto { organization_id } = dto.into_inner();
     let extensions = request.extensions();
@@ -85,7 +93,7 @@ async fn create_organization_secret(dto: Json<OrganizationIdDto>, request: HttpR
     Ok(Json(secret))
 }
 
-#[delete("secret")]
+#[delete("secret", wrap = "from_fn(admin_token)")]
 async fn delete_organization_secret(dto: Json<DeleteSecretDto>, request: HttpRequest) -> Result<(), ApiResponse> {
     let DeleteSecretDto { id, organization_id } = dto.into_inner();
     let extensions = request.extensions();
@@ -94,6 +102,33 @@ async fn delete_organization_secret(dto: Json<DeleteSecretDto>, request: HttpReq
     Ok(())
 }
 
+#[put("owner", wrap = "from_fn(admin_token)")]
+async fn transfer_ownership(dto: Json<TransferOwnershipDto>, request: HttpRequest) -> Result<(), ApiResponse> {
+    let TransferOwnershipDto { organization_id, user_id } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    organization_service::transfer_ownership(organization_id, user_id, user).await?;
+    Ok(())
+}
+
+#[put("two_factor", wrap = "from_fn(admin_token)")]
+async fn require_two_factor(dto: Json<TwoFactorRequirementDto>, request: HttpRequest) -> Result<Json<Organization>, ApiResponse> {
+    let TwoFactorRequirementDto { organization_id, required } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let organization = organization_service::require_two_factor(organization_id, required, user).await?;
+    Ok(Json(organization))
+}
+
+#[delete("{organization_id}", wrap = "from_fn(admin_token)")]
+async fn delete_organization(dto: Path<OrganizationIdDto>, request: HttpRequest) -> Result<(), ApiResponse> {
+    let OrganizationIdDto { organization_id } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    organization_service::delete_organization(organization_id, user).await?;
+    Ok(())
+}
+
 #[get("")]
 async fn get_organization_from_secret(dto: Query<SignatureDto>) -> Result<Json<Organization>, ApiResponse> {
     let SignatureDto { id, signature } = dto.into_inner();
@@ -112,6 +147,10 @@ pub fn organization_routes(cfg: &mut ServiceConfig) {
     cfg.service(delete_user);
     cfg.service(create_organization_secret);
     cfg.service(delete_organization_secret);
+    cfg.service(transfer_ownership);
+    cfg.service(require_two_factor);
+    // Registered last so it does not catch `DELETE user` and `DELETE secret`
+    cfg.service(delete_organization);
 }
 
 pub fn sdk_routes(cfg: &mut ServiceConfig) {
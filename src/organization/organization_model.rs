@@ -16,6 +16,14 @@ pub struct Organization {
     pub created_by: Uuid,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+
+    pub used_bytes: i64,
+    pub object_count: i64,
+    pub quota_bytes: Option<i64>,
+    pub soft_quota_bytes: Option<i64>,
+    pub quota_objects: Option<i64>,
+    // Members without two-factor authentication lose access until they enable it
+    pub require_two_factor: bool,
 }
 
 #[derive(Queryable, Selectable, Associations, Insertable, Debug)]
@@ -59,6 +67,12 @@ impl Organization {
             created_by,
             created_at: chrono::Utc::now().naive_utc(),
             updated_at: None,
+            used_bytes: 0,
+            object_count: 0,
+            quota_bytes: None,
+            soft_quota_bytes: None,
+            quota_objects: None,
+            require_two_factor: false,
         }
     }
 }
@@ -1,5 +1,5 @@
 use actix_web::http::StatusCode;
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use diesel::{alias, dsl, BoolExpressionMethods, ExpressionMethods, JoinOnDsl, NullableExpressionMethods, PgTextExpressionMethods, QueryDsl, SelectableHelper};
 use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
 use hmac::{Hmac, Mac};
@@ -12,9 +12,12 @@ use crate::{
 use crate::folder::folder_service::EDITABLE_ROLES;
 use crate::organization::organization_dto::{OrganizationUserRoleDto, UserDto};
 use crate::organization::organization_model::OrganizationSecret;
-use crate::schema::{organization_secrets, users};
+use crate::schema::{buckets, organization_secrets, users};
+use crate::token::token_service;
+use crate::user::user_service;
 
 pub async fn create(name: String, user: &User) -> Result<Organization, ApiResponse> {
+    user_service::require_verified(user)?;
     let org = Organization::new(name, user.id);
     let user_org = UserOrganization::new(org.id, user.id,OrganizationRole::OWNER, None);
     let mut conn = db_config::get_connection().await?;
@@ -47,6 +50,9 @@ pub async fn list_organizations(user: &User, keyword: Option<String>, limit: i64
     if let Some(cursor) = cursor {
         query = query.filter(organizations::id.gt(cursor));
     }
+    if let Some(organization_ids) = token_service::organization_limit() {
+        query = query.filter(organizations::id.eq_any(organization_ids));
+    }
     let organizations = query
         .limit(limit)
         .select(Organization::as_select())
@@ -63,7 +69,7 @@ pub async fn fetch_organization(organization_id: Uuid, _user: &User) -> Result<O
         .select((Organization::as_select(), Option::<UserOrganization>::as_select()))
         .get_result::<(Organization, Option<UserOrganization>)>(&mut conn)
         .await?;
-    if user_organization.is_none() {
+    if token_service::restrict(user_organization).is_none() {
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()));
     }
     Ok(organization)
@@ -221,7 +227,125 @@ pub async fn delete_user(user_id: Uuid, organization_id: Uuid, user: &User) -> R
     Ok(())
 }
 
+pub async fn transfer_ownership(organization_id: Uuid, new_owner_id: Uuid, user: &User) -> Result<(), ApiResponse> {
+    if new_owner_id == user.id {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "You already own this organization".to_string()));
+    }
+    let mut conn = db_config::get_connection().await?;
+    let (_, user_organization) = validate_access(organization_id, user.id, &mut conn).await?;
+    if user_organization.map(|user_organization| user_organization.role) != Some(OrganizationRole::OWNER) {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Only an owner can transfer the organization".to_string()));
+    }
+    let user_id = user.id;
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let promoted = diesel::update(user_organizations::table)
+                .filter(user_organizations::organization_id.eq(organization_id))
+                .filter(user_organizations::user_id.eq(new_owner_id))
+                .set(user_organizations::role.eq(OrganizationRole::OWNER))
+                .execute(conn)
+                .await?;
+            if promoted == 0 {
+                return Err(ApiResponse::new(StatusCode::FORBIDDEN, "User is not a part of this organization".to_string()));
+            }
+            // The previous owner stays on as an admin
+            diesel::update(user_organizations::table)
+                .filter(user_organizations::organization_id.eq(organization_id))
+                .filter(user_organizations::user_id.eq(user_id))
+                .set(user_organizations::role.eq(OrganizationRole::ADMIN))
+                .execute(conn)
+                .await?;
+            Ok(())
+        })
+    }).await
+}
+
+// Buckets have to be deleted first so no stored object is dropped by accident
+pub async fn delete_organization(organization_id: Uuid, user: &User) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let (organization, user_organization) = validate_access(organization_id, user.id, &mut conn).await?;
+    if user_organization.map(|user_organization| user_organization.role) != Some(OrganizationRole::OWNER) {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Only an owner can delete the organization".to_string()));
+    }
+    let bucket_count = buckets::table
+        .filter(buckets::organization_id.eq(organization_id))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await?;
+    if bucket_count > 0 {
+        return Err(ApiResponse::new(StatusCode::CONFLICT, format!("Organization still has {bucket_count} bucket(s), delete them first")));
+    }
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            diesel::delete(organization_secrets::table.filter(organization_secrets::organization_id.eq(organization_id)))
+                .execute(conn)
+                .await?;
+            diesel::delete(user_organizations::table.filter(user_organizations::organization_id.eq(organization_id)))
+                .execute(conn)
+                .await?;
+            diesel::delete(organizations::table.find(organization_id))
+                .execute(conn)
+                .await?;
+            Ok(())
+        })
+    }).await?;
+    remove_organization_dir(&organization.name).await;
+    Ok(())
+}
+
+// Names are validated on create, the containment check also covers rows created before that
+async fn remove_organization_dir(name: &str) {
+    let (Ok(root), Ok(path)) = (tokio::fs::canonicalize("files").await, tokio::fs::canonicalize(format!("files/{name}")).await) else {
+        return;
+    };
+    if path == root || !path.starts_with(&root) {
+        error!("Refusing to remove {} for organization {name}, it is outside of {}", path.display(), root.display());
+        return;
+    }
+    if let Err(e) = tokio::fs::remove_dir_all(&path).await {
+        error!("Failed to remove {} for organization {name}: {e}", path.display());
+    }
+}
+
+pub async fn require_two_factor(organization_id: Uuid, required: bool, user: &User) -> Result<Organization, ApiResponse> {
+    // Otherwise the owner would lock themselves out right away
+    if required && user.totp_enabled_at.is_none() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Enable two-factor authentication for your own account first".to_string()));
+    }
+    let mut conn = db_config::get_connection().await?;
+    let (_, user_organization) = validate_access(organization_id, user.id, &mut conn).await?;
+    if user_organization.map(|user_organization| user_organization.role) != Some(OrganizationRole::OWNER) {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Only an owner can change the two-factor requirement".to_string()));
+    }
+    let organization = diesel::update(organizations::table.find(organization_id))
+        .set((
+            organizations::require_two_factor.eq(required),
+            organizations::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<Organization>(&mut conn)
+        .await?;
+    Ok(organization)
+}
+
+// Organizations that would be left without an owner if `user_id` went away
+pub async fn sole_owned_organizations(user_id: Uuid, conn: &mut AsyncPgConnection) -> Result<Vec<Organization>, ApiResponse> {
+    let other_owners = alias!(user_organizations as other_owners);
+    let organizations = organizations::table
+        .inner_join(user_organizations::table)
+        .filter(user_organizations::user_id.eq(user_id))
+        .filter(user_organizations::role.eq(OrganizationRole::OWNER))
+        .filter(dsl::not(dsl::exists(other_owners
+            .filter(other_owners.field(user_organizations::organization_id).eq(organizations::id))
+            .filter(other_owners.field(user_organizations::role).eq(OrganizationRole::OWNER))
+            .filter(other_owners.field(user_organizations::user_id).ne(user_id)))))
+        .select(Organization::as_select())
+        .order(organizations::name.asc())
+        .load::<Organization>(conn)
+        .await?;
+    Ok(organizations)
+}
+
 pub async fn create_organization_secret(organization_id: Uuid, user: &User) -> Result<OrganizationSecret, ApiResponse> {
+    user_service::require_verified(user)?;
     let mut conn = db_config::get_connection().await?;
     let (_, user_organization) = validate_access(organization_id ,user.id, &mut conn).await?;
     if let Some(user_organization) = user_organization {
@@ -294,6 +418,7 @@ pub async fn validate_access(organization_id: Uuid, user_id: Uuid, conn: &mut As
         .select((Organization::as_select(), Option::<UserOrganization>::as_select()))
         .first::<(Organization, Option<UserOrganization>)>(conn)
         .await?;
+    let user_organization = token_service::restrict(user_organization);
 
     if user_organization.is_none() {
         return Err(ApiResponse::new(StatusCode::FORBIDDEN, "User is not a part of this organization".to_string()));
@@ -0,0 +1,4 @@
+pub mod replication_handler;
+pub mod replication_service;
+mod replication_dto;
+pub mod replication_model;
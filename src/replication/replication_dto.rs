@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::replication::replication_model::ReplicationStatus;
+
+#[derive(Deserialize)]
+pub struct CreateReplicationDto {
+    pub source_bucket_id: Uuid,
+    pub destination_bucket_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateReplicationDto {
+    pub replication_id: Uuid,
+    pub enabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ReplicationIdDto {
+    pub replication_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct SearchReplicationTaskDto {
+    pub status: Option<ReplicationStatus>,
+    pub limit: i64,
+    pub cursor: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct ReplicationCountDto {
+    pub count: usize,
+}
@@ -0,0 +1,81 @@
+use crate::error::ApiResponse;
+use crate::replication::replication_dto::{CreateReplicationDto, ReplicationCountDto, ReplicationIdDto, SearchReplicationTaskDto, UpdateReplicationDto};
+use crate::replication::replication_model::{BucketReplication, ReplicationTask};
+use crate::replication::replication_service;
+use crate::user::user_model::User;
+use actix_web::web::{Json, Path, Query, ServiceConfig};
+use actix_web::{delete, get, post, put, HttpMessage, HttpRequest};
+use uuid::Uuid;
+
+#[get("bucket/{bucket_id}")]
+async fn list(bucket_id: Path<Uuid>, request: HttpRequest) -> Result<Json<Vec<BucketReplication>>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let replications = replication_service::list(bucket_id.into_inner(), user).await?;
+    Ok(Json(replications))
+}
+
+#[post("")]
+async fn create(dto: Json<CreateReplicationDto>, request: HttpRequest) -> Result<Json<BucketReplication>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let CreateReplicationDto { source_bucket_id, destination_bucket_id } = dto.into_inner();
+    let replication = replication_service::create(source_bucket_id, destination_bucket_id, user).await?;
+    Ok(Json(replication))
+}
+
+#[put("")]
+async fn update(dto: Json<UpdateReplicationDto>, request: HttpRequest) -> Result<Json<BucketReplication>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let UpdateReplicationDto { replication_id, enabled } = dto.into_inner();
+    let replication = replication_service::update(replication_id, enabled, user).await?;
+    Ok(Json(replication))
+}
+
+#[delete("{replication_id}")]
+async fn delete(dto: Path<ReplicationIdDto>, request: HttpRequest) -> Result<Json<BucketReplication>, ApiResponse> {
+    let ReplicationIdDto { replication_id } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let replication = replication_service::delete(replication_id, user).await?;
+    Ok(Json(replication))
+}
+
+#[post("{replication_id}/backfill")]
+async fn backfill(dto: Path<ReplicationIdDto>, request: HttpRequest) -> Result<Json<ReplicationCountDto>, ApiResponse> {
+    let ReplicationIdDto { replication_id } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let count = replication_service::backfill(replication_id, user).await?;
+    Ok(Json(ReplicationCountDto { count }))
+}
+
+#[post("{replication_id}/retry")]
+async fn retry(dto: Path<ReplicationIdDto>, request: HttpRequest) -> Result<Json<ReplicationCountDto>, ApiResponse> {
+    let ReplicationIdDto { replication_id } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let count = replication_service::retry(replication_id, user).await?;
+    Ok(Json(ReplicationCountDto { count }))
+}
+
+#[get("{replication_id}/tasks")]
+async fn tasks(dto: Path<ReplicationIdDto>, query: Query<SearchReplicationTaskDto>, request: HttpRequest) -> Result<Json<Vec<ReplicationTask>>, ApiResponse> {
+    let ReplicationIdDto { replication_id } = dto.into_inner();
+    let SearchReplicationTaskDto { status, limit, cursor } = query.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let tasks = replication_service::tasks(replication_id, status, limit, cursor, user).await?;
+    Ok(Json(tasks))
+}
+
+pub fn replication_routes(cfg: &mut ServiceConfig) {
+    cfg.service(list);
+    cfg.service(create);
+    cfg.service(update);
+    cfg.service(delete);
+    cfg.service(backfill);
+    cfg.service(retry);
+    cfg.service(tasks);
+}
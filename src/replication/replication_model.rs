@@ -0,0 +1,86 @@
+use crate::schema::{bucket_replications, replication_tasks};
+use crate::user::user_model::User;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Associations, Identifiable, Insertable, Queryable, QueryableByName, Selectable};
+use diesel_derive_enum::DbEnum;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Insertable, Identifiable, Selectable, Queryable, Associations, Serialize, Debug)]
+#[diesel(belongs_to(User, foreign_key = created_by))]
+#[diesel(table_name = bucket_replications)]
+pub struct BucketReplication {
+    pub id: Uuid,
+    pub source_bucket_id: Uuid,
+    pub destination_bucket_id: Uuid,
+    pub enabled: bool,
+    pub created_by: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl BucketReplication {
+    pub fn new(source_bucket_id: Uuid, destination_bucket_id: Uuid, user_id: Uuid) -> Self {
+        BucketReplication {
+            id: Uuid::now_v7(),
+            source_bucket_id,
+            destination_bucket_id,
+            enabled: true,
+            created_by: user_id,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }
+    }
+}
+
+// One queued change to apply to a destination bucket; `path` is relative to the bucket root
+#[derive(QueryableByName, Insertable, Identifiable, Selectable, Queryable, Associations, Serialize, Debug)]
+#[diesel(belongs_to(BucketReplication, foreign_key = replication_id))]
+#[diesel(table_name = replication_tasks)]
+pub struct ReplicationTask {
+    pub id: Uuid,
+    pub replication_id: Uuid,
+    pub file_id: Option<Uuid>,
+    pub path: String,
+    pub operation: ReplicationOperation,
+    pub status: ReplicationStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl ReplicationTask {
+    pub fn new(replication_id: Uuid, file_id: Option<Uuid>, path: String, operation: ReplicationOperation) -> Self {
+        let now = Utc::now().naive_utc();
+        ReplicationTask {
+            id: Uuid::now_v7(),
+            replication_id,
+            file_id,
+            path,
+            operation,
+            status: ReplicationStatus::PENDING,
+            attempts: 0,
+            last_error: None,
+            next_attempt_at: now,
+            created_at: now,
+            updated_at: None,
+        }
+    }
+}
+
+#[derive(DbEnum, Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[db_enum(existing_type_path = "crate::schema::sql_types::ReplicationStatus")]
+pub enum ReplicationStatus {
+    PENDING,
+    COMPLETED,
+    FAILED,
+}
+
+#[derive(DbEnum, Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[db_enum(existing_type_path = "crate::schema::sql_types::ReplicationOperation")]
+pub enum ReplicationOperation {
+    PUT,
+    DELETE,
+}
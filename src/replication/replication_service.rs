@@ -0,0 +1,407 @@
+use crate::bucket::bucket_model::Bucket;
+use crate::config::db_config;
+use crate::error::ApiResponse;
+use crate::file::file_model::File;
+use crate::file::file_service::{self, PutOptions};
+use crate::metadata::metadata_service;
+use crate::organization::organization_model::{Organization, OrganizationRole, UserOrganization};
+use crate::replication::replication_model::{BucketReplication, ReplicationOperation, ReplicationStatus, ReplicationTask};
+use crate::schema::{bucket_replications, buckets, files, organizations, replication_tasks, user_organizations};
+use crate::token::token_service;
+use crate::user::user_model::User;
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use chrono::{Duration, Utc};
+use diesel::sql_types::BigInt;
+use diesel::{sql_query, BoolExpressionMethods, ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, QueryableByName, SelectableHelper};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use std::env;
+use uuid::Uuid;
+
+lazy_static! {
+    static ref REPLICATION_ROLES: [OrganizationRole; 2] = [OrganizationRole::OWNER, OrganizationRole::ADMIN];
+    static ref REPLICATION_INTERVAL: u64 = env::var("REPLICATION_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(10);
+    static ref REPLICATION_BATCH_SIZE: i64 = env::var("REPLICATION_BATCH_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(100);
+}
+
+const MAX_ATTEMPTS: i32 = 8;
+const RETRY_DELAY_SECS: i64 = 30;
+// Rows per insert, well below the bind parameter limit of a single statement
+const INSERT_CHUNK_SIZE: usize = 1000;
+
+#[derive(QueryableByName)]
+struct ObjectPath {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    file_id: Uuid,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    path: String,
+}
+
+// Paths of every file in a bucket, or only below `folder_id` when it is given
+const OBJECT_PATHS: &str = r#"
+    WITH RECURSIVE paths AS (
+        SELECT id, ''::TEXT AS path FROM folders WHERE bucket_id = $1 AND parent_id IS NULL
+        UNION ALL
+        SELECT f.id, p.path || f.name || '/' FROM folders f
+        INNER JOIN paths p ON f.parent_id = p.id
+    ),
+    subtree AS (
+        SELECT id FROM folders WHERE id = $2
+        UNION ALL
+        SELECT f.id FROM folders f
+        INNER JOIN subtree s ON f.parent_id = s.id
+    )
+    SELECT files.id AS file_id, paths.path || files.name AS path
+    FROM files
+    INNER JOIN paths ON paths.id = files.folder_id
+    WHERE $2 IS NULL OR files.folder_id IN (SELECT id FROM subtree)
+"#;
+
+pub async fn list(bucket_id: Uuid, user: &User) -> Result<Vec<BucketReplication>, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_administered_bucket(bucket_id, user, &mut conn).await?;
+    let replications = bucket_replications::table
+        .filter(bucket_replications::source_bucket_id.eq(bucket_id).or(bucket_replications::destination_bucket_id.eq(bucket_id)))
+        .order(bucket_replications::id)
+        .select(BucketReplication::as_select())
+        .load::<BucketReplication>(&mut conn)
+        .await?;
+    Ok(replications)
+}
+
+pub async fn create(source_bucket_id: Uuid, destination_bucket_id: Uuid, user: &User) -> Result<BucketReplication, ApiResponse> {
+    if source_bucket_id == destination_bucket_id {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "A bucket cannot replicate into itself".to_string()));
+    }
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_administered_bucket(source_bucket_id, user, &mut conn).await?;
+    let _ = find_administered_bucket(destination_bucket_id, user, &mut conn).await?;
+    let replication = diesel::insert_into(bucket_replications::table)
+        .values(BucketReplication::new(source_bucket_id, destination_bucket_id, user.id))
+        .get_result::<BucketReplication>(&mut conn)
+        .await
+        .map_err(|_| ApiResponse::new(StatusCode::CONFLICT, "This replication already exists".to_string()))?;
+    Ok(replication)
+}
+
+pub async fn update(replication_id: Uuid, enabled: bool, user: &User) -> Result<BucketReplication, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_administered_replication(replication_id, user, &mut conn).await?;
+    let replication = diesel::update(bucket_replications::table.find(replication_id))
+        .set((bucket_replications::enabled.eq(enabled), bucket_replications::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<BucketReplication>(&mut conn)
+        .await?;
+    Ok(replication)
+}
+
+pub async fn delete(replication_id: Uuid, user: &User) -> Result<BucketReplication, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_administered_replication(replication_id, user, &mut conn).await?;
+    let replication = diesel::delete(bucket_replications::table.find(replication_id))
+        .get_result::<BucketReplication>(&mut conn)
+        .await?;
+    Ok(replication)
+}
+
+// Queues a copy of every object already in the source bucket
+pub async fn backfill(replication_id: Uuid, user: &User) -> Result<usize, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let replication = find_administered_replication(replication_id, user, &mut conn).await?;
+    // Tasks are built here rather than by the database, the worker orders them by their v7 ids
+    let tasks: Vec<ReplicationTask> = object_paths(replication.source_bucket_id, None, &mut conn).await?
+        .into_iter()
+        .map(|object| ReplicationTask::new(replication.id, Some(object.file_id), object.path, ReplicationOperation::PUT))
+        .collect();
+    let count = tasks.len();
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            for chunk in tasks.chunks(INSERT_CHUNK_SIZE) {
+                diesel::insert_into(replication_tasks::table)
+                    .values(chunk)
+                    .execute(conn)
+                    .await?;
+                let file_ids: Vec<Uuid> = chunk.iter().filter_map(|task| task.file_id).collect();
+                diesel::update(files::table.filter(files::id.eq_any(file_ids)))
+                    .set(files::replication_status.eq(ReplicationStatus::PENDING))
+                    .execute(conn)
+                    .await?;
+            }
+            Ok(())
+        })
+    }).await?;
+    info!("Queued {count} files of bucket {} for replication {}", replication.source_bucket_id, replication.id);
+    Ok(count)
+}
+
+pub async fn tasks(replication_id: Uuid, status: Option<ReplicationStatus>, limit: i64, cursor: Option<Uuid>, user: &User) -> Result<Vec<ReplicationTask>, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_administered_replication(replication_id, user, &mut conn).await?;
+    let mut query = replication_tasks::table
+        .filter(replication_tasks::replication_id.eq(replication_id))
+        .order(replication_tasks::id)
+        .into_boxed();
+    if let Some(status) = status {
+        query = query.filter(replication_tasks::status.eq(status));
+    }
+    if let Some(cursor) = cursor {
+        query = query.filter(replication_tasks::id.gt(cursor));
+    }
+    let tasks = query.limit(limit)
+        .select(ReplicationTask::as_select())
+        .load::<ReplicationTask>(&mut conn)
+        .await?;
+    Ok(tasks)
+}
+
+// Puts failed tasks back in the queue with a fresh set of attempts
+pub async fn retry(replication_id: Uuid, user: &User) -> Result<usize, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let _ = find_administered_replication(replication_id, user, &mut conn).await?;
+    let count = diesel::update(replication_tasks::table
+        .filter(replication_tasks::replication_id.eq(replication_id))
+        .filter(replication_tasks::status.eq(ReplicationStatus::FAILED)))
+        .set((
+            replication_tasks::status.eq(ReplicationStatus::PENDING),
+            replication_tasks::attempts.eq(0),
+            replication_tasks::next_attempt_at.eq(Utc::now().naive_utc()),
+            replication_tasks::updated_at.eq(Utc::now().naive_utc())))
+        .execute(&mut conn)
+        .await?;
+    Ok(count)
+}
+
+async fn find_administered_bucket(bucket_id: Uuid, user: &User, conn: &mut AsyncPgConnection) -> Result<Bucket, ApiResponse> {
+    let (bucket, user_organization) = buckets::table.find(bucket_id)
+        .left_join(user_organizations::table.on(buckets::organization_id.eq(user_organizations::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select((Bucket::as_select(), Option::<UserOrganization>::as_select()))
+        .first::<(Bucket, Option<UserOrganization>)>(conn)
+        .await?;
+    match token_service::restrict(user_organization) {
+        Some(user_organization) if REPLICATION_ROLES.contains(&user_organization.role) => Ok(bucket),
+        _ => Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to manage replication of this bucket".to_string()))
+    }
+}
+
+async fn find_administered_replication(replication_id: Uuid, user: &User, conn: &mut AsyncPgConnection) -> Result<BucketReplication, ApiResponse> {
+    let replication = bucket_replications::table.find(replication_id)
+        .select(BucketReplication::as_select())
+        .first::<BucketReplication>(conn)
+        .await
+        .map_err(|_| ApiResponse::new(StatusCode::NOT_FOUND, "Replication not found".to_string()))?;
+    let _ = find_administered_bucket(replication.source_bucket_id, user, conn).await?;
+    Ok(replication)
+}
+
+// Queues a create or overwrite of `path` for every enabled replication of the bucket.
+// Must run inside the transaction that writes the file row.
+pub async fn enqueue_put(bucket_id: Uuid, file_id: Uuid, path: &str, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let queued = enqueue(bucket_id, Some(file_id), path, ReplicationOperation::PUT, conn).await?;
+    if queued > 0 {
+        diesel::update(files::table.find(file_id))
+            .set(files::replication_status.eq(ReplicationStatus::PENDING))
+            .execute(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+pub async fn enqueue_delete(bucket_id: Uuid, path: &str, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let _ = enqueue(bucket_id, None, path, ReplicationOperation::DELETE, conn).await?;
+    Ok(())
+}
+
+// Queues a delete for every file below a folder, before the folder is removed
+pub async fn enqueue_folder_delete(bucket_id: Uuid, folder_id: Uuid, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let replication_ids = bucket_replications::table
+        .filter(bucket_replications::source_bucket_id.eq(bucket_id))
+        .filter(bucket_replications::enabled.eq(true))
+        .select(bucket_replications::id)
+        .load::<Uuid>(conn)
+        .await?;
+    if replication_ids.is_empty() {
+        return Ok(());
+    }
+    let objects = object_paths(bucket_id, Some(folder_id), conn).await?;
+    let tasks: Vec<ReplicationTask> = replication_ids.iter()
+        .flat_map(|replication_id| objects.iter()
+            .map(|object| ReplicationTask::new(*replication_id, None, object.path.clone(), ReplicationOperation::DELETE)))
+        .collect();
+    for chunk in tasks.chunks(INSERT_CHUNK_SIZE) {
+        diesel::insert_into(replication_tasks::table)
+            .values(chunk)
+            .execute(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn object_paths(bucket_id: Uuid, folder_id: Option<Uuid>, conn: &mut AsyncPgConnection) -> Result<Vec<ObjectPath>, ApiResponse> {
+    let objects = sql_query(OBJECT_PATHS)
+        .bind::<diesel::sql_types::Uuid, _>(bucket_id)
+        .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(folder_id)
+        .load::<ObjectPath>(conn)
+        .await?;
+    Ok(objects)
+}
+
+async fn enqueue(bucket_id: Uuid, file_id: Option<Uuid>, path: &str, operation: ReplicationOperation, conn: &mut AsyncPgConnection) -> Result<usize, ApiResponse> {
+    let replication_ids = bucket_replications::table
+        .filter(bucket_replications::source_bucket_id.eq(bucket_id))
+        .filter(bucket_replications::enabled.eq(true))
+        .select(bucket_replications::id)
+        .load::<Uuid>(conn)
+        .await?;
+    if replication_ids.is_empty() {
+        return Ok(0);
+    }
+    let tasks: Vec<ReplicationTask> = replication_ids.into_iter()
+        .map(|replication_id| ReplicationTask::new(replication_id, file_id, path.to_string(), operation))
+        .collect();
+    let queued = diesel::insert_into(replication_tasks::table)
+        .values(tasks)
+        .execute(conn)
+        .await?;
+    Ok(queued)
+}
+
+// Applies queued tasks every REPLICATION_INTERVAL_SECS for as long as the server is up
+pub fn start_worker() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(*REPLICATION_INTERVAL));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_tasks().await {
+                error!("Replication worker failed: {e}");
+            }
+        }
+    });
+}
+
+async fn run_tasks() -> Result<(), ApiResponse> {
+    loop {
+        let mut conn = db_config::get_connection().await?;
+        // A task waits for older pending tasks on the same path so changes land in order
+        let query = r#"
+        SELECT * FROM replication_tasks t
+        WHERE t.status = 'pending' AND t.next_attempt_at <= now()
+          AND NOT EXISTS (
+            SELECT 1 FROM replication_tasks older
+            WHERE older.replication_id = t.replication_id AND older.path = t.path
+              AND older.status = 'pending' AND older.id < t.id
+          )
+        ORDER BY t.id
+        LIMIT $1
+"#;
+        let tasks = sql_query(query)
+            .bind::<BigInt, _>(*REPLICATION_BATCH_SIZE)
+            .load::<ReplicationTask>(&mut conn)
+            .await?;
+        drop(conn);
+
+        let batch_len = tasks.len() as i64;
+        for task in tasks {
+            let result = run_task(&task).await;
+            finish_task(&task, result).await?;
+        }
+        if batch_len < *REPLICATION_BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+async fn run_task(task: &ReplicationTask) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let replication = bucket_replications::table.find(task.replication_id)
+        .select(BucketReplication::as_select())
+        .first::<BucketReplication>(&mut conn)
+        .await?;
+    let (destination, destination_organization) = find_bucket_and_organization(replication.destination_bucket_id, &mut conn).await?;
+
+    match task.operation {
+        ReplicationOperation::PUT => {
+            let file = match task.file_id {
+                Some(file_id) => files::table.find(file_id)
+                    .select(File::as_select())
+                    .first::<File>(&mut conn)
+                    .await
+                    .optional()?,
+                None => None,
+            };
+            // The file was deleted after this task was queued, its delete task follows
+            let Some(file) = file else {
+                return Ok(());
+            };
+            let (source, source_organization) = find_bucket_and_organization(replication.source_bucket_id, &mut conn).await?;
+            let metadata = metadata_service::file_metadata(&[file.id], &mut conn).await?.remove(&file.id).unwrap_or_default();
+            drop(conn);
+            let path = format!("files/{}/{}/{}", source_organization.name, source.name, task.path);
+            let body = file_service::read_object(&path, &source, &file).await?;
+            let options = PutOptions { metadata, ..PutOptions::default() };
+            file_service::put_object(&destination_organization.name, &destination, &task.path, Bytes::from(body), replication.created_by, options).await
+        }
+        ReplicationOperation::DELETE => {
+            drop(conn);
+            let _ = file_service::delete_object(&destination_organization.name, &destination, &task.path, false).await?;
+            Ok(())
+        }
+    }
+}
+
+async fn finish_task(task: &ReplicationTask, result: Result<(), ApiResponse>) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    match result {
+        Ok(()) => {
+            diesel::delete(replication_tasks::table.find(task.id)).execute(&mut conn).await?;
+            if let Some(file_id) = task.file_id {
+                let outstanding = replication_tasks::table
+                    .filter(replication_tasks::file_id.eq(file_id))
+                    .select(replication_tasks::status)
+                    .load::<ReplicationStatus>(&mut conn)
+                    .await?;
+                let status = if outstanding.contains(&ReplicationStatus::FAILED) {
+                    ReplicationStatus::FAILED
+                } else if outstanding.is_empty() {
+                    ReplicationStatus::COMPLETED
+                } else {
+                    ReplicationStatus::PENDING
+                };
+                diesel::update(files::table.find(file_id))
+                    .set(files::replication_status.eq(status))
+                    .execute(&mut conn)
+                    .await?;
+            }
+        }
+        Err(e) => {
+            let attempts = task.attempts + 1;
+            let status = if attempts >= MAX_ATTEMPTS { ReplicationStatus::FAILED } else { ReplicationStatus::PENDING };
+            let next_attempt_at = Utc::now().naive_utc() + Duration::seconds(RETRY_DELAY_SECS << task.attempts.min(10));
+            warn!("Replication of {} ({:?}) failed on attempt {attempts}: {e}", task.path, task.operation);
+            diesel::update(replication_tasks::table.find(task.id))
+                .set((
+                    replication_tasks::status.eq(status),
+                    replication_tasks::attempts.eq(attempts),
+                    replication_tasks::last_error.eq(e.to_string()),
+                    replication_tasks::next_attempt_at.eq(next_attempt_at),
+                    replication_tasks::updated_at.eq(Utc::now().naive_utc())))
+                .execute(&mut conn)
+                .await?;
+            if let (ReplicationStatus::FAILED, Some(file_id)) = (status, task.file_id) {
+                diesel::update(files::table.find(file_id))
+                    .set(files::replication_status.eq(ReplicationStatus::FAILED))
+                    .execute(&mut conn)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn find_bucket_and_organization(bucket_id: Uuid, conn: &mut AsyncPgConnection) -> Result<(Bucket, Organization), ApiResponse> {
+    let result = buckets::table.find(bucket_id)
+        .inner_join(organizations::table.on(organizations::id.eq(buckets::organization_id)))
+        .select((Bucket::as_select(), Organization::as_select()))
+        .first::<(Bucket, Organization)>(conn)
+        .await?;
+    Ok(result)
+}
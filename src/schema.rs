@@ -5,9 +5,41 @@ pub mod sql_types {
     #[diesel(postgres_type(name = "bucket_visibility"))]
     pub struct BucketVisibility;
 
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "extraction_status"))]
+    pub struct ExtractionStatus;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "lifecycle_action"))]
+    pub struct LifecycleAction;
+
     #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "organization_role"))]
     pub struct OrganizationRole;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "replication_operation"))]
+    pub struct ReplicationOperation;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "replication_status"))]
+    pub struct ReplicationStatus;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "security_event_kind"))]
+    pub struct SecurityEventKind;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "storage_tier"))]
+    pub struct StorageTier;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "token_scope"))]
+    pub struct TokenScope;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
 }
 
 diesel::table! {
@@ -23,10 +55,89 @@ diesel::table! {
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
         visibility -> BucketVisibility,
+        encrypted -> Bool,
+        encryption_key -> Nullable<Bytea>,
+        used_bytes -> Int8,
+        object_count -> Int8,
+        quota_bytes -> Nullable<Int8>,
+        soft_quota_bytes -> Nullable<Int8>,
+        quota_objects -> Nullable<Int8>,
+        cors_rules -> Jsonb,
+        website -> Nullable<Jsonb>,
+        #[max_length = 255]
+        cache_control -> Nullable<Varchar>,
+        #[max_length = 511]
+        content_disposition -> Nullable<Varchar>,
+        #[max_length = 255]
+        content_type -> Nullable<Varchar>,
     }
 }
 
 diesel::table! {
+    bucket_domains (id) {
+        id -> Uuid,
+        bucket_id -> Uuid,
+        #[max_length = 253]
+        hostname -> Varchar,
+        #[max_length = 32]
+        verification_token -> Bpchar,
+        verified_at -> Nullable<Timestamp>,
+        created_by -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    bucket_replications (id) {
+        id -> Uuid,
+        source_bucket_id -> Uuid,
+        destination_bucket_id -> Uuid,
+        enabled -> Bool,
+        created_by -> Uuid,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    bucket_usage_history (bucket_id, day) {
+        bucket_id -> Uuid,
+        day -> Date,
+        used_bytes -> Int8,
+        object_count -> Int8,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ExtractionStatus;
+    use super::sql_types::Tsvector;
+
+    file_contents (file_id) {
+        file_id -> Uuid,
+        status -> ExtractionStatus,
+        content -> Nullable<Text>,
+        content_tsv -> Nullable<Tsvector>,
+        attempts -> Int4,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    file_metadata (file_id, key) {
+        file_id -> Uuid,
+        #[max_length = 128]
+        key -> Varchar,
+        #[max_length = 1024]
+        value -> Varchar,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::StorageTier;
+    use super::sql_types::ReplicationStatus;
+
     files (id) {
         id -> Uuid,
         #[max_length = 255]
@@ -35,6 +146,30 @@ diesel::table! {
         created_by -> Uuid,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        encryption_key -> Nullable<Bytea>,
+        #[max_length = 64]
+        customer_key_fingerprint -> Nullable<Bpchar>,
+        size -> Int8,
+        storage_tier -> StorageTier,
+        replication_status -> Nullable<ReplicationStatus>,
+        #[max_length = 255]
+        cache_control -> Nullable<Varchar>,
+        #[max_length = 511]
+        content_disposition -> Nullable<Varchar>,
+        #[max_length = 255]
+        content_type -> Nullable<Varchar>,
+        #[max_length = 64]
+        etag -> Nullable<Bpchar>,
+    }
+}
+
+diesel::table! {
+    folder_metadata (folder_id, key) {
+        folder_id -> Uuid,
+        #[max_length = 128]
+        key -> Varchar,
+        #[max_length = 1024]
+        value -> Varchar,
     }
 }
 
@@ -51,6 +186,35 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::LifecycleAction;
+
+    lifecycle_rules (id) {
+        id -> Uuid,
+        bucket_id -> Uuid,
+        #[max_length = 1024]
+        prefix -> Varchar,
+        action -> LifecycleAction,
+        age_days -> Int4,
+        enabled -> Bool,
+        created_by -> Uuid,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+        last_run_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    login_attempts (subject) {
+        #[max_length = 320]
+        subject -> Varchar,
+        failures -> Int4,
+        locked_until -> Nullable<Timestamp>,
+        last_failure_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     organization_secrets (id) {
         #[max_length = 16]
@@ -63,6 +227,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    organization_usage_history (organization_id, day) {
+        organization_id -> Uuid,
+        day -> Date,
+        used_bytes -> Int8,
+        object_count -> Int8,
+    }
+}
+
 diesel::table! {
     organizations (id) {
         id -> Uuid,
@@ -71,6 +244,110 @@ diesel::table! {
         created_by -> Uuid,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        used_bytes -> Int8,
+        object_count -> Int8,
+        quota_bytes -> Nullable<Int8>,
+        soft_quota_bytes -> Nullable<Int8>,
+        quota_objects -> Nullable<Int8>,
+        require_two_factor -> Bool,
+    }
+}
+
+diesel::table! {
+    password_resets (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 64]
+        token_hash -> Bpchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::TokenScope;
+
+    personal_access_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 255]
+        name -> Varchar,
+        #[max_length = 16]
+        token_prefix -> Varchar,
+        #[max_length = 64]
+        token_hash -> Bpchar,
+        scope -> TokenScope,
+        organization_ids -> Nullable<Array<Uuid>>,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        #[max_length = 64]
+        last_used_ip -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        #[max_length = 64]
+        code_hash -> Bpchar,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ReplicationOperation;
+    use super::sql_types::ReplicationStatus;
+
+    replication_tasks (id) {
+        id -> Uuid,
+        replication_id -> Uuid,
+        file_id -> Nullable<Uuid>,
+        path -> Text,
+        operation -> ReplicationOperation,
+        status -> ReplicationStatus,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    revoked_tokens (jti) {
+        jti -> Uuid,
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    rotated_refresh_tokens (jti) {
+        jti -> Uuid,
+        session_id -> Uuid,
+        rotated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::SecurityEventKind;
+
+    security_events (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        kind -> SecurityEventKind,
+        session_id -> Nullable<Uuid>,
+        #[max_length = 511]
+        user_agent -> Nullable<Varchar>,
+        #[max_length = 64]
+        ip_address -> Nullable<Varchar>,
+        created_at -> Timestamp,
     }
 }
 
@@ -94,6 +371,11 @@ diesel::table! {
         user_id -> Uuid,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        #[max_length = 511]
+        user_agent -> Nullable<Varchar>,
+        #[max_length = 64]
+        ip_address -> Nullable<Varchar>,
+        last_used_at -> Timestamp,
     }
 }
 
@@ -113,26 +395,67 @@ diesel::table! {
         updated_at -> Nullable<Timestamp>,
         #[max_length = 511]
         image -> Nullable<Varchar>,
+        verification_sent_at -> Nullable<Timestamp>,
+        #[max_length = 255]
+        pending_email -> Nullable<Varchar>,
+        username_changed_at -> Nullable<Timestamp>,
+        deletion_scheduled_at -> Nullable<Timestamp>,
+        #[max_length = 64]
+        totp_secret -> Nullable<Varchar>,
+        totp_enabled_at -> Nullable<Timestamp>,
+        totp_last_step -> Nullable<Int8>,
     }
 }
 
+diesel::joinable!(bucket_domains -> buckets (bucket_id));
+diesel::joinable!(bucket_domains -> users (created_by));
+diesel::joinable!(bucket_replications -> users (created_by));
+diesel::joinable!(bucket_usage_history -> buckets (bucket_id));
 diesel::joinable!(buckets -> users (created_by));
+diesel::joinable!(file_contents -> files (file_id));
+diesel::joinable!(file_metadata -> files (file_id));
 diesel::joinable!(files -> folders (folder_id));
 diesel::joinable!(files -> users (created_by));
+diesel::joinable!(folder_metadata -> folders (folder_id));
 diesel::joinable!(folders -> buckets (bucket_id));
 diesel::joinable!(folders -> users (created_by));
+diesel::joinable!(lifecycle_rules -> buckets (bucket_id));
+diesel::joinable!(lifecycle_rules -> users (created_by));
 diesel::joinable!(organization_secrets -> organizations (organization_id));
 diesel::joinable!(organization_secrets -> users (created_by));
+diesel::joinable!(organization_usage_history -> organizations (organization_id));
 diesel::joinable!(organizations -> users (created_by));
+diesel::joinable!(password_resets -> users (user_id));
+diesel::joinable!(personal_access_tokens -> users (user_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(replication_tasks -> bucket_replications (replication_id));
+diesel::joinable!(rotated_refresh_tokens -> user_session (session_id));
+diesel::joinable!(security_events -> users (user_id));
 diesel::joinable!(user_organizations -> organizations (organization_id));
 diesel::joinable!(user_session -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    bucket_domains,
+    bucket_replications,
+    bucket_usage_history,
     buckets,
+    file_contents,
+    file_metadata,
     files,
+    folder_metadata,
     folders,
+    lifecycle_rules,
+    login_attempts,
     organization_secrets,
+    organization_usage_history,
     organizations,
+    password_resets,
+    personal_access_tokens,
+    recovery_codes,
+    replication_tasks,
+    revoked_tokens,
+    rotated_refresh_tokens,
+    security_events,
     user_organizations,
     user_session,
     users,
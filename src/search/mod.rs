@@ -0,0 +1,5 @@
+pub mod search_handler;
+pub mod search_service;
+mod search_dto;
+mod search_extractor;
+pub mod search_model;
@@ -0,0 +1,43 @@
+use crate::search::search_model::{EntryKind, NameHit, NameSort, SortOrder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct SearchContentDto {
+    pub q: String,
+    pub organization_id: Option<Uuid>,
+    pub bucket_id: Option<Uuid>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct SearchNameDto {
+    pub q: String,
+    pub organization_id: Option<Uuid>,
+    pub bucket_id: Option<Uuid>,
+    pub kind: Option<EntryKind>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub created_by: Option<Uuid>,
+    // Exact content type, or a whole family such as `image/*`
+    pub content_type: Option<String>,
+    #[serde(default = "default_name_sort")]
+    pub sort: NameSort,
+    pub order: Option<SortOrder>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+fn default_name_sort() -> NameSort {
+    NameSort::RELEVANCE
+}
+
+#[derive(Serialize)]
+pub struct NameSearchPage {
+    pub items: Vec<NameHit>,
+    pub next_cursor: Option<String>,
+}
@@ -0,0 +1,81 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+// tsvector values are capped at 1MB, so only the start of a large document is indexed
+const MAX_CONTENT_BYTES: usize = 512 * 1024;
+// Upper bound on a single decompressed part of an office document
+const MAX_PART_BYTES: u64 = 32 * 1024 * 1024;
+
+const TEXT_EXTENSIONS: [&str; 38] = [
+    "txt", "text", "md", "markdown", "rst", "adoc", "csv", "tsv", "log", "json", "yaml", "yml", "toml", "ini", "xml", "html", "htm", "css",
+    "rs", "py", "js", "jsx", "ts", "tsx", "go", "java", "kt", "c", "h", "cpp", "hpp", "cs", "rb", "php", "swift", "sh", "sql", "scala",
+];
+
+// Plain text of a document, None when its format cannot be read
+pub fn extract(name: &str, content_type: Option<&str>, body: &[u8]) -> Result<Option<String>, String> {
+    let extension = Path::new(name).extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+        .unwrap_or_default();
+    let text = match extension.as_str() {
+        "pdf" => pdf_extract::extract_text_from_mem(body).map_err(|e| e.to_string())?,
+        "docx" => zip_text(body, |part| part == "word/document.xml")?,
+        "pptx" => zip_text(body, |part| part.starts_with("ppt/slides/slide") && part.ends_with(".xml"))?,
+        "xlsx" => zip_text(body, |part| part == "xl/sharedStrings.xml")?,
+        "odt" | "ods" | "odp" => zip_text(body, |part| part == "content.xml")?,
+        extension if TEXT_EXTENSIONS.contains(&extension) => String::from_utf8_lossy(body).into_owned(),
+        _ if content_type.is_some_and(|content_type| content_type.starts_with("text/")) => String::from_utf8_lossy(body).into_owned(),
+        _ => return Ok(None),
+    };
+    // Control characters cannot be stored in TEXT (NUL) or would clash with the highlight markers
+    let text: String = text.chars().filter(|c| matches!(c, '\n' | '\t') || !c.is_control()).collect();
+    Ok(Some(truncate(text)))
+}
+
+// Concatenated text of the XML parts `wanted` selects, in document order
+fn zip_text(body: &[u8], wanted: impl Fn(&str) -> bool) -> Result<String, String> {
+    let mut archive = ZipArchive::new(Cursor::new(body)).map_err(|e| e.to_string())?;
+    let mut parts: Vec<String> = archive.file_names().filter(|part| wanted(part)).map(str::to_string).collect();
+    // slide2.xml comes before slide10.xml
+    parts.sort_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)));
+    let mut text = String::new();
+    for part in parts {
+        let mut xml = String::new();
+        archive.by_name(&part).map_err(|e| e.to_string())?
+            .take(MAX_PART_BYTES)
+            .read_to_string(&mut xml)
+            .map_err(|e| e.to_string())?;
+        xml_text(&xml, &mut text)?;
+        if text.len() > MAX_CONTENT_BYTES {
+            break;
+        }
+    }
+    Ok(text)
+}
+
+fn xml_text(xml: &str, text: &mut String) -> Result<(), String> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Text(e) => text.push_str(&e.unescape().map_err(|e| e.to_string())?),
+            // Paragraphs, shared strings and table cells end a run of words
+            Event::End(e) if matches!(e.local_name().as_ref(), b"p" | b"si" | b"tc" | b"table-cell") => text.push('\n'),
+            Event::Eof => return Ok(()),
+            _ => (),
+        }
+    }
+}
+
+fn truncate(mut text: String) -> String {
+    if text.len() > MAX_CONTENT_BYTES {
+        let mut end = MAX_CONTENT_BYTES;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    text
+}
@@ -0,0 +1,28 @@
+use crate::error::ApiResponse;
+use crate::search::search_dto::{NameSearchPage, SearchContentDto, SearchNameDto};
+use crate::search::search_model::SearchResult;
+use crate::search::search_service;
+use crate::user::user_model::User;
+use actix_web::web::{Json, Query, ServiceConfig};
+use actix_web::{get, HttpMessage, HttpRequest};
+
+#[get("content")]
+async fn search_content(query: Query<SearchContentDto>, request: HttpRequest) -> Result<Json<Vec<SearchResult>>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let results = search_service::search_content(query.into_inner(), user).await?;
+    Ok(Json(results))
+}
+
+#[get("names")]
+async fn search_names(query: Query<SearchNameDto>, request: HttpRequest) -> Result<Json<NameSearchPage>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let page = search_service::search_names(query.into_inner(), user).await?;
+    Ok(Json(page))
+}
+
+pub fn search_routes(cfg: &mut ServiceConfig) {
+    cfg.service(search_content)
+        .service(search_names);
+}
@@ -0,0 +1,110 @@
+use crate::file::file_model::File;
+use crate::schema::file_contents;
+use chrono::{NaiveDateTime, Utc};
+use diesel::sql_types::{BigInt, Double, Float, Nullable, Text, Timestamp, Uuid as SqlUuid};
+use diesel::{Associations, Insertable, Queryable, QueryableByName, Selectable};
+use diesel_derive_enum::DbEnum;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(DbEnum, Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[db_enum(existing_type_path = "crate::schema::sql_types::ExtractionStatus")]
+pub enum ExtractionStatus {
+    PENDING,
+    INDEXED,
+    UNSUPPORTED,
+    FAILED,
+}
+
+// Text extracted from a file, its tsvector column is generated by the database
+#[derive(Insertable, Selectable, Queryable, Associations, Debug)]
+#[diesel(belongs_to(File))]
+#[diesel(table_name = file_contents)]
+pub struct FileContent {
+    pub file_id: Uuid,
+    pub status: ExtractionStatus,
+    pub content: Option<String>,
+    pub attempts: i32,
+    pub updated_at: NaiveDateTime,
+}
+
+impl FileContent {
+    pub fn pending(file_id: Uuid) -> Self {
+        FileContent {
+            file_id,
+            status: ExtractionStatus::PENDING,
+            content: None,
+            attempts: 0,
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct SearchResult {
+    #[diesel(sql_type = SqlUuid)]
+    pub file_id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = SqlUuid)]
+    pub folder_id: Uuid,
+    #[diesel(sql_type = SqlUuid)]
+    pub bucket_id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub bucket_name: String,
+    #[diesel(sql_type = Float)]
+    pub rank: f32,
+    #[diesel(sql_type = Text)]
+    pub snippet: String,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    FILE,
+    FOLDER,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum NameSort {
+    RELEVANCE,
+    NAME,
+    #[serde(rename = "created_at")]
+    CREATED,
+    SIZE,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    ASC,
+    DESC,
+}
+
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct NameHit {
+    #[diesel(sql_type = SqlUuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub kind: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    // Full path inside the bucket, folders end with a slash
+    #[diesel(sql_type = Text)]
+    pub path: String,
+    #[diesel(sql_type = SqlUuid)]
+    pub bucket_id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub bucket_name: String,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub size: Option<i64>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub content_type: Option<String>,
+    #[diesel(sql_type = SqlUuid)]
+    pub created_by: Uuid,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+    #[diesel(sql_type = Double)]
+    pub score: f64,
+}
@@ -0,0 +1,401 @@
+use crate::bucket::bucket_model::Bucket;
+use crate::config::db_config;
+use crate::error::ApiResponse;
+use crate::file::file_model::File;
+use crate::file::file_service;
+use crate::folder::folder_service;
+use crate::organization::organization_model::Organization;
+use crate::schema::{buckets, file_contents, files, folders, organizations, user_organizations};
+use crate::search::search_dto::{NameSearchPage, SearchContentDto, SearchNameDto};
+use crate::search::search_extractor;
+use crate::search::search_model::{EntryKind, ExtractionStatus, FileContent, NameHit, NameSort, SearchResult, SortOrder};
+use crate::token::token_service;
+use crate::user::user_model::User;
+use actix_web::http::StatusCode;
+use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+use chrono::Utc;
+use diesel::sql_types::{BigInt, Nullable, Text, Timestamp, Uuid as SqlUuid};
+use diesel::{sql_query, ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::env;
+use uuid::Uuid;
+
+const MAX_ATTEMPTS: i32 = 5;
+const MAX_QUERY_LEN: usize = 256;
+// ts_headline marks matches with these, they are turned into <mark> once the snippet is escaped
+const HIGHLIGHT_START: char = '\u{2}';
+const HIGHLIGHT_STOP: char = '\u{3}';
+
+lazy_static! {
+    static ref SEARCH_INTERVAL: u64 = env::var("SEARCH_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(30);
+    static ref SEARCH_BATCH_SIZE: i64 = env::var("SEARCH_BATCH_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(50);
+    static ref MAX_EXTRACT_BYTES: i64 = env::var("SEARCH_MAX_EXTRACT_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(64 * 1024 * 1024);
+}
+
+// Queues a new or overwritten file for text extraction, dropping whatever was indexed for it before
+pub async fn enqueue(file_id: Uuid, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    diesel::insert_into(file_contents::table)
+        .values(FileContent::pending(file_id))
+        .on_conflict(file_contents::file_id)
+        .do_update()
+        .set((
+            file_contents::status.eq(ExtractionStatus::PENDING),
+            file_contents::content.eq(None::<String>),
+            file_contents::attempts.eq(0),
+            file_contents::updated_at.eq(Utc::now().naive_utc())))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn search_content(query: SearchContentDto, user: &User) -> Result<Vec<SearchResult>, ApiResponse> {
+    let SearchContentDto { q, organization_id, bucket_id, limit, offset } = query;
+    let q = q.trim();
+    if q.is_empty() || q.len() > MAX_QUERY_LEN {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("Search query must be between 1 and {MAX_QUERY_LEN} characters")));
+    }
+    let mut conn = db_config::get_connection().await?;
+    check_scope(organization_id, bucket_id, user, &mut conn).await?;
+
+    let query = r#"
+    SELECT ranked.file_id, ranked.name, ranked.folder_id, ranked.bucket_id, ranked.bucket_name, ranked.rank,
+           ts_headline('english', file_contents.content, ranked.query, $7) AS snippet
+    FROM (
+        SELECT files.id AS file_id, files.name::text AS name, files.folder_id, buckets.id AS bucket_id, buckets.name::text AS bucket_name,
+               ts_rank(file_contents.content_tsv, query) AS rank, query
+        FROM file_contents
+        CROSS JOIN websearch_to_tsquery('english', $1) query
+        INNER JOIN files ON files.id = file_contents.file_id
+        INNER JOIN folders ON folders.id = files.folder_id
+        INNER JOIN buckets ON buckets.id = folders.bucket_id
+        INNER JOIN user_organizations ON user_organizations.organization_id = buckets.organization_id AND user_organizations.user_id = $2
+        WHERE file_contents.status = 'indexed'
+          AND file_contents.content_tsv @@ query
+          AND ($3::uuid IS NULL OR buckets.id = $3)
+          AND ($4::uuid IS NULL OR buckets.organization_id = $4)
+        ORDER BY rank DESC, files.id
+        LIMIT $5 OFFSET $6
+    ) ranked
+    INNER JOIN file_contents ON file_contents.file_id = ranked.file_id
+    ORDER BY ranked.rank DESC, ranked.file_id
+    "#;
+    let results = sql_query(query)
+        .bind::<Text, _>(q)
+        .bind::<SqlUuid, _>(user.id)
+        .bind::<Nullable<SqlUuid>, _>(bucket_id)
+        .bind::<Nullable<SqlUuid>, _>(organization_id)
+        .bind::<BigInt, _>(limit.unwrap_or(20).clamp(1, 100))
+        .bind::<BigInt, _>(offset.unwrap_or(0).max(0))
+        .bind::<Text, _>(format!("StartSel={HIGHLIGHT_START}, StopSel={HIGHLIGHT_STOP}, MaxFragments=3, MaxWords=30, MinWords=10"))
+        .load::<SearchResult>(&mut conn)
+        .await?;
+    Ok(results.into_iter().map(|result| SearchResult { snippet: highlight(&result.snippet), ..result }).collect())
+}
+
+// Searches are scoped to a bucket or an organization, and the caller has to belong to the organization
+async fn check_scope(organization_id: Option<Uuid>, bucket_id: Option<Uuid>, user: &User, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let organization_id = match bucket_id {
+        Some(bucket_id) => buckets::table.find(bucket_id).select(buckets::organization_id).first::<Uuid>(conn).await?,
+        None => organization_id
+            .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "Search needs an organization_id or a bucket_id".to_string()))?,
+    };
+    let member = user_organizations::table
+        .filter(user_organizations::organization_id.eq(organization_id))
+        .filter(user_organizations::user_id.eq(user.id))
+        .count()
+        .get_result::<i64>(conn)
+        .await?;
+    if member == 0 || !token_service::allows_organization(organization_id) {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have access to this organization".to_string()));
+    }
+    Ok(())
+}
+
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+// Position after the last hit of a page, tied to the sort it was produced under
+#[derive(Serialize, Deserialize)]
+struct NameCursor {
+    sort: NameSort,
+    key: String,
+    id: Uuid,
+}
+
+impl NameSort {
+    // Sort expression over a hit and the type its cursor key is cast back to
+    fn expression(self) -> (&'static str, &'static str) {
+        match self {
+            NameSort::RELEVANCE => ("hits.score", "float8"),
+            NameSort::NAME => ("hits.name", "text"),
+            NameSort::CREATED => ("hits.created_at", "timestamp"),
+            NameSort::SIZE => ("COALESCE(hits.size, 0)", "int8"),
+        }
+    }
+
+    fn default_order(self) -> SortOrder {
+        match self {
+            NameSort::NAME => SortOrder::ASC,
+            _ => SortOrder::DESC,
+        }
+    }
+
+    fn key(self, hit: &NameHit) -> String {
+        match self {
+            NameSort::RELEVANCE => hit.score.to_string(),
+            NameSort::NAME => hit.name.clone(),
+            NameSort::CREATED => hit.created_at.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+            NameSort::SIZE => hit.size.unwrap_or(0).to_string(),
+        }
+    }
+}
+
+pub async fn search_names(query: SearchNameDto, user: &User) -> Result<NameSearchPage, ApiResponse> {
+    let q = query.q.trim();
+    if q.is_empty() || q.len() > MAX_QUERY_LEN {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("Search query must be between 1 and {MAX_QUERY_LEN} characters")));
+    }
+    let cursor = match &query.cursor {
+        Some(cursor) => {
+            let cursor = BASE64_URL_SAFE_NO_PAD.decode(cursor).ok()
+                .and_then(|cursor| serde_json::from_slice::<NameCursor>(&cursor).ok())
+                .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))?;
+            if cursor.sort != query.sort {
+                return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Cursor belongs to a different sort".to_string()));
+            }
+            Some(cursor)
+        }
+        None => None,
+    };
+    // `image/*` matches the whole family
+    let content_type = query.content_type.as_deref().map(|content_type| match content_type.strip_suffix('*') {
+        Some(family) => format!("{}%", escape_like(family)),
+        None => escape_like(content_type),
+    });
+    let pattern = format!("%{}%", escape_like(q));
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    let mut conn = db_config::get_connection().await?;
+    check_scope(query.organization_id, query.bucket_id, user, &mut conn).await?;
+
+    // Folders have no size or content type, filtering on either leaves only files
+    let file_only = query.min_size.is_some() || query.max_size.is_some() || content_type.is_some();
+    let include_files = query.kind != Some(EntryKind::FOLDER);
+    let include_folders = query.kind != Some(EntryKind::FILE) && !file_only;
+    let mut branches = Vec::new();
+    if include_folders {
+        branches.push(r#"
+            SELECT folders.id, 'folder' AS kind, folders.name::text AS name, folders.parent_id AS parent_id, folders.bucket_id,
+                   NULL::int8 AS size, NULL::text AS content_type, folders.created_by, folders.created_at,
+                   word_similarity($1, folders.name)::float8 AS score
+            FROM folders
+            WHERE folders.parent_id IS NOT NULL
+              AND (folders.name ILIKE $2 OR $1 <% folders.name)
+              AND ($6::uuid IS NULL OR folders.created_by = $6)
+              AND ($7::timestamp IS NULL OR folders.created_at >= $7)
+              AND ($8::timestamp IS NULL OR folders.created_at < $8)"#);
+    }
+    if include_files {
+        branches.push(r#"
+            SELECT files.id, 'file' AS kind, files.name::text AS name, files.folder_id AS parent_id, folders.bucket_id,
+                   files.size, files.content_type::text, files.created_by, files.created_at,
+                   word_similarity($1, files.name)::float8 AS score
+            FROM files
+            INNER JOIN folders ON folders.id = files.folder_id
+            WHERE (files.name ILIKE $2 OR $1 <% files.name)
+              AND ($6::uuid IS NULL OR files.created_by = $6)
+              AND ($7::timestamp IS NULL OR files.created_at >= $7)
+              AND ($8::timestamp IS NULL OR files.created_at < $8)
+              AND ($9::int8 IS NULL OR files.size >= $9)
+              AND ($10::int8 IS NULL OR files.size <= $10)
+              AND ($11::text IS NULL OR files.content_type ILIKE $11)"#);
+    }
+    if branches.is_empty() {
+        return Ok(NameSearchPage { items: Vec::new(), next_cursor: None });
+    }
+    let hits = branches.join("\n            UNION ALL");
+    let (sort, key_type) = query.sort.expression();
+    let (direction, comparison) = match query.order.unwrap_or(query.sort.default_order()) {
+        SortOrder::ASC => ("ASC", ">"),
+        SortOrder::DESC => ("DESC", "<"),
+    };
+
+    let sql = format!(r#"
+    SELECT ranked.id, ranked.kind, ranked.name,
+           '/' || COALESCE(ancestors.path, '') || ranked.name || CASE WHEN ranked.kind = 'folder' THEN '/' ELSE '' END AS path,
+           ranked.bucket_id, ranked.bucket_name, ranked.size, ranked.content_type, ranked.created_by, ranked.created_at, ranked.score
+    FROM (
+        SELECT hits.*, buckets.name::text AS bucket_name
+        FROM ({hits}
+        ) hits
+        INNER JOIN buckets ON buckets.id = hits.bucket_id
+        INNER JOIN user_organizations ON user_organizations.organization_id = buckets.organization_id AND user_organizations.user_id = $3
+        WHERE ($4::uuid IS NULL OR buckets.id = $4)
+          AND ($5::uuid IS NULL OR buckets.organization_id = $5)
+          AND ($13::text IS NULL OR ({sort}, hits.id) {comparison} ($13::{key_type}, $14))
+        ORDER BY {sort} {direction}, hits.id {direction}
+        LIMIT $12
+    ) ranked
+    CROSS JOIN LATERAL (
+        WITH RECURSIVE chain AS (
+            SELECT folders.id, folders.parent_id, folders.name, 0 AS depth FROM folders WHERE folders.id = ranked.parent_id
+            UNION ALL
+            SELECT folders.id, folders.parent_id, folders.name, chain.depth + 1 FROM folders
+            INNER JOIN chain ON folders.id = chain.parent_id
+        )
+        SELECT string_agg(chain.name || '/', '' ORDER BY chain.depth DESC) FILTER (WHERE chain.parent_id IS NOT NULL) AS path FROM chain
+    ) ancestors
+    ORDER BY {ranked_sort} {direction}, ranked.id {direction}
+    "#, ranked_sort = sort.replace("hits.", "ranked."));
+
+    let mut items = sql_query(sql)
+        .bind::<Text, _>(q)
+        .bind::<Text, _>(pattern)
+        .bind::<SqlUuid, _>(user.id)
+        .bind::<Nullable<SqlUuid>, _>(query.bucket_id)
+        .bind::<Nullable<SqlUuid>, _>(query.organization_id)
+        .bind::<Nullable<SqlUuid>, _>(query.created_by)
+        .bind::<Nullable<Timestamp>, _>(query.created_after.map(|date| date.naive_utc()))
+        .bind::<Nullable<Timestamp>, _>(query.created_before.map(|date| date.naive_utc()))
+        .bind::<Nullable<BigInt>, _>(query.min_size)
+        .bind::<Nullable<BigInt>, _>(query.max_size)
+        .bind::<Nullable<Text>, _>(content_type)
+        .bind::<BigInt, _>(limit + 1)
+        .bind::<Nullable<Text>, _>(cursor.as_ref().map(|cursor| cursor.key.clone()))
+        .bind::<Nullable<SqlUuid>, _>(cursor.as_ref().map(|cursor| cursor.id))
+        .load::<NameHit>(&mut conn)
+        .await?;
+
+    // One extra row is fetched to tell whether another page follows
+    let next_cursor = if items.len() as i64 > limit {
+        items.truncate(limit as usize);
+        items.last().map(|last| {
+            let cursor = NameCursor { sort: query.sort, key: query.sort.key(last), id: last.id };
+            BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_vec(&cursor).unwrap_or_default())
+        })
+    } else {
+        None
+    };
+    Ok(NameSearchPage { items, next_cursor })
+}
+
+// Escapes the snippet for HTML and turns the highlight markers into <mark> elements
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            HIGHLIGHT_START => html.push_str("<mark>"),
+            HIGHLIGHT_STOP => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+pub fn start_worker() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(*SEARCH_INTERVAL));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_pending().await {
+                error!("Search indexer failed: {e}");
+            }
+        }
+    });
+}
+
+async fn run_pending() -> Result<(), ApiResponse> {
+    loop {
+        let mut conn = db_config::get_connection().await?;
+        let pending = file_contents::table
+            .filter(file_contents::status.eq(ExtractionStatus::PENDING))
+            .order(file_contents::updated_at)
+            .limit(*SEARCH_BATCH_SIZE)
+            .select(FileContent::as_select())
+            .load::<FileContent>(&mut conn)
+            .await?;
+        drop(conn);
+
+        let batch_len = pending.len() as i64;
+        for content in pending {
+            if let Err(e) = index_file(&content).await {
+                error!("Indexing file {} failed: {e}", content.file_id);
+            }
+        }
+        if batch_len < *SEARCH_BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+async fn index_file(content: &FileContent) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let target = files::table.find(content.file_id)
+        .inner_join(folders::table.on(folders::id.eq(files::folder_id)))
+        .inner_join(buckets::table.on(buckets::id.eq(folders::bucket_id)))
+        .inner_join(organizations::table.on(organizations::id.eq(buckets::organization_id)))
+        .select((File::as_select(), Bucket::as_select(), Organization::as_select()))
+        .first::<(File, Bucket, Organization)>(&mut conn)
+        .await
+        .optional()?;
+    let Some((file, bucket, organization)) = target else {
+        return Ok(());
+    };
+    // Objects under a customer provided key cannot be read without the customer
+    if file.customer_key_fingerprint.is_some() || file.size > *MAX_EXTRACT_BYTES {
+        return finish(content, ExtractionStatus::UNSUPPORTED, None, &mut conn).await;
+    }
+    let path = format!("files/{}/{}{}{}", organization.name, bucket.name, folder_service::folder_path(file.folder_id, &mut conn).await?, file.name);
+    drop(conn);
+
+    let body = match file_service::read_object(&path, &bucket, &file).await {
+        Ok(body) => body,
+        Err(e) => {
+            let mut conn = db_config::get_connection().await?;
+            let status = if content.attempts + 1 >= MAX_ATTEMPTS { ExtractionStatus::FAILED } else { ExtractionStatus::PENDING };
+            diesel::update(file_contents::table.find(content.file_id))
+                .filter(file_contents::updated_at.eq(content.updated_at))
+                .set((
+                    file_contents::status.eq(status),
+                    file_contents::attempts.eq(content.attempts + 1),
+                    file_contents::updated_at.eq(Utc::now().naive_utc())))
+                .execute(&mut conn)
+                .await?;
+            return Err(e);
+        }
+    };
+    // Extraction is CPU bound and parsers may panic on malformed documents, both stay off the async workers
+    let (name, content_type) = (file.name.clone(), file.content_type.clone());
+    let extracted = tokio::task::spawn_blocking(move || search_extractor::extract(&name, content_type.as_deref(), &body)).await;
+    let (status, text) = match extracted {
+        Ok(Ok(Some(text))) => (ExtractionStatus::INDEXED, Some(text)),
+        Ok(Ok(None)) => (ExtractionStatus::UNSUPPORTED, None),
+        Ok(Err(e)) => {
+            warn!("Cannot extract text from file {}: {e}", file.id);
+            (ExtractionStatus::FAILED, None)
+        }
+        Err(_) => (ExtractionStatus::FAILED, None),
+    };
+    let mut conn = db_config::get_connection().await?;
+    finish(content, status, text, &mut conn).await
+}
+
+// Stores the outcome unless the file was overwritten and queued again in the meantime
+async fn finish(content: &FileContent, status: ExtractionStatus, text: Option<String>, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    diesel::update(file_contents::table.find(content.file_id))
+        .filter(file_contents::updated_at.eq(content.updated_at))
+        .set((
+            file_contents::status.eq(status),
+            file_contents::content.eq(text),
+            file_contents::updated_at.eq(Utc::now().naive_utc())))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
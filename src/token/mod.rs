@@ -0,0 +1,4 @@
+pub mod token_handler;
+pub mod token_service;
+mod token_dto;
+pub mod token_model;
@@ -0,0 +1,25 @@
+use crate::token::token_model::{PersonalAccessToken, TokenScope};
+use crate::util::deserializer_util::trim;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator_derive::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct CreateTokenDto {
+    #[serde(deserialize_with = "trim")]
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+    pub scope: TokenScope,
+    // Leave out to allow every organization the user belongs to
+    pub organization_ids: Option<Vec<Uuid>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+// The only response that ever contains the token itself
+#[derive(Serialize)]
+pub struct CreatedTokenDto {
+    pub token: String,
+    #[serde(flatten)]
+    pub details: PersonalAccessToken,
+}
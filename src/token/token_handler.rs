@@ -0,0 +1,44 @@
+use crate::error::ApiResponse;
+use crate::token::token_dto::{CreateTokenDto, CreatedTokenDto};
+use crate::token::token_model::PersonalAccessToken;
+use crate::token::token_service;
+use crate::user::user_model::User;
+use actix_web::http::StatusCode;
+use actix_web::web::{Json, Path, ServiceConfig};
+use actix_web::{delete, get, post, HttpMessage, HttpRequest, HttpResponse};
+use uuid::Uuid;
+use validator::Validate;
+
+#[post("")]
+async fn create_token(dto: Json<CreateTokenDto>, request: HttpRequest) -> Result<Json<CreatedTokenDto>, ApiResponse> {
+    if let Err(e) = dto.validate() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    let CreateTokenDto { name, scope, organization_ids, expires_at } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let (token, details) = token_service::create(name, scope, organization_ids, expires_at, user).await?;
+    Ok(Json(CreatedTokenDto { token, details }))
+}
+
+#[get("")]
+async fn list_tokens(request: HttpRequest) -> Result<Json<Vec<PersonalAccessToken>>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let tokens = token_service::list(user).await?;
+    Ok(Json(tokens))
+}
+
+#[delete("{id}")]
+async fn revoke_token(id: Path<Uuid>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    token_service::revoke(id.into_inner(), user).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn token_routes(cfg: &mut ServiceConfig) {
+    cfg.service(create_token);
+    cfg.service(list_tokens);
+    cfg.service(revoke_token);
+}
@@ -0,0 +1,56 @@
+use crate::schema::personal_access_tokens;
+use crate::user::user_model::User;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Associations, Identifiable, Insertable, Queryable, Selectable};
+use diesel_derive_enum::DbEnum;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(DbEnum, Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[db_enum(existing_type_path = "crate::schema::sql_types::TokenScope")]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    // Only safe methods like GET
+    READ,
+    // Anything but managing organizations
+    WRITE,
+    ADMIN,
+}
+
+// A long lived token for scripts, only the hash of the token is kept
+#[derive(Identifiable, Selectable, Queryable, Insertable, Associations, Serialize, Debug)]
+#[diesel(table_name = personal_access_tokens)]
+#[diesel(belongs_to(User))]
+pub struct PersonalAccessToken {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub user_id: Uuid,
+    pub name: String,
+    pub token_prefix: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub scope: TokenScope,
+    pub organization_ids: Option<Vec<Uuid>>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub last_used_ip: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl PersonalAccessToken {
+    pub fn new(user_id: Uuid, name: String, token_prefix: String, token_hash: String, scope: TokenScope, organization_ids: Option<Vec<Uuid>>, expires_at: Option<NaiveDateTime>) -> Self {
+        PersonalAccessToken {
+            id: Uuid::now_v7(),
+            user_id,
+            name,
+            token_prefix,
+            token_hash,
+            scope,
+            organization_ids,
+            expires_at,
+            last_used_at: None,
+            last_used_ip: None,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
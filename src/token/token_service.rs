@@ -0,0 +1,159 @@
+use crate::config::db_config;
+use crate::error::ApiResponse;
+use crate::organization::organization_model::UserOrganization;
+use crate::schema::{personal_access_tokens, user_organizations, users};
+use crate::token::token_model::{PersonalAccessToken, TokenScope};
+use crate::user::user_model::User;
+use actix_web::http::{Method, StatusCode};
+use chrono::{DateTime, Duration, Utc};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use uuid::Uuid;
+
+pub const TOKEN_PREFIX: &str = "blz_pat_";
+
+tokio::task_local! {
+    static ORGANIZATION_LIMIT: OrganizationLimit;
+}
+
+// Memberships the current request may not use, see `restrict`
+#[derive(Default)]
+pub struct OrganizationLimit {
+    // Set when the request comes with a token limited to these organizations
+    pub allowed: Option<Vec<Uuid>>,
+    // Organizations requiring two-factor authentication the user has not enabled
+    pub blocked: Vec<Uuid>,
+}
+
+impl OrganizationLimit {
+    fn allows(&self, organization_id: Uuid) -> bool {
+        self.allowed.as_ref().is_none_or(|allowed| allowed.contains(&organization_id)) && !self.blocked.contains(&organization_id)
+    }
+}
+
+pub async fn create(name: String, scope: TokenScope, organization_ids: Option<Vec<Uuid>>, expires_at: Option<DateTime<Utc>>, user: &User) -> Result<(String, PersonalAccessToken), ApiResponse> {
+    let expires_at = expires_at.map(|expires_at| expires_at.naive_utc());
+    if expires_at.is_some_and(|expires_at| expires_at <= Utc::now().naive_utc()) {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Expiry must be in the future".to_string()));
+    }
+    let mut conn = db_config::get_connection().await?;
+    let organization_ids = match organization_ids {
+        Some(mut organization_ids) => {
+            organization_ids.sort();
+            organization_ids.dedup();
+            if organization_ids.is_empty() {
+                return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Leave out organization_ids to allow every organization".to_string()));
+            }
+            let memberships = user_organizations::table
+                .filter(user_organizations::user_id.eq(user.id))
+                .filter(user_organizations::organization_id.eq_any(&organization_ids))
+                .count()
+                .get_result::<i64>(&mut conn)
+                .await?;
+            if memberships != organization_ids.len() as i64 {
+                return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You are not a part of every listed organization".to_string()));
+            }
+            Some(organization_ids)
+        }
+        None => None,
+    };
+    let secret: String = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect();
+    let token = format!("{TOKEN_PREFIX}{secret}");
+    let details = diesel::insert_into(personal_access_tokens::table)
+        .values(PersonalAccessToken::new(user.id, name, token[..TOKEN_PREFIX.len() + 4].to_string(), hash(&token), scope, organization_ids, expires_at))
+        .get_result::<PersonalAccessToken>(&mut conn)
+        .await
+        .map_err(|e| match e {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => ApiResponse::new(StatusCode::CONFLICT, "A token with this name already exists".to_string()),
+            e => e.into(),
+        })?;
+    Ok((token, details))
+}
+
+pub async fn list(user: &User) -> Result<Vec<PersonalAccessToken>, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let tokens = personal_access_tokens::table
+        .filter(personal_access_tokens::user_id.eq(user.id))
+        .order(personal_access_tokens::created_at.desc())
+        .load::<PersonalAccessToken>(&mut conn)
+        .await?;
+    Ok(tokens)
+}
+
+pub async fn revoke(token_id: Uuid, user: &User) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let deleted = diesel::delete(personal_access_tokens::table)
+        .filter(personal_access_tokens::id.eq(token_id))
+        .filter(personal_access_tokens::user_id.eq(user.id))
+        .execute(&mut conn)
+        .await?;
+    if deleted == 0 {
+        return Err(ApiResponse::new(StatusCode::NOT_FOUND, "Token not found".to_string()));
+    }
+    Ok(())
+}
+
+// Routes that need a session or an admin token are wrapped in `session_only` and `admin_token`
+pub async fn authenticate(token: &str, method: &Method, ip_address: Option<String>) -> Result<(User, PersonalAccessToken), ApiResponse> {
+    let now = Utc::now().naive_utc();
+    let mut conn = db_config::get_connection().await?;
+    let (token, user) = personal_access_tokens::table
+        .inner_join(users::table)
+        .filter(personal_access_tokens::token_hash.eq(hash(token)))
+        .filter(personal_access_tokens::expires_at.is_null().or(personal_access_tokens::expires_at.gt(now)))
+        .select((PersonalAccessToken::as_select(), User::as_select()))
+        .first::<(PersonalAccessToken, User)>(&mut conn)
+        .await
+        .optional()?
+        .ok_or(ApiResponse::new(StatusCode::UNAUTHORIZED, "Token is invalid or has expired".to_string()))?;
+    if !permits(token.scope, method) {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "The scope of this token does not allow this request".to_string()));
+    }
+    // A minute of precision is plenty, so busy scripts do not write on every request
+    diesel::update(personal_access_tokens::table.find(token.id))
+        .filter(personal_access_tokens::last_used_at.is_null().or(personal_access_tokens::last_used_at.lt(now - Duration::minutes(1))))
+        .set((
+            personal_access_tokens::last_used_at.eq(now),
+            personal_access_tokens::last_used_ip.eq(ip_address)))
+        .execute(&mut conn)
+        .await?;
+    Ok((user, token))
+}
+
+fn permits(scope: TokenScope, method: &Method) -> bool {
+    match scope {
+        TokenScope::READ => [Method::GET, Method::HEAD, Method::OPTIONS].contains(method),
+        TokenScope::WRITE | TokenScope::ADMIN => true,
+    }
+}
+
+fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token))
+}
+
+// Runs a request with its organization limit in place
+pub async fn limit_organizations<F: Future>(limit: OrganizationLimit, request: F) -> F::Output {
+    ORGANIZATION_LIMIT.scope(limit, request).await
+}
+
+pub fn organization_limit() -> Option<Vec<Uuid>> {
+    ORGANIZATION_LIMIT.try_with(|limit| limit.allowed.clone()).ok().flatten()
+}
+
+pub fn allows_organization(organization_id: Uuid) -> bool {
+    ORGANIZATION_LIMIT.try_with(|limit| limit.allows(organization_id)).unwrap_or(true)
+}
+
+// Hides a membership the current request may not use, callers then treat the user as not being part of the organization
+pub fn restrict(user_organization: Option<UserOrganization>) -> Option<UserOrganization> {
+    user_organization.filter(|user_organization| allows_organization(user_organization.organization_id))
+}
@@ -0,0 +1,4 @@
+pub mod usage_handler;
+pub mod usage_service;
+mod usage_dto;
+pub mod usage_model;
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::usage::usage_model::{BucketUsageHistory, OrganizationUsageHistory, Usage};
+
+#[derive(Deserialize)]
+pub struct UsageQueryDto {
+    pub days: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct QuotaDto {
+    pub quota_bytes: Option<i64>,
+    pub soft_quota_bytes: Option<i64>,
+    pub quota_objects: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct BucketUsageDto {
+    pub bucket_id: Uuid,
+    pub name: String,
+    pub usage: Usage,
+}
+
+#[derive(Serialize)]
+pub struct OrganizationUsageDto {
+    pub organization_id: Uuid,
+    pub usage: Usage,
+    pub buckets: Vec<BucketUsageDto>,
+    pub history: Vec<OrganizationUsageHistory>,
+}
+
+#[derive(Serialize)]
+pub struct BucketUsageHistoryDto {
+    pub bucket_id: Uuid,
+    pub usage: Usage,
+    pub history: Vec<BucketUsageHistory>,
+}
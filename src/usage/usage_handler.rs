@@ -0,0 +1,47 @@
+use crate::error::ApiResponse;
+use crate::usage::usage_dto::{BucketUsageHistoryDto, OrganizationUsageDto, QuotaDto, UsageQueryDto};
+use crate::usage::usage_model::Usage;
+use crate::usage::usage_service;
+use crate::user::user_model::User;
+use actix_web::web::{Json, Path, Query, ServiceConfig};
+use actix_web::{get, put, HttpMessage, HttpRequest};
+use uuid::Uuid;
+
+#[get("organization/{organization_id}")]
+async fn organization_usage(organization_id: Path<Uuid>, dto: Query<UsageQueryDto>, request: HttpRequest) -> Result<Json<OrganizationUsageDto>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let usage = usage_service::organization_usage(organization_id.into_inner(), dto.into_inner().days, user).await?;
+    Ok(Json(usage))
+}
+
+#[get("bucket/{bucket_id}")]
+async fn bucket_usage(bucket_id: Path<Uuid>, dto: Query<UsageQueryDto>, request: HttpRequest) -> Result<Json<BucketUsageHistoryDto>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let usage = usage_service::bucket_usage(bucket_id.into_inner(), dto.into_inner().days, user).await?;
+    Ok(Json(usage))
+}
+
+#[put("organization/{organization_id}/quota")]
+async fn update_organization_quota(organization_id: Path<Uuid>, dto: Json<QuotaDto>, request: HttpRequest) -> Result<Json<Usage>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let usage = usage_service::update_organization_quota(organization_id.into_inner(), dto.into_inner(), user).await?;
+    Ok(Json(usage))
+}
+
+#[put("bucket/{bucket_id}/quota")]
+async fn update_bucket_quota(bucket_id: Path<Uuid>, dto: Json<QuotaDto>, request: HttpRequest) -> Result<Json<Usage>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let usage = usage_service::update_bucket_quota(bucket_id.into_inner(), dto.into_inner(), user).await?;
+    Ok(Json(usage))
+}
+
+pub fn usage_routes(cfg: &mut ServiceConfig) {
+    cfg.service(organization_usage);
+    cfg.service(bucket_usage);
+    cfg.service(update_organization_quota);
+    cfg.service(update_bucket_quota);
+}
@@ -0,0 +1,78 @@
+use chrono::NaiveDate;
+use diesel::sql_types::{BigInt, Bool, Text};
+use diesel::{Insertable, Queryable, QueryableByName, Selectable};
+use serde::Serialize;
+use uuid::Uuid;
+use crate::bucket::bucket_model::Bucket;
+use crate::organization::organization_model::Organization;
+use crate::schema::{bucket_usage_history, organization_usage_history};
+
+#[derive(Insertable, Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = bucket_usage_history)]
+pub struct BucketUsageHistory {
+    pub bucket_id: Uuid,
+    pub day: NaiveDate,
+    pub used_bytes: i64,
+    pub object_count: i64,
+}
+
+#[derive(Insertable, Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = organization_usage_history)]
+pub struct OrganizationUsageHistory {
+    pub organization_id: Uuid,
+    pub day: NaiveDate,
+    pub used_bytes: i64,
+    pub object_count: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Usage {
+    pub used_bytes: i64,
+    pub object_count: i64,
+    pub quota_bytes: Option<i64>,
+    pub soft_quota_bytes: Option<i64>,
+    pub quota_objects: Option<i64>,
+}
+
+impl From<&Bucket> for Usage {
+    fn from(bucket: &Bucket) -> Usage {
+        Usage {
+            used_bytes: bucket.used_bytes,
+            object_count: bucket.object_count,
+            quota_bytes: bucket.quota_bytes,
+            soft_quota_bytes: bucket.soft_quota_bytes,
+            quota_objects: bucket.quota_objects,
+        }
+    }
+}
+
+impl From<&Organization> for Usage {
+    fn from(organization: &Organization) -> Usage {
+        Usage {
+            used_bytes: organization.used_bytes,
+            object_count: organization.object_count,
+            quota_bytes: organization.quota_bytes,
+            soft_quota_bytes: organization.soft_quota_bytes,
+            quota_objects: organization.quota_objects,
+        }
+    }
+}
+
+#[derive(QueryableByName, Debug)]
+pub struct StoredTotals {
+    #[diesel(sql_type = BigInt)]
+    pub bytes: i64,
+    #[diesel(sql_type = BigInt)]
+    pub objects: i64,
+}
+
+// A file the usage backfill has no size for yet, with its path relative to the bucket root
+#[derive(QueryableByName, Debug)]
+pub struct UnsizedFile {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub id: Uuid,
+    #[diesel(sql_type = Text)]
+    pub path: String,
+    #[diesel(sql_type = Bool)]
+    pub encrypted: bool,
+}
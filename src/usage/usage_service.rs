@@ -0,0 +1,342 @@
+use crate::bucket::bucket_model::Bucket;
+use crate::config::db_config;
+use crate::error::ApiResponse;
+use crate::organization::organization_model::{Organization, OrganizationRole, UserOrganization};
+use crate::organization::organization_service;
+use crate::schema::{bucket_usage_history, buckets, files, organization_usage_history, organizations, user_organizations};
+use crate::usage::usage_dto::{BucketUsageDto, BucketUsageHistoryDto, OrganizationUsageDto, QuotaDto};
+use crate::usage::usage_model::{BucketUsageHistory, OrganizationUsageHistory, StoredTotals, UnsizedFile, Usage};
+use crate::token::token_service;
+use crate::user::user_model::User;
+use crate::util::crypto_util;
+use actix_web::http::StatusCode;
+use chrono::{Duration, NaiveDate, Utc};
+use diesel::upsert::excluded;
+use diesel::{sql_query, BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, SelectableHelper};
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use lazy_static::lazy_static;
+use uuid::Uuid;
+
+lazy_static! {
+    static ref QUOTA_ROLES: [OrganizationRole; 2] = [OrganizationRole::OWNER, OrganizationRole::ADMIN];
+}
+
+const DEFAULT_HISTORY_DAYS: i64 = 30;
+const MAX_HISTORY_DAYS: i64 = 3650;
+
+// Applies a change in stored bytes/objects to a bucket and its organization.
+// Must run inside the transaction that creates or removes the file rows.
+pub async fn record(bucket_id: Uuid, bytes: i64, objects: i64, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let bucket = buckets::table.find(bucket_id)
+        .select(Bucket::as_select())
+        .for_update()
+        .first::<Bucket>(conn)
+        .await?;
+    let organization = organizations::table.find(bucket.organization_id)
+        .select(Organization::as_select())
+        .for_update()
+        .first::<Organization>(conn)
+        .await?;
+    if bytes > 0 || objects > 0 {
+        check_quota("Bucket", &Usage::from(&bucket), bytes, objects)?;
+        check_quota("Organization", &Usage::from(&organization), bytes, objects)?;
+    }
+
+    record_bucket(&bucket, bucket.used_bytes + bytes, bucket.object_count + objects, conn).await?;
+    record_organization(&organization, organization.used_bytes + bytes, organization.object_count + objects, conn).await
+}
+
+// Sizes the files stored before usage was tracked from their bytes on disk, then recounts every bucket from its files.
+// Run with `blaze backfill-usage`, running it again only picks up what is still missing
+pub async fn backfill() -> Result<(usize, usize), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let stored = buckets::table
+        .inner_join(organizations::table.on(organizations::id.eq(buckets::organization_id)))
+        .select((Bucket::as_select(), Organization::as_select()))
+        .load::<(Bucket, Organization)>(&mut conn)
+        .await?;
+    let mut sized = 0;
+    for (bucket, organization) in &stored {
+        sized += size_files(bucket, organization, &mut conn).await?;
+        let bucket_id = bucket.id;
+        conn.transaction::<(), ApiResponse, _>(|conn| {
+            Box::pin(async move { recount_bucket(bucket_id, conn).await })
+        }).await?;
+    }
+    Ok((sized, stored.len()))
+}
+
+async fn size_files(bucket: &Bucket, organization: &Organization, conn: &mut AsyncPgConnection) -> Result<usize, ApiResponse> {
+    let query = r#"
+    WITH RECURSIVE paths AS (
+        SELECT id, ''::TEXT AS path FROM folders WHERE bucket_id = $1 AND parent_id IS NULL
+        UNION ALL
+        SELECT f.id, p.path || f.name || '/' FROM folders f
+        INNER JOIN paths p ON f.parent_id = p.id
+    )
+    SELECT files.id AS id, paths.path || files.name AS path, files.encryption_key IS NOT NULL AS encrypted
+    FROM files
+    INNER JOIN paths ON paths.id = files.folder_id
+    WHERE files.size = 0
+"#;
+    let unsized_files = sql_query(query)
+        .bind::<diesel::sql_types::Uuid, _>(bucket.id)
+        .load::<UnsizedFile>(conn)
+        .await?;
+    let mut sized = 0;
+    for file in unsized_files {
+        let path = format!("files/{}/{}/{}", organization.name, bucket.name, file.path);
+        let size = match tokio::fs::metadata(&path).await {
+            Ok(metadata) if file.encrypted => crypto_util::plaintext_len(metadata.len()),
+            Ok(metadata) => Some(metadata.len()),
+            Err(e) => {
+                warn!("Cannot size {path}: {e}");
+                continue;
+            }
+        };
+        let Some(size) = size.filter(|size| *size > 0) else {
+            continue;
+        };
+        // An upload since the query already set the size
+        sized += diesel::update(files::table.find(file.id).filter(files::size.eq(0)))
+            .set(files::size.eq(size as i64))
+            .execute(conn)
+            .await?;
+    }
+    Ok(sized)
+}
+
+// Sets a bucket's usage to the totals of its files, passing the difference on to its organization.
+// Must run inside a transaction, the locks keep concurrent uploads from being counted twice or not at all
+async fn recount_bucket(bucket_id: Uuid, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let bucket = buckets::table.find(bucket_id)
+        .select(Bucket::as_select())
+        .for_update()
+        .first::<Bucket>(conn)
+        .await?;
+    let organization = organizations::table.find(bucket.organization_id)
+        .select(Organization::as_select())
+        .for_update()
+        .first::<Organization>(conn)
+        .await?;
+    let totals = sql_query(r#"
+    SELECT coalesce(sum(files.size), 0)::BIGINT AS bytes, count(*) AS objects
+    FROM files INNER JOIN folders ON folders.id = files.folder_id
+    WHERE folders.bucket_id = $1
+"#)
+        .bind::<diesel::sql_types::Uuid, _>(bucket.id)
+        .get_result::<StoredTotals>(conn)
+        .await?;
+    record_bucket(&bucket, totals.bytes, totals.objects, conn).await?;
+    record_organization(&organization,
+        organization.used_bytes + totals.bytes - bucket.used_bytes,
+        organization.object_count + totals.objects - bucket.object_count,
+        conn).await
+}
+
+// Bytes a bucket can still take before it or its organization reaches its hard quota, None when neither has one
+pub async fn remaining_bytes(bucket_id: Uuid, conn: &mut AsyncPgConnection) -> Result<Option<i64>, ApiResponse> {
+    let bucket = buckets::table.find(bucket_id)
+        .select(Bucket::as_select())
+        .first::<Bucket>(conn)
+        .await?;
+    let organization = organizations::table.find(bucket.organization_id)
+        .select(Organization::as_select())
+        .first::<Organization>(conn)
+        .await?;
+    let remaining = [Usage::from(&bucket), Usage::from(&organization)].iter()
+        .filter_map(|usage| usage.quota_bytes.map(|quota_bytes| (quota_bytes - usage.used_bytes).max(0)))
+        .min();
+    Ok(remaining)
+}
+
+// Totals of every file below a folder, used when the whole subtree is purged
+pub async fn folder_totals(folder_id: Uuid, conn: &mut AsyncPgConnection) -> Result<StoredTotals, ApiResponse> {
+    let query = r#"
+    WITH RECURSIVE subtree AS (
+        SELECT id FROM folders WHERE id = $1
+        UNION ALL
+        SELECT f.id FROM folders f
+        INNER JOIN subtree s ON f.parent_id = s.id
+    )
+    SELECT coalesce(sum(size), 0)::BIGINT AS bytes, count(*) AS objects
+    FROM files WHERE folder_id IN (SELECT id FROM subtree);
+"#;
+    let totals = sql_query(query)
+        .bind::<diesel::sql_types::Uuid, _>(folder_id)
+        .get_result::<StoredTotals>(conn)
+        .await?;
+    Ok(totals)
+}
+
+// Drops a bucket's totals from its organization before the bucket is deleted
+pub async fn remove_bucket(bucket: &Bucket, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let organization = organizations::table.find(bucket.organization_id)
+        .select(Organization::as_select())
+        .for_update()
+        .first::<Organization>(conn)
+        .await?;
+    record_organization(&organization, organization.used_bytes - bucket.used_bytes, organization.object_count - bucket.object_count, conn).await
+}
+
+async fn record_bucket(bucket: &Bucket, used_bytes: i64, object_count: i64, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    diesel::update(buckets::table.find(bucket.id))
+        .set((buckets::used_bytes.eq(used_bytes), buckets::object_count.eq(object_count)))
+        .execute(conn)
+        .await?;
+    diesel::insert_into(bucket_usage_history::table)
+        .values(BucketUsageHistory { bucket_id: bucket.id, day: Utc::now().date_naive(), used_bytes, object_count })
+        .on_conflict((bucket_usage_history::bucket_id, bucket_usage_history::day))
+        .do_update()
+        .set((
+            bucket_usage_history::used_bytes.eq(excluded(bucket_usage_history::used_bytes)),
+            bucket_usage_history::object_count.eq(excluded(bucket_usage_history::object_count))))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn record_organization(organization: &Organization, used_bytes: i64, object_count: i64, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    diesel::update(organizations::table.find(organization.id))
+        .set((organizations::used_bytes.eq(used_bytes), organizations::object_count.eq(object_count)))
+        .execute(conn)
+        .await?;
+    diesel::insert_into(organization_usage_history::table)
+        .values(OrganizationUsageHistory { organization_id: organization.id, day: Utc::now().date_naive(), used_bytes, object_count })
+        .on_conflict((organization_usage_history::organization_id, organization_usage_history::day))
+        .do_update()
+        .set((
+            organization_usage_history::used_bytes.eq(excluded(organization_usage_history::used_bytes)),
+            organization_usage_history::object_count.eq(excluded(organization_usage_history::object_count))))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+fn check_quota(scope: &str, usage: &Usage, bytes: i64, objects: i64) -> Result<(), ApiResponse> {
+    if let Some(quota_bytes) = usage.quota_bytes {
+        if bytes > quota_bytes {
+            return Err(ApiResponse::new(StatusCode::PAYLOAD_TOO_LARGE, format!("{scope} quota is {quota_bytes} bytes, the upload is {bytes} bytes")));
+        }
+        if usage.used_bytes + bytes > quota_bytes {
+            return Err(ApiResponse::new(StatusCode::INSUFFICIENT_STORAGE, format!("{scope} storage quota exceeded: {} of {quota_bytes} bytes used", usage.used_bytes)));
+        }
+    }
+    if let Some(quota_objects) = usage.quota_objects.filter(|quota| usage.object_count + objects > *quota) {
+        return Err(ApiResponse::new(StatusCode::INSUFFICIENT_STORAGE, format!("{scope} object quota exceeded: {} of {quota_objects} objects stored", usage.object_count)));
+    }
+    if let Some(soft_quota_bytes) = usage.soft_quota_bytes.filter(|quota| usage.used_bytes + bytes > *quota) {
+        warn!("{scope} soft quota exceeded: {} of {soft_quota_bytes} bytes used", usage.used_bytes + bytes);
+    }
+    Ok(())
+}
+
+pub async fn organization_usage(organization_id: Uuid, days: Option<i64>, user: &User) -> Result<OrganizationUsageDto, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let (organization, _) = organization_service::validate_access(organization_id, user.id, &mut conn).await?;
+
+    let buckets = buckets::table
+        .filter(buckets::organization_id.eq(organization_id))
+        .order(buckets::used_bytes.desc())
+        .select(Bucket::as_select())
+        .load::<Bucket>(&mut conn)
+        .await?;
+    let since = history_since(days);
+    let history = organization_usage_history::table
+        .filter(organization_usage_history::organization_id.eq(organization_id))
+        .filter(organization_usage_history::day.ge(since))
+        .order(organization_usage_history::day.asc())
+        .load::<OrganizationUsageHistory>(&mut conn)
+        .await?;
+
+    Ok(OrganizationUsageDto {
+        organization_id,
+        usage: Usage::from(&organization),
+        buckets: buckets.iter()
+            .map(|bucket| BucketUsageDto { bucket_id: bucket.id, name: bucket.name.clone(), usage: Usage::from(bucket) })
+            .collect(),
+        history,
+    })
+}
+
+pub async fn bucket_usage(bucket_id: Uuid, days: Option<i64>, user: &User) -> Result<BucketUsageHistoryDto, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let (bucket, user_organization) = find_bucket(bucket_id, user, &mut conn).await?;
+    if user_organization.is_none() {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "User doesn't have access to this organization".to_string()));
+    }
+    let since = history_since(days);
+    let history = bucket_usage_history::table
+        .filter(bucket_usage_history::bucket_id.eq(bucket_id))
+        .filter(bucket_usage_history::day.ge(since))
+        .order(bucket_usage_history::day.asc())
+        .load::<BucketUsageHistory>(&mut conn)
+        .await?;
+
+    Ok(BucketUsageHistoryDto { bucket_id, usage: Usage::from(&bucket), history })
+}
+
+// `days` comes from the query string, clamped so the subtraction cannot overflow
+fn history_since(days: Option<i64>) -> NaiveDate {
+    let days = days.unwrap_or(DEFAULT_HISTORY_DAYS).clamp(1, MAX_HISTORY_DAYS);
+    Utc::now().date_naive() - Duration::days(days)
+}
+
+pub async fn update_organization_quota(organization_id: Uuid, quota: QuotaDto, user: &User) -> Result<Usage, ApiResponse> {
+    validate_quota(&quota)?;
+    let mut conn = db_config::get_connection().await?;
+    let (_, user_organization) = organization_service::validate_access(organization_id, user.id, &mut conn).await?;
+    match user_organization {
+        Some(user_organization) if QUOTA_ROLES.contains(&user_organization.role) => (),
+        _ => return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to change quotas".to_string()))
+    }
+    let QuotaDto { quota_bytes, soft_quota_bytes, quota_objects } = quota;
+    let organization = diesel::update(organizations::table.find(organization_id))
+        .set((
+            organizations::quota_bytes.eq(quota_bytes),
+            organizations::soft_quota_bytes.eq(soft_quota_bytes),
+            organizations::quota_objects.eq(quota_objects),
+            organizations::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<Organization>(&mut conn)
+        .await?;
+    Ok(Usage::from(&organization))
+}
+
+pub async fn update_bucket_quota(bucket_id: Uuid, quota: QuotaDto, user: &User) -> Result<Usage, ApiResponse> {
+    validate_quota(&quota)?;
+    let mut conn = db_config::get_connection().await?;
+    let (_, user_organization) = find_bucket(bucket_id, user, &mut conn).await?;
+    match user_organization {
+        Some(user_organization) if QUOTA_ROLES.contains(&user_organization.role) => (),
+        _ => return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to change quotas".to_string()))
+    }
+    let QuotaDto { quota_bytes, soft_quota_bytes, quota_objects } = quota;
+    let bucket = diesel::update(buckets::table.find(bucket_id))
+        .set((
+            buckets::quota_bytes.eq(quota_bytes),
+            buckets::soft_quota_bytes.eq(soft_quota_bytes),
+            buckets::quota_objects.eq(quota_objects),
+            buckets::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<Bucket>(&mut conn)
+        .await?;
+    Ok(Usage::from(&bucket))
+}
+
+async fn find_bucket(bucket_id: Uuid, user: &User, conn: &mut AsyncPgConnection) -> Result<(Bucket, Option<UserOrganization>), ApiResponse> {
+    let (bucket, user_organization) = buckets::table.find(bucket_id)
+        .left_join(user_organizations::table.on(buckets::organization_id.eq(user_organizations::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select((Bucket::as_select(), Option::<UserOrganization>::as_select()))
+        .first::<(Bucket, Option<UserOrganization>)>(conn)
+        .await?;
+    Ok((bucket, token_service::restrict(user_organization)))
+}
+
+fn validate_quota(quota: &QuotaDto) -> Result<(), ApiResponse> {
+    if [quota.quota_bytes, quota.soft_quota_bytes, quota.quota_objects].iter().flatten().any(|limit| *limit < 0) {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Quotas cannot be negative".to_string()));
+    }
+    if matches!((quota.quota_bytes, quota.soft_quota_bytes), (Some(quota_bytes), Some(soft_quota_bytes)) if soft_quota_bytes > quota_bytes) {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Soft quota cannot be larger than the hard quota".to_string()));
+    }
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use crate::user::user_model::User;
 use crate::util::validator_util::{validate_password, validate_username};
-use crate::util::deserializer_util::{trim_lower, trim};
+use crate::util::deserializer_util::{trim, trim_lower, trim_lower_option, trim_option};
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator_derive::Validate;
@@ -25,13 +26,76 @@ pub struct  RegisterUserDto {
     pub password: String,
 }
 
+#[derive(Deserialize)]
+pub struct VerifyEmailDto {
+    pub token: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ForgotPasswordDto {
+    #[validate(email)]
+    #[serde(deserialize_with = "trim_lower")]
+    pub email: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ResetPasswordDto {
+    pub token: String,
+
+    #[serde(deserialize_with = "trim")]
+    #[validate(length(min = 8, max = 24))]
+    #[validate(custom(function = "validate_password"))]
+    pub password: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ChangePasswordDto {
+    pub current_password: String,
+
+    #[serde(deserialize_with = "trim")]
+    #[validate(length(min = 8, max = 24))]
+    #[validate(custom(function = "validate_password"))]
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateProfileDto {
+    #[serde(default, deserialize_with = "trim_option")]
+    #[validate(length(min = 4, max = 32))]
+    pub name: Option<String>,
+
+    #[serde(default, deserialize_with = "trim_lower_option")]
+    #[validate(length(min = 4, max = 32))]
+    #[validate(custom(function = "validate_username"))]
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ChangeEmailDto {
+    #[validate(email)]
+    #[serde(deserialize_with = "trim_lower")]
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountDto {
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DeletionScheduledDto {
+    pub deletion_scheduled_at: NaiveDateTime,
+}
+
 #[derive(Serialize)]
 pub struct UserDto {
     pub id: Uuid,
     pub name: String,
     pub email: String,
     pub username: String,
-    pub is_verified: bool
+    pub is_verified: bool,
+    pub image: Option<String>,
 }
 
 impl From<User> for UserDto {
@@ -41,7 +105,8 @@ impl From<User> for UserDto {
             name: user.name,
             email: user.email,
             username: user.username,
-            is_verified: user.is_verified
+            is_verified: user.is_verified,
+            image: user.image,
         }
     }
 }
@@ -1,13 +1,13 @@
 use crate::error::ApiResponse;
-use crate::user::user_dto::{ RegisterUserDto, SearchDto, UserDto};
+use crate::user::user_dto::{ChangeEmailDto, ChangePasswordDto, DeleteAccountDto, DeletionScheduledDto, ForgotPasswordDto, RegisterUserDto, ResetPasswordDto, SearchDto, UpdateProfileDto, UserDto, VerifyEmailDto};
 use crate::user::user_model::User;
 use crate::user::user_service::{self, register_user};
 use actix_web::middleware::from_fn;
-use actix_web::web::{Json, Path, Query};
-use actix_web::{get, post, web};
+use actix_web::web::{Bytes, Json, Path, PayloadConfig, Query};
+use actix_web::{delete, get, patch, post, put, web, HttpMessage, HttpRequest, HttpResponse};
 use actix_web::http::StatusCode;
 use uuid::Uuid;
-use crate::auth::auth_middleware::jwt_auth;
+use crate::auth::auth_middleware::{jwt_auth, session_only};
 use validator::Validate;
 
 #[post("")]
@@ -19,6 +19,106 @@ async fn register(dto: Json<RegisterUserDto>) -> Result<Json<User>, ApiResponse>
     Ok(Json(register_user(name, email, username, password).await?))
 }
 
+#[post("verify")]
+async fn verify_email(dto: Json<VerifyEmailDto>) -> Result<Json<UserDto>, ApiResponse> {
+    let user = user_service::verify_email(&dto.token).await?;
+    Ok(Json(UserDto::from(user)))
+}
+
+#[post("verify/resend")]
+async fn resend_verification(request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    user_service::resend_verification(user).await?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[post("password/forgot")]
+async fn forgot_password(dto: Json<ForgotPasswordDto>) -> Result<HttpResponse, ApiResponse> {
+    if let Err(e) = dto.validate() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    user_service::forgot_password(dto.into_inner().email).await?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[post("password/reset")]
+async fn reset_password(dto: Json<ResetPasswordDto>) -> Result<HttpResponse, ApiResponse> {
+    if let Err(e) = dto.validate() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    let ResetPasswordDto { token, password } = dto.into_inner();
+    user_service::reset_password(&token, &password).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[put("password", wrap = "from_fn(session_only)")]
+async fn change_password(dto: Json<ChangePasswordDto>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    if let Err(e) = dto.validate() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    let ChangePasswordDto { current_password, new_password } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    user_service::change_password(user, &current_password, &new_password).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[patch("me")]
+async fn update_profile(dto: Json<UpdateProfileDto>, request: HttpRequest) -> Result<Json<UserDto>, ApiResponse> {
+    if let Err(e) = dto.validate() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    let UpdateProfileDto { name, username } = dto.into_inner();
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let user = user_service::update_profile(user, name, username).await?;
+    Ok(Json(UserDto::from(user)))
+}
+
+#[put("me/email", wrap = "from_fn(session_only)")]
+async fn change_email(dto: Json<ChangeEmailDto>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    if let Err(e) = dto.validate() {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()));
+    }
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    user_service::change_email(user, dto.into_inner().email).await?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[put("me/avatar")]
+async fn update_avatar(body: Bytes, request: HttpRequest) -> Result<Json<UserDto>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let user = user_service::update_avatar(user, body).await?;
+    Ok(Json(UserDto::from(user)))
+}
+
+#[delete("me/avatar")]
+async fn delete_avatar(request: HttpRequest) -> Result<Json<UserDto>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let user = user_service::delete_avatar(user).await?;
+    Ok(Json(UserDto::from(user)))
+}
+
+#[post("me/deletion", wrap = "from_fn(session_only)")]
+async fn schedule_deletion(dto: Json<DeleteAccountDto>, request: HttpRequest) -> Result<Json<DeletionScheduledDto>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let deletion_scheduled_at = user_service::schedule_deletion(user, dto.into_inner().password).await?;
+    Ok(Json(DeletionScheduledDto { deletion_scheduled_at }))
+}
+
+#[delete("me/deletion", wrap = "from_fn(session_only)")]
+async fn cancel_deletion(request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    user_service::cancel_deletion(user).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[get("{id}")]
 async fn find(id: Path<Uuid>)  -> Result<Json<UserDto>, ApiResponse> {
     let user = user_service::find_by_id(id.into_inner()).await;
@@ -40,9 +140,21 @@ async fn search(search_dto: Query<SearchDto>)  -> Result<Json<Vec<UserDto>>, Api
 }
 pub fn user_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(register);
+    cfg.service(verify_email);
+    cfg.service(forgot_password);
+    cfg.service(reset_password);
 
     cfg.service(web::scope("")
         .wrap(from_fn(jwt_auth))
+        .service(resend_verification)
+        .service(change_password)
+        .service(update_profile)
+        .service(change_email)
+        .service(delete_avatar)
+        .service(schedule_deletion)
+        .service(cancel_deletion)
+        .app_data(PayloadConfig::new(user_service::AVATAR_MAX_BYTES))
+        .service(update_avatar)
         .service(search)
         .service(find));
 }
\ No newline at end of file
@@ -1,9 +1,14 @@
 use chrono::Utc;
 use diesel::internal::derives::multiconnection::chrono::NaiveDateTime;
-use diesel::{Associations, Identifiable, Insertable, Queryable, Selectable};
+use diesel::{AsChangeset, Associations, Identifiable, Insertable, Queryable, Selectable};
+use diesel_derive_enum::DbEnum;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+// Both are seeded by migration, the tombstone takes over whatever a deleted account created
+pub const SYSTEM_USER_ID: Uuid = Uuid::nil();
+pub const DELETED_USER_ID: Uuid = Uuid::from_u128(2);
+
 #[derive(Identifiable, Selectable, Serialize, Deserialize, Queryable, Insertable, Debug)]
 #[diesel(table_name = crate::schema::users)]
 pub struct User {
@@ -17,6 +22,23 @@ pub struct User {
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
     pub image: Option<String>,
+    #[serde(skip)]
+    pub verification_sent_at: Option<NaiveDateTime>,
+    // Address waiting for verification, it replaces `email` once confirmed
+    #[serde(skip)]
+    pub pending_email: Option<String>,
+    #[serde(skip)]
+    pub username_changed_at: Option<NaiveDateTime>,
+    // Set while the account waits out the grace period before it is erased
+    #[serde(skip)]
+    pub deletion_scheduled_at: Option<NaiveDateTime>,
+    // Kept from the start of enrollment, `totp_enabled_at` is only set once a first code confirmed it
+    #[serde(skip)]
+    pub totp_secret: Option<String>,
+    #[serde(skip)]
+    pub totp_enabled_at: Option<NaiveDateTime>,
+    #[serde(skip)]
+    pub totp_last_step: Option<i64>,
 }
 
 impl User {
@@ -31,10 +53,26 @@ impl User {
             is_verified: false,
             created_at:  Utc::now().naive_utc(),
             updated_at: None,
+            verification_sent_at: None,
+            pending_email: None,
+            username_changed_at: None,
+            deletion_scheduled_at: None,
+            totp_secret: None,
+            totp_enabled_at: None,
+            totp_last_step: None,
         }
     }
 }
 
+#[derive(AsChangeset)]
+#[diesel(table_name = crate::schema::users)]
+pub struct UserChangeset {
+    pub name: Option<String>,
+    pub username: Option<String>,
+    pub username_changed_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
 #[derive(Identifiable, Selectable, Serialize, Deserialize, Queryable, Insertable, Associations, Debug)]
 #[diesel(table_name = crate::schema::user_session)]
 #[diesel(belongs_to(User))]
@@ -44,16 +82,90 @@ pub struct UserSession {
     pub user_id: Uuid,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    // Bumped on every refresh, a session idle for too long is no longer accepted
+    pub last_used_at: NaiveDateTime,
 }
 
 impl UserSession {
-    pub fn new(jti: Uuid, user_id: Uuid) -> Self {
+    pub fn new(jti: Uuid, user_id: Uuid, user_agent: Option<String>, ip_address: Option<String>) -> Self {
+        let now = Utc::now().naive_utc();
         UserSession {
             id: Uuid::now_v7(),
             jti,
             user_id,
-            created_at: Utc::now().naive_utc(),
+            created_at: now,
             updated_at: None,
+            user_agent,
+            ip_address,
+            last_used_at: now,
+        }
+    }
+}
+// A forgot-password request, only the hash of the mailed token is kept
+#[derive(Identifiable, Selectable, Queryable, Insertable, Associations, Debug)]
+#[diesel(table_name = crate::schema::password_resets)]
+#[diesel(belongs_to(User))]
+pub struct PasswordReset {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl PasswordReset {
+    pub fn new(user_id: Uuid, token_hash: String, expires_at: NaiveDateTime) -> Self {
+        PasswordReset {
+            id: Uuid::now_v7(),
+            user_id,
+            token_hash,
+            expires_at,
+            used_at: None,
+            created_at: Utc::now().naive_utc(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(DbEnum, Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[db_enum(existing_type_path = "crate::schema::sql_types::SecurityEventKind")]
+pub enum SecurityEventKind {
+    // A refresh token was presented again after it had been rotated
+    #[db_enum(rename = "refresh_token_reuse")]
+    #[serde(rename = "refresh_token_reuse")]
+    REUSE,
+    // Too many failed logins locked the account for a while
+    #[db_enum(rename = "account_lockout")]
+    #[serde(rename = "account_lockout")]
+    LOCKOUT,
+}
+
+// Something suspicious that happened to an account, kept for the user and for auditing
+#[derive(Identifiable, Selectable, Queryable, Insertable, Associations, Debug)]
+#[diesel(table_name = crate::schema::security_events)]
+#[diesel(belongs_to(User))]
+pub struct SecurityEvent {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: SecurityEventKind,
+    pub session_id: Option<Uuid>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl SecurityEvent {
+    pub fn new(user_id: Uuid, kind: SecurityEventKind, session_id: Option<Uuid>, user_agent: Option<String>, ip_address: Option<String>) -> Self {
+        SecurityEvent {
+            id: Uuid::now_v7(),
+            user_id,
+            kind,
+            session_id,
+            user_agent,
+            ip_address,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
@@ -1,15 +1,48 @@
 use crate::config::db_config;
 use crate::error::ApiResponse;
+use crate::mail::mail_service::{self, Mail, APP_URL};
 use crate::schema::users::dsl::users;
-use crate::user::user_model::User;
+use crate::bucket::bucket_model::Bucket;
+use crate::file::file_service::{self, PutOptions};
+use crate::organization::organization_model::Organization;
+use crate::organization::organization_service;
+use crate::schema::{bucket_domains, bucket_replications, buckets, files, folders, lifecycle_rules, organization_secrets, organizations, password_resets, user_organizations, user_session};
+use crate::user::user_model::{PasswordReset, User, UserChangeset, DELETED_USER_ID, SYSTEM_USER_ID};
+use crate::util::jwt_util;
 use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use lazy_static::lazy_static;
+use std::env;
 use diesel::pg::Pg;
 use diesel::ExpressionMethods;
 use diesel::PgTextExpressionMethods;
-use diesel::{debug_query, BoolExpressionMethods, QueryDsl};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::{debug_query, BoolExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, SelectableHelper};
 use diesel_async::RunQueryDsl;
 use uuid::Uuid;
 
+const EMAIL_VERIFICATION_AUDIENCE: &str = "email_verification";
+// Seeded by migration, owned by the internal `.system` organization
+const AVATAR_BUCKET_ID: Uuid = Uuid::from_u128(1);
+pub const AVATAR_MAX_BYTES: usize = 2 * 1024 * 1024;
+
+lazy_static! {
+    static ref EMAIL_VERIFICATION_EXPIRY: i64 = env::var("EMAIL_VERIFICATION_EXPIRY").ok().and_then(|v| v.parse().ok()).unwrap_or(24 * 60 * 60);
+    static ref EMAIL_VERIFICATION_COOLDOWN: i64 = env::var("EMAIL_VERIFICATION_COOLDOWN_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60);
+    static ref USERNAME_CHANGE_COOLDOWN_DAYS: i64 = env::var("USERNAME_CHANGE_COOLDOWN_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(30);
+    static ref PASSWORD_RESET_EXPIRY: i64 = env::var("PASSWORD_RESET_EXPIRY").ok().and_then(|v| v.parse().ok()).unwrap_or(30 * 60);
+    static ref PASSWORD_RESET_COOLDOWN: i64 = env::var("PASSWORD_RESET_COOLDOWN_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60);
+    static ref ACCOUNT_DELETION_GRACE_DAYS: i64 = env::var("ACCOUNT_DELETION_GRACE_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(14);
+    static ref ACCOUNT_DELETION_INTERVAL: u64 = env::var("ACCOUNT_DELETION_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60 * 60);
+    // When set, unverified users cannot create organizations or organization secrets
+    static ref REQUIRE_VERIFIED_EMAIL: bool = env::var("REQUIRE_VERIFIED_EMAIL").map(|v| v == "true").unwrap_or(false);
+}
+
 pub async fn register_user(name: String, email: String, username: String, raw_password: String) -> Result<User, ApiResponse> {
     let password = bcrypt::hash(&raw_password, 10).unwrap();
     let pool = db_config::get_connection_pool().await;
@@ -17,10 +50,360 @@ pub async fn register_user(name: String, email: String, username: String, raw_pa
 
     // let mut conn = db_config::connection().await?;
 
-    Ok(diesel::insert_into(users)
+    let user = diesel::insert_into(users)
         .values(User::new(name, username, email, Some(password), None))
         .get_result::<User>(&mut conn).await
-        .map_err(|_| ApiResponse::new(StatusCode::CONFLICT, "frokpskf".to_string()))?)
+        .map_err(|_| ApiResponse::new(StatusCode::CONFLICT, "frokpskf".to_string()))?;
+    // The account exists either way, a failed mail can be sent again through the resend endpoint
+    if let Err(e) = send_verification(&user, &user.email, &mut conn).await {
+        error!("Cannot send verification mail to {}: {e}", user.id);
+    }
+    Ok(user)
+}
+
+// Mails a verification link for `address`, the account email or an address the user is changing to
+pub async fn send_verification(user: &User, address: &str, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let token = jwt_util::issue_email_token(user.id, address, EMAIL_VERIFICATION_AUDIENCE, *EMAIL_VERIFICATION_EXPIRY)
+        .map_err(|e| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let hours = *EMAIL_VERIFICATION_EXPIRY / 3600;
+    mail_service::send(Mail {
+        to: address.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: format!("Hi {},\n\nConfirm your email address by opening the link below, it is valid for {hours} hours.\n\n{}/auth/verify-email?token={token}\n\nIf you did not create an account you can ignore this mail.\n", user.name, *APP_URL),
+    }).await?;
+    diesel::update(users.find(user.id))
+        .set(crate::schema::users::verification_sent_at.eq(Utc::now().naive_utc()))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn verify_email(token: &str) -> Result<User, ApiResponse> {
+    let claims = jwt_util::decode_email_token(token, EMAIL_VERIFICATION_AUDIENCE)
+        .map_err(|_| ApiResponse::new(StatusCode::BAD_REQUEST, "Verification link is invalid or has expired".to_string()))?
+        .claims;
+    let mut conn = db_config::get_connection().await?;
+    let user = users.find(claims.sub).first::<User>(&mut conn).await
+        .optional()?
+        .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "Verification link is invalid or has expired".to_string()))?;
+    if user.pending_email.as_deref() == Some(claims.email.as_str()) {
+        return confirm_email_change(user.id, claims.email, &mut conn).await;
+    }
+    // A link sent before the address changed does not verify the new one
+    if user.email != claims.email {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Verification link is for a different email address".to_string()));
+    }
+    if user.is_verified {
+        return Ok(user);
+    }
+    let user = diesel::update(users.find(user.id))
+        .set((
+            crate::schema::users::is_verified.eq(true),
+            crate::schema::users::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<User>(&mut conn)
+        .await?;
+    Ok(user)
+}
+
+async fn confirm_email_change(user_id: Uuid, email: String, conn: &mut AsyncPgConnection) -> Result<User, ApiResponse> {
+    let user = diesel::update(users.find(user_id))
+        .set((
+            crate::schema::users::email.eq(email),
+            crate::schema::users::pending_email.eq(None::<String>),
+            crate::schema::users::is_verified.eq(true),
+            crate::schema::users::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<User>(conn)
+        .await
+        .map_err(|e| match e {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => ApiResponse::new(StatusCode::CONFLICT, "Email is already in use".to_string()),
+            e => e.into(),
+        })?;
+    Ok(user)
+}
+
+pub async fn resend_verification(user: &User) -> Result<(), ApiResponse> {
+    let address = match &user.pending_email {
+        Some(pending_email) => pending_email.clone(),
+        None if !user.is_verified => user.email.clone(),
+        None => return Err(ApiResponse::new(StatusCode::CONFLICT, "Email is already verified".to_string())),
+    };
+    let mut conn = db_config::get_connection().await?;
+    // Claims the cooldown slot before sending so concurrent requests cannot both send
+    let now = Utc::now().naive_utc();
+    let claimed = diesel::update(users.find(user.id))
+        .filter(crate::schema::users::verification_sent_at.is_null()
+            .or(crate::schema::users::verification_sent_at.le(now - Duration::seconds(*EMAIL_VERIFICATION_COOLDOWN))))
+        .set(crate::schema::users::verification_sent_at.eq(now))
+        .execute(&mut conn)
+        .await?;
+    if claimed == 0 {
+        return Err(ApiResponse::new(StatusCode::TOO_MANY_REQUESTS, format!("A verification mail was sent recently, try again in {} seconds", *EMAIL_VERIFICATION_COOLDOWN)));
+    }
+    send_verification(user, &address, &mut conn).await
+}
+
+pub async fn update_profile(user: &User, name: Option<String>, username: Option<String>) -> Result<User, ApiResponse> {
+    let now = Utc::now().naive_utc();
+    let username = username.filter(|username| *username != user.username);
+    let next_change = user.username_changed_at
+        .filter(|_| username.is_some())
+        .map(|changed_at| changed_at + Duration::days(*USERNAME_CHANGE_COOLDOWN_DAYS));
+    if let Some(next_change) = next_change.filter(|next_change| *next_change > now) {
+        return Err(ApiResponse::new(StatusCode::TOO_MANY_REQUESTS, format!("Username can be changed again after {}", next_change.format("%Y-%m-%d %H:%M UTC"))));
+    }
+    let mut conn = db_config::get_connection().await?;
+    let changes = UserChangeset {
+        name,
+        username_changed_at: username.as_ref().map(|_| now),
+        username,
+        updated_at: Some(now),
+    };
+    let user = diesel::update(users.find(user.id))
+        .set(changes)
+        .get_result::<User>(&mut conn)
+        .await
+        .map_err(|e| match e {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => ApiResponse::new(StatusCode::CONFLICT, "Username is already taken".to_string()),
+            e => e.into(),
+        })?;
+    Ok(user)
+}
+
+// The current address stays in use until the new one is confirmed through the mailed link
+pub async fn change_email(user: &User, email: String) -> Result<(), ApiResponse> {
+    if email == user.email {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "This is already your email address".to_string()));
+    }
+    let mut conn = db_config::get_connection().await?;
+    let taken = users.filter(crate::schema::users::email.eq(&email))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await?;
+    if taken > 0 {
+        return Err(ApiResponse::new(StatusCode::CONFLICT, "Email is already in use".to_string()));
+    }
+    let user = diesel::update(users.find(user.id))
+        .set((
+            crate::schema::users::pending_email.eq(Some(&email)),
+            crate::schema::users::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<User>(&mut conn)
+        .await?;
+    send_verification(&user, &email, &mut conn).await
+}
+
+pub async fn update_avatar(user: &User, body: Bytes) -> Result<User, ApiResponse> {
+    if body.len() > AVATAR_MAX_BYTES {
+        return Err(ApiResponse::new(StatusCode::PAYLOAD_TOO_LARGE, format!("Avatar must be at most {} KiB", AVATAR_MAX_BYTES / 1024)));
+    }
+    // The stored type comes from the bytes, not from what the client claims
+    let extension = match &body[..] {
+        [0x89, b'P', b'N', b'G', ..] => "png",
+        [0xFF, 0xD8, 0xFF, ..] => "jpg",
+        [b'G', b'I', b'F', b'8', ..] => "gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        _ => return Err(ApiResponse::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Avatar must be a PNG, JPEG, GIF or WebP image".to_string())),
+    };
+    let mut conn = db_config::get_connection().await?;
+    let (bucket, organization) = avatar_bucket(&mut conn).await?;
+    // Every upload gets a new name so caches never serve a stale avatar
+    let path = format!("{}/{}.{extension}", user.id, Uuid::now_v7());
+    file_service::put_object(&organization.name, &bucket, &path, body, user.id, PutOptions::default()).await?;
+    let image = format!("{}/f/{}/{}/{path}", *APP_URL, organization.name, bucket.name);
+    let updated = diesel::update(users.find(user.id))
+        .set((
+            crate::schema::users::image.eq(Some(image)),
+            crate::schema::users::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<User>(&mut conn)
+        .await?;
+    remove_avatar(user, &organization, &bucket).await;
+    Ok(updated)
+}
+
+pub async fn delete_avatar(user: &User) -> Result<User, ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let (bucket, organization) = avatar_bucket(&mut conn).await?;
+    let updated = diesel::update(users.find(user.id))
+        .set((
+            crate::schema::users::image.eq(None::<String>),
+            crate::schema::users::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<User>(&mut conn)
+        .await?;
+    remove_avatar(user, &organization, &bucket).await;
+    Ok(updated)
+}
+
+async fn avatar_bucket(conn: &mut AsyncPgConnection) -> Result<(Bucket, Organization), ApiResponse> {
+    let bucket = buckets::table.find(AVATAR_BUCKET_ID)
+        .inner_join(organizations::table.on(organizations::id.eq(buckets::organization_id)))
+        .select((Bucket::as_select(), Organization::as_select()))
+        .first::<(Bucket, Organization)>(conn)
+        .await?;
+    Ok(bucket)
+}
+
+// Drops the avatar the user had before, images hosted elsewhere (e.g. from OAuth) are left alone
+async fn remove_avatar(user: &User, organization: &Organization, bucket: &Bucket) {
+    let prefix = format!("{}/f/{}/{}/", *APP_URL, organization.name, bucket.name);
+    let Some(path) = user.image.as_ref().and_then(|image| image.strip_prefix(&prefix)) else {
+        return;
+    };
+    if let Err(e) = file_service::delete_object(&organization.name, bucket, path, false).await {
+        warn!("Cannot remove old avatar of {}: {e}", user.id);
+    }
+}
+
+// Marks the account for deletion, it is erased by the worker once the grace period is over
+pub async fn schedule_deletion(user: &User, password: Option<String>) -> Result<NaiveDateTime, ApiResponse> {
+    // Accounts created through OAuth have no password, the session is all they can prove
+    if let Some(actual_password) = &user.password {
+        let password = password.ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "Password is required to delete the account".to_string()))?;
+        if !bcrypt::verify(password, actual_password).unwrap_or(false) {
+            return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Password is incorrect".to_string()));
+        }
+    }
+    if user.deletion_scheduled_at.is_some() {
+        return Err(ApiResponse::new(StatusCode::CONFLICT, "Account deletion is already scheduled".to_string()));
+    }
+    let mut conn = db_config::get_connection().await?;
+    ensure_no_sole_ownership(user.id, &mut conn).await?;
+    let scheduled_at = Utc::now().naive_utc() + Duration::days(*ACCOUNT_DELETION_GRACE_DAYS);
+    diesel::update(users.find(user.id))
+        .set((
+            crate::schema::users::deletion_scheduled_at.eq(Some(scheduled_at)),
+            crate::schema::users::updated_at.eq(Utc::now().naive_utc())))
+        .execute(&mut conn)
+        .await?;
+    // The deletion is scheduled either way, the mail is only a courtesy
+    if let Err(e) = mail_service::send(Mail {
+        to: user.email.clone(),
+        subject: "Your account is scheduled for deletion".to_string(),
+        body: format!("Hi {},\n\nYour account will be deleted on {}. Until then you can sign in and cancel the deletion from your account settings.\n\n{}\n", user.name, scheduled_at.format("%Y-%m-%d %H:%M UTC"), *APP_URL),
+    }).await {
+        error!("Cannot send deletion notice to {}: {e}", user.id);
+    }
+    Ok(scheduled_at)
+}
+
+pub async fn cancel_deletion(user: &User) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let cancelled = diesel::update(users.find(user.id))
+        .filter(crate::schema::users::deletion_scheduled_at.is_not_null())
+        .set((
+            crate::schema::users::deletion_scheduled_at.eq(None::<NaiveDateTime>),
+            crate::schema::users::updated_at.eq(Utc::now().naive_utc())))
+        .execute(&mut conn)
+        .await?;
+    if cancelled == 0 {
+        return Err(ApiResponse::new(StatusCode::CONFLICT, "Account deletion is not scheduled".to_string()));
+    }
+    Ok(())
+}
+
+async fn ensure_no_sole_ownership(user_id: Uuid, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let organizations = organization_service::sole_owned_organizations(user_id, conn).await?;
+    if !organizations.is_empty() {
+        let names = organizations.into_iter().map(|organization| organization.name).collect::<Vec<_>>().join(", ");
+        return Err(ApiResponse::new(StatusCode::CONFLICT, format!("Transfer ownership of or delete these organizations first: {names}")));
+    }
+    Ok(())
+}
+
+pub fn start_deletion_worker() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(*ACCOUNT_DELETION_INTERVAL));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_pending_deletions().await {
+                error!("Account deletion run failed: {e}");
+            }
+        }
+    });
+}
+
+async fn run_pending_deletions() -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let due = users
+        .filter(crate::schema::users::deletion_scheduled_at.le(Utc::now().naive_utc()))
+        .load::<User>(&mut conn)
+        .await?;
+    for user in due {
+        if let Err(e) = delete_account(&user, &mut conn).await {
+            warn!("Cannot delete account {}: {e}", user.id);
+        }
+    }
+    Ok(())
+}
+
+// Hands everything the user created to the tombstone and removes the user row, sessions and reset links go with it
+async fn delete_account(user: &User, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    let user_id = user.id;
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            // Ownership may have changed hands during the grace period
+            ensure_no_sole_ownership(user_id, conn).await?;
+            diesel::update(organizations::table.filter(organizations::created_by.eq(user_id)))
+                .set(organizations::created_by.eq(DELETED_USER_ID))
+                .execute(conn)
+                .await?;
+            diesel::update(organization_secrets::table.filter(organization_secrets::created_by.eq(user_id)))
+                .set(organization_secrets::created_by.eq(DELETED_USER_ID))
+                .execute(conn)
+                .await?;
+            diesel::update(buckets::table.filter(buckets::created_by.eq(user_id)))
+                .set(buckets::created_by.eq(DELETED_USER_ID))
+                .execute(conn)
+                .await?;
+            diesel::update(bucket_domains::table.filter(bucket_domains::created_by.eq(user_id)))
+                .set(bucket_domains::created_by.eq(DELETED_USER_ID))
+                .execute(conn)
+                .await?;
+            diesel::update(bucket_replications::table.filter(bucket_replications::created_by.eq(user_id)))
+                .set(bucket_replications::created_by.eq(DELETED_USER_ID))
+                .execute(conn)
+                .await?;
+            diesel::update(lifecycle_rules::table.filter(lifecycle_rules::created_by.eq(user_id)))
+                .set(lifecycle_rules::created_by.eq(DELETED_USER_ID))
+                .execute(conn)
+                .await?;
+            diesel::update(folders::table.filter(folders::created_by.eq(user_id)))
+                .set(folders::created_by.eq(DELETED_USER_ID))
+                .execute(conn)
+                .await?;
+            diesel::update(files::table.filter(files::created_by.eq(user_id)))
+                .set(files::created_by.eq(DELETED_USER_ID))
+                .execute(conn)
+                .await?;
+            diesel::update(user_organizations::table.filter(user_organizations::added_by.eq(user_id)))
+                .set(user_organizations::added_by.eq(DELETED_USER_ID))
+                .execute(conn)
+                .await?;
+            diesel::delete(user_organizations::table.filter(user_organizations::user_id.eq(user_id)))
+                .execute(conn)
+                .await?;
+            diesel::delete(users.find(user_id))
+                .execute(conn)
+                .await?;
+            Ok(())
+        })
+    }).await?;
+    info!("Deleted account {user_id}");
+    let (bucket, organization) = avatar_bucket(conn).await?;
+    remove_avatar(user, &organization, &bucket).await;
+    if let Err(e) = mail_service::send(Mail {
+        to: user.email.clone(),
+        subject: "Your account has been deleted".to_string(),
+        body: format!("Hi {},\n\nYour account and personal data have been deleted. Content you created in shared organizations stays available to their members without your name on it.\n", user.name),
+    }).await {
+        error!("Cannot send deletion confirmation to {user_id}: {e}");
+    }
+    Ok(())
+}
+
+pub fn require_verified(user: &User) -> Result<(), ApiResponse> {
+    if *REQUIRE_VERIFIED_EMAIL && !user.is_verified {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Verify your email address first".to_string()));
+    }
+    Ok(())
 }
 
 pub async fn find_by_id(user_id: Uuid) -> Option<User> {
@@ -38,6 +421,8 @@ pub async fn search(keyword: String, limit: i64, cursor: Option<Uuid>) -> Result
         .filter(crate::schema::users::username.ilike(format!("%{}%", keyword))
                 .or(crate::schema::users::name.ilike(format!("%{}%", keyword)))
                 .or(crate::schema::users::email.ilike(format!("%{}%", keyword))))
+            // The internal system and tombstone users are not real accounts
+            .filter(crate::schema::users::id.ne_all([SYSTEM_USER_ID, DELETED_USER_ID]))
             .order(crate::schema::users::id.asc()) // 👈 must match cursor logic
         .into_boxed();
     if let Some(cursor) = cursor {
@@ -52,3 +437,107 @@ println!("SQL: {}", sql);
     
     Ok(results)
 }
+
+// Always succeeds so the endpoint does not tell which addresses have an account. The lookup and the mail
+// run after the response, so a known address takes no longer to answer than an unknown one
+pub async fn forgot_password(email: String) -> Result<(), ApiResponse> {
+    tokio::spawn(async move {
+        if let Err(e) = send_password_reset(&email).await {
+            error!("Cannot handle password reset request: {e}");
+        }
+    });
+    Ok(())
+}
+
+async fn send_password_reset(email: &str) -> Result<(), ApiResponse> {
+    let mut conn = db_config::get_connection().await?;
+    let Some(user) = users.filter(crate::schema::users::email.eq(email)).first::<User>(&mut conn).await.optional()? else {
+        return Ok(());
+    };
+    let now = Utc::now().naive_utc();
+    let recent = password_resets::table
+        .filter(password_resets::user_id.eq(user.id))
+        .filter(password_resets::created_at.gt(now - Duration::seconds(*PASSWORD_RESET_COOLDOWN)))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await?;
+    if recent > 0 {
+        return Ok(());
+    }
+    let token: String = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(48)
+        .map(char::from)
+        .collect();
+    diesel::insert_into(password_resets::table)
+        .values(PasswordReset::new(user.id, hex::encode(Sha256::digest(&token)), now + Duration::seconds(*PASSWORD_RESET_EXPIRY)))
+        .execute(&mut conn)
+        .await?;
+    let minutes = *PASSWORD_RESET_EXPIRY / 60;
+    mail_service::send(Mail {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!("Hi {},\n\nSomeone asked to reset the password of your account. Open the link below within {minutes} minutes to choose a new one, it works once.\n\n{}/auth/reset-password?token={token}\n\nIf this was not you, you can ignore this mail and your password stays the same.\n", user.name, *APP_URL),
+    }).await
+}
+
+pub async fn reset_password(token: &str, new_password: &str) -> Result<(), ApiResponse> {
+    let token_hash = hex::encode(Sha256::digest(token));
+    let password = hash_password(new_password)?;
+    let mut conn = db_config::get_connection().await?;
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move {
+            let now = Utc::now().naive_utc();
+            // Marking the link used in the same statement that checks it keeps it single use under concurrent requests
+            let reset = diesel::update(password_resets::table)
+                .filter(password_resets::token_hash.eq(token_hash))
+                .filter(password_resets::used_at.is_null())
+                .filter(password_resets::expires_at.gt(now))
+                .set(password_resets::used_at.eq(now))
+                .get_result::<PasswordReset>(conn)
+                .await
+                .optional()?
+                .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "Reset link is invalid or has expired".to_string()))?;
+            // Any other link still out there stops working as well
+            diesel::update(password_resets::table)
+                .filter(password_resets::user_id.eq(reset.user_id))
+                .filter(password_resets::used_at.is_null())
+                .set(password_resets::used_at.eq(now))
+                .execute(conn)
+                .await?;
+            set_password(reset.user_id, password, conn).await
+        })
+    }).await
+}
+
+pub async fn change_password(user: &User, current_password: &str, new_password: &str) -> Result<(), ApiResponse> {
+    let actual_password = user.password.as_ref()
+        .ok_or(ApiResponse::new(StatusCode::BAD_REQUEST, "This account has no password, use forgot password to set one".to_string()))?;
+    if !bcrypt::verify(current_password, actual_password).unwrap_or(false) {
+        return Err(ApiResponse::new(StatusCode::FORBIDDEN, "Current password is incorrect".to_string()));
+    }
+    let password = hash_password(new_password)?;
+    let user_id = user.id;
+    let mut conn = db_config::get_connection().await?;
+    conn.transaction::<(), ApiResponse, _>(|conn| {
+        Box::pin(async move { set_password(user_id, password, conn).await })
+    }).await
+}
+
+fn hash_password(password: &str) -> Result<String, ApiResponse> {
+    bcrypt::hash(password, 10).map_err(|e| ApiResponse::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// Stores the new hash and signs the user out everywhere
+async fn set_password(user_id: Uuid, password: String, conn: &mut AsyncPgConnection) -> Result<(), ApiResponse> {
+    diesel::update(users.find(user_id))
+        .set((
+            crate::schema::users::password.eq(Some(password)),
+            crate::schema::users::updated_at.eq(Utc::now().naive_utc())))
+        .execute(conn)
+        .await?;
+    diesel::delete(user_session::table.filter(user_session::user_id.eq(user_id)))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Error, Key, Nonce};
+use rand::RngCore;
+
+pub const KEY_LEN: usize = 32;
+pub const CHUNK_SIZE: u64 = 64 * 1024;
+pub const TAG_LEN: u64 = 16;
+pub const HEADER_LEN: u64 = (MAGIC.len() + NONCE_PREFIX_LEN) as u64;
+
+const MAGIC: &[u8; 4] = b"BLZ1";
+const NONCE_LEN: usize = 12;
+const NONCE_PREFIX_LEN: usize = 7;
+
+pub fn generate_key() -> Vec<u8> {
+    let mut key = vec![0u8; KEY_LEN];
+    rand::rng().fill_bytes(&mut key);
+    key
+}
+
+// Wrapped keys are stored as `nonce || ciphertext || tag`
+pub fn wrap_key(kek: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(kek));
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::rng().fill_bytes(&mut nonce);
+    let mut wrapped = nonce.to_vec();
+    wrapped.extend(cipher.encrypt(Nonce::from_slice(&nonce), key)?);
+    Ok(wrapped)
+}
+
+pub fn unwrap_key(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>, Error> {
+    if wrapped.len() < NONCE_LEN {
+        return Err(Error);
+    }
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(kek));
+    let (nonce, ciphertext) = wrapped.split_at(NONCE_LEN);
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+}
+
+/// Chunked AES-256-GCM in the STREAM construction: every chunk is sealed with
+/// `prefix || chunk index || last flag` as nonce, so chunks cannot be reordered,
+/// dropped or truncated, and any chunk can be decrypted on its own.
+pub struct StreamCipher {
+    cipher: Aes256Gcm,
+    prefix: [u8; NONCE_PREFIX_LEN],
+}
+
+impl StreamCipher {
+    pub fn new(key: &[u8]) -> Self {
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        rand::rng().fill_bytes(&mut prefix);
+        StreamCipher { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)), prefix }
+    }
+
+    pub fn from_header(key: &[u8], header: &[u8]) -> Result<Self, Error> {
+        if header.len() != HEADER_LEN as usize || &header[..MAGIC.len()] != MAGIC {
+            return Err(Error);
+        }
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        prefix.copy_from_slice(&header[MAGIC.len()..]);
+        Ok(StreamCipher { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)), prefix })
+    }
+
+    pub fn header(&self) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&self.prefix);
+        header
+    }
+
+    pub fn seal(&self, index: u64, last: bool, chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        self.cipher.encrypt(&self.nonce(index, last)?, Payload { msg: chunk, aad: &[] })
+    }
+
+    pub fn open(&self, index: u64, last: bool, chunk: &[u8]) -> Result<Vec<u8>, Error> {
+        self.cipher.decrypt(&self.nonce(index, last)?, Payload { msg: chunk, aad: &[] })
+    }
+
+    fn nonce(&self, index: u64, last: bool) -> Result<Nonce<aes_gcm::aead::consts::U12>, Error> {
+        let index = u32::try_from(index).map_err(|_| Error)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.prefix);
+        nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&index.to_be_bytes());
+        nonce[NONCE_LEN - 1] = last as u8;
+        Ok(*Nonce::from_slice(&nonce))
+    }
+}
+
+pub fn chunk_count(ciphertext_len: u64) -> u64 {
+    ciphertext_len.saturating_sub(HEADER_LEN).div_ceil(CHUNK_SIZE + TAG_LEN).max(1)
+}
+
+pub fn chunk_offset(index: u64) -> u64 {
+    HEADER_LEN + index * (CHUNK_SIZE + TAG_LEN)
+}
+
+pub fn plaintext_len(ciphertext_len: u64) -> Option<u64> {
+    ciphertext_len
+        .checked_sub(HEADER_LEN)?
+        .checked_sub(chunk_count(ciphertext_len) * TAG_LEN)
+}
@@ -10,3 +10,13 @@ pub fn trim_lower<'de, D>(deserializer: D) -> Result<String, D::Error> where D:
     Ok(s.trim().to_lowercase())
 }
 
+
+pub fn trim_option<'de, D>(deserializer: D) -> Result<Option<String>, D::Error> where D: serde::Deserializer<'de> {
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+    Ok(s.map(|s| s.trim().to_string()))
+}
+
+pub fn trim_lower_option<'de, D>(deserializer: D) -> Result<Option<String>, D::Error> where D: serde::Deserializer<'de> {
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+    Ok(s.map(|s| s.trim().to_lowercase()))
+}
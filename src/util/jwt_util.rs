@@ -1,29 +1,185 @@
-use crate::auth::auth_model::{Claims, RefreshTokenClaims};
+use crate::auth::auth_model::{Claims, EmailTokenClaims, RefreshTokenClaims};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use chrono::{Duration, Utc};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, TokenData, Validation};
+use jsonwebtoken::errors::{Error, ErrorKind};
+use jsonwebtoken::jwk::{AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
 use lazy_static::lazy_static;
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair, RsaPublicKeyComponents};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::env;
-use jsonwebtoken::errors::Error;
 use uuid::Uuid;
 
+// Tokens signed before keys had ids carry no `kid`, they are checked against this key
+const LEGACY_KID: &str = "default";
+
 lazy_static! {
-    static ref JWT_SECRET: String = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
     static ref JWT_EXPIRY: i64 = env::var("JWT_EXPIRY").expect("JWT_EXPIRY must be set").parse::<i64>().expect("JWT_EXPIRY must be a number");
     static ref REFRESH_TOKEN_EXPIRY: i64 = env::var("REFRESH_TOKEN_EXPIRY").expect("Refresh token must be set").parse::<i64>().expect("Refresh token must be a number");
     static ref APPLICATION_NAME: String = env::var("APPLICATION_NAME").expect("APPLICATION_NAME must be set");
-    static ref ENCODING_KEY: EncodingKey = EncodingKey::from_secret(JWT_SECRET.as_bytes());
-    static ref DECODING_KEY: DecodingKey = DecodingKey::from_secret(JWT_SECRET.as_bytes());
+    static ref SIGNING_KEYS: Vec<SigningKey> = load_keys();
+    static ref ACTIVE_KEY: &'static SigningKey = {
+        let mut active = SIGNING_KEYS.iter().filter(|key| key.state == KeyState::ACTIVE);
+        let key = active.next().expect("One JWT key must be active");
+        assert!(active.next().is_none(), "Only one JWT key can be active");
+        key
+    };
+}
+
+#[derive(Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum KeyState {
+    // Signs new tokens and verifies them
+    ACTIVE,
+    // Only verifies tokens signed before the key was rotated out
+    VERIFY,
+}
+
+// One entry of the JWT_KEYS_FILE array, asymmetric keys are given as a PKCS#8 (or PKCS#1 for RSA) private key
+#[derive(Deserialize)]
+struct KeyConfig {
+    kid: String,
+    alg: Algorithm,
+    state: KeyState,
+    secret: Option<String>,
+    private_key_file: Option<String>,
+}
+
+struct SigningKey {
+    kid: String,
+    alg: Algorithm,
+    state: KeyState,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    // Public part published through the JWKS endpoint, HS256 keys have none
+    jwk: Option<Jwk>,
+}
+
+// Without JWT_KEYS_FILE the single HS256 JWT_SECRET keeps working as before
+fn load_keys() -> Vec<SigningKey> {
+    let legacy_secret = env::var("JWT_SECRET").ok();
+    let Ok(path) = env::var("JWT_KEYS_FILE") else {
+        let secret = legacy_secret.expect("JWT_SECRET or JWT_KEYS_FILE must be set");
+        return vec![hmac_key(LEGACY_KID.to_string(), KeyState::ACTIVE, &secret)];
+    };
+    let configs: Vec<KeyConfig> = serde_json::from_str(&std::fs::read_to_string(&path).expect("JWT_KEYS_FILE must be readable"))
+        .expect("JWT_KEYS_FILE must be a JSON array of keys");
+    let mut keys = configs.into_iter().map(load_key).collect::<Vec<_>>();
+    if let Some(secret) = legacy_secret.filter(|_| keys.iter().all(|key| key.kid != LEGACY_KID)) {
+        keys.push(hmac_key(LEGACY_KID.to_string(), KeyState::VERIFY, &secret));
+    }
+    keys
+}
+
+fn load_key(config: KeyConfig) -> SigningKey {
+    let KeyConfig { kid, alg, state, secret, private_key_file } = config;
+    if alg == Algorithm::HS256 {
+        let secret = secret.unwrap_or_else(|| panic!("JWT key {kid} needs a secret"));
+        return hmac_key(kid, state, &secret);
+    }
+    let path = private_key_file.unwrap_or_else(|| panic!("JWT key {kid} needs a private_key_file"));
+    let pem_bytes = std::fs::read(&path).unwrap_or_else(|e| panic!("Cannot read JWT key {kid} from {path}: {e}"));
+    let pem = pem::parse(&pem_bytes).unwrap_or_else(|e| panic!("JWT key {kid} is not a PEM file: {e}"));
+    let common = CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_id: Some(kid.clone()),
+        ..Default::default()
+    };
+    let (encoding, decoding, jwk) = match alg {
+        Algorithm::RS256 => {
+            let key_pair = match pem.tag() {
+                "RSA PRIVATE KEY" => RsaKeyPair::from_der(pem.contents()),
+                _ => RsaKeyPair::from_pkcs8(pem.contents()),
+            }.unwrap_or_else(|e| panic!("JWT key {kid} is not an RSA private key: {e}"));
+            let public = RsaPublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+            let jwk = Jwk {
+                common: CommonParameters { key_algorithm: Some(KeyAlgorithm::RS256), ..common },
+                algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(&public.n),
+                    e: URL_SAFE_NO_PAD.encode(&public.e),
+                }),
+            };
+            (EncodingKey::from_rsa_pem(&pem_bytes), Ok(DecodingKey::from_rsa_raw_components(&public.n, &public.e)), jwk)
+        }
+        Algorithm::EdDSA => {
+            let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pem.contents())
+                .unwrap_or_else(|e| panic!("JWT key {kid} is not an Ed25519 private key: {e}"));
+            let x = URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref());
+            let decoding = DecodingKey::from_ed_components(&x);
+            let jwk = Jwk {
+                common: CommonParameters { key_algorithm: Some(KeyAlgorithm::EdDSA), ..common },
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x,
+                }),
+            };
+            (EncodingKey::from_ed_pem(&pem_bytes), decoding, jwk)
+        }
+        alg => panic!("JWT key {kid} uses {alg:?}, only HS256, RS256 and EdDSA are supported"),
+    };
+    SigningKey {
+        encoding: encoding.unwrap_or_else(|e| panic!("Invalid JWT key {kid}: {e}")),
+        decoding: decoding.unwrap_or_else(|e| panic!("Invalid JWT key {kid}: {e}")),
+        jwk: Some(jwk),
+        kid,
+        alg,
+        state,
+    }
+}
+
+fn hmac_key(kid: String, state: KeyState, secret: &str) -> SigningKey {
+    SigningKey {
+        kid,
+        alg: Algorithm::HS256,
+        state,
+        encoding: EncodingKey::from_secret(secret.as_bytes()),
+        decoding: DecodingKey::from_secret(secret.as_bytes()),
+        jwk: None,
+    }
 }
 
-pub fn issue(user_id: Uuid) -> Result<String, Error> {
+// Loads the keys up front so a broken key configuration stops the server from starting
+pub fn init() {
+    info!("Signing tokens with JWT key {}, {} key(s) loaded", ACTIVE_KEY.kid, SIGNING_KEYS.len());
+}
+
+// Public keys of every asymmetric key that still verifies tokens
+pub fn jwks() -> JwkSet {
+    JwkSet { keys: SIGNING_KEYS.iter().filter_map(|key| key.jwk.clone()).collect() }
+}
+
+fn encode<T: Serialize>(claims: &T) -> Result<String, Error> {
+    let key = *ACTIVE_KEY;
+    let mut header = Header::new(key.alg);
+    header.kid = Some(key.kid.clone());
+    jsonwebtoken::encode(&header, claims, &key.encoding)
+}
+
+// Picks the key by the token's `kid` and only accepts the algorithm that key was made for
+fn decode_with<T: DeserializeOwned>(token: &str, configure: impl FnOnce(&mut Validation)) -> Result<TokenData<T>, Error> {
+    let header = jsonwebtoken::decode_header(token)?;
+    let kid = header.kid.as_deref().unwrap_or(LEGACY_KID);
+    let key = SIGNING_KEYS.iter().find(|key| key.kid == kid).ok_or(Error::from(ErrorKind::InvalidToken))?;
+    let mut validation = Validation::new(key.alg);
+    configure(&mut validation);
+    jsonwebtoken::decode::<T>(token, &key.decoding, &validation)
+}
+
+pub fn issue(user_id: Uuid, session_id: Uuid) -> Result<String, Error> {
     let claims = Claims {
         sub: user_id,
+        sid: session_id,
+        jti: Uuid::now_v7(),
         iss: APPLICATION_NAME.to_string(),
         iat: Utc::now().timestamp(),
-        exp: (Utc::now() + Duration::seconds(JWT_EXPIRY.clone())).timestamp(),
+        exp: (Utc::now() + Duration::seconds(*JWT_EXPIRY)).timestamp(),
     };
 
-    jsonwebtoken::encode(&Header::default(), &claims, &ENCODING_KEY)
+    encode(&claims)
 }
 
 pub fn issue_refresh_token(user_id: Uuid, jti: Uuid) -> Result<String, Error> {
@@ -32,17 +188,36 @@ pub fn issue_refresh_token(user_id: Uuid, jti: Uuid) -> Result<String, Error> {
         jti,
         iss: APPLICATION_NAME.to_string(),
         iat: Utc::now().timestamp(),
-        exp: (Utc::now() + Duration::seconds(REFRESH_TOKEN_EXPIRY.clone())).timestamp(),
+        exp: (Utc::now() + Duration::seconds(*REFRESH_TOKEN_EXPIRY)).timestamp(),
     };
-    let token = jsonwebtoken::encode(&Header::default(), &claims, &ENCODING_KEY)?;
+    let token = encode(&claims)?;
     Ok(token)
 
 }
 
 pub fn decode(token: &str) -> Result<TokenData<Claims>, Error> {
-    jsonwebtoken::decode::<Claims>(token, &DECODING_KEY, &Validation::default())
+    decode_with::<Claims>(token, |_| ())
 }
 
 pub fn decode_refresh_token(token: &str) -> Result<TokenData<RefreshTokenClaims>, Error> {
-    jsonwebtoken::decode::<RefreshTokenClaims>(token, &DECODING_KEY, &Validation::default())
-}
\ No newline at end of file
+    decode_with::<RefreshTokenClaims>(token, |_| ())
+}
+
+pub fn issue_email_token(user_id: Uuid, email: &str, audience: &str, expiry: i64) -> Result<String, Error> {
+    let claims = EmailTokenClaims {
+        sub: user_id,
+        email: email.to_string(),
+        aud: audience.to_string(),
+        iss: APPLICATION_NAME.to_string(),
+        iat: Utc::now().timestamp(),
+        exp: (Utc::now() + Duration::seconds(expiry)).timestamp(),
+    };
+    encode(&claims)
+}
+
+pub fn decode_email_token(token: &str, audience: &str) -> Result<TokenData<EmailTokenClaims>, Error> {
+    decode_with::<EmailTokenClaims>(token, |validation| {
+        validation.set_audience(&[audience]);
+        validation.set_required_spec_claims(&["exp", "aud"]);
+    })
+}
@@ -1,3 +1,4 @@
 pub mod deserializer_util;
 pub mod validator_util;
-pub mod jwt_util;
\ No newline at end of file
+pub mod jwt_util;
+pub mod crypto_util;
\ No newline at end of file
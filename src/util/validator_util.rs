@@ -4,19 +4,28 @@ use validator::ValidationError;
 
 lazy_static! {
     static ref RE_PASSWORD: Regex = Regex::new(r"^(?=.*[a-z])(?=.*[A-Z])(?=.*\d)(?=.*[@$!%*?&])[A-Za-z\d@$!%*?&]{8,}$").unwrap();
-    static ref RE_USERNAME: Regex = Regex::new(r"[a-zA-Z][a-zA-Z0-9._]{4,32}$").unwrap();
+    static ref RE_USERNAME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9._]{3,31}$").unwrap();
+    // Used as a directory under `files/`, so no dots or slashes
+    static ref RE_ORGANIZATION_NAME: Regex = Regex::new(r"^[a-zA-Z0-9][a-zA-Z0-9_-]{3,254}$").unwrap();
 }
 
 pub fn validate_password(password: &str) -> Result<(), ValidationError> {
     match RE_PASSWORD.is_match(password) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(ValidationError::new("Password Error")),
+        Ok(true) => Ok(()),
+        _ => Err(ValidationError::new("Password Error")),
     }
 }
 
 pub fn validate_username(username: &str) -> Result<(), ValidationError> {
     match RE_USERNAME.is_match(username) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(ValidationError::new("Username Error")),
+        Ok(true) => Ok(()),
+        _ => Err(ValidationError::new("Username Error")),
     }
-}
\ No newline at end of file
+}
+
+pub fn validate_organization_name(name: &str) -> Result<(), ValidationError> {
+    match RE_ORGANIZATION_NAME.is_match(name) {
+        Ok(true) => Ok(()),
+        _ => Err(ValidationError::new("Organization Name Error")),
+    }
+}
@@ -0,0 +1,5 @@
+pub mod website_handler;
+pub mod website_middleware;
+pub mod website_service;
+mod website_dto;
+pub mod website_model;
@@ -0,0 +1,16 @@
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::website::website_model::WebsiteConfig;
+
+#[derive(Deserialize)]
+pub struct UpdateWebsiteDto {
+    pub bucket_id: Uuid,
+    pub website: Option<WebsiteConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct WebsitePathDto {
+    pub organization_name: String,
+    pub bucket_name: String,
+    pub path: String,
+}
@@ -0,0 +1,35 @@
+use crate::bucket::bucket_model::Bucket;
+use crate::error::ApiResponse;
+use crate::user::user_model::User;
+use crate::website::website_dto::{UpdateWebsiteDto, WebsitePathDto};
+use crate::website::website_service;
+use actix_web::http::header;
+use actix_web::web::{Json, Path, ServiceConfig};
+use actix_web::{put, route, HttpMessage, HttpRequest, HttpResponse};
+
+#[put("")]
+async fn update(dto: Json<UpdateWebsiteDto>, request: HttpRequest) -> Result<Json<Bucket>, ApiResponse> {
+    let extensions = request.extensions();
+    let user = extensions.get::<User>().unwrap();
+    let UpdateWebsiteDto { bucket_id, website } = dto.into_inner();
+    let bucket = website_service::update(bucket_id, website, user).await?;
+    Ok(Json(bucket))
+}
+
+#[route("{organization_name}/{bucket_name}{path:.*}", method = "GET", method = "HEAD")]
+async fn serve(dto: Path<WebsitePathDto>, request: HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let WebsitePathDto { organization_name, bucket_name, path } = dto.into_inner();
+    let base = format!("/w/{organization_name}/{bucket_name}");
+    if path.is_empty() {
+        return Ok(HttpResponse::MovedPermanently().insert_header((header::LOCATION, base + "/")).finish());
+    }
+    website_service::serve(&organization_name, &bucket_name, &path, &base, &request).await
+}
+
+pub fn website_routes(cfg: &mut ServiceConfig) {
+    cfg.service(update);
+}
+
+pub fn hosting_routes(cfg: &mut ServiceConfig) {
+    cfg.service(serve);
+}
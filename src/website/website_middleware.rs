@@ -0,0 +1,42 @@
+use crate::domain::domain_service;
+use crate::website::website_service;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+use lazy_static::lazy_static;
+use std::env;
+
+lazy_static! {
+    static ref WEBSITE_DOMAIN: Option<String> = env::var("WEBSITE_DOMAIN").ok();
+}
+
+// Serves `<bucket>.<organization>.<WEBSITE_DOMAIN>` as the website of that bucket and verified
+// custom domains as their bucket; other hosts fall through to the normal routes
+pub async fn website_host(request: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let host = request.connection_info().host().to_lowercase();
+    let host = host.split(':').next().unwrap_or_default();
+    let ((organization_name, bucket_name), custom_domain) = if let Some(target) = website_bucket(host) {
+        (target, false)
+    } else if let Some(target) = domain_service::resolve(host).await? {
+        (target, true)
+    } else {
+        return next.call(request).await.map(ServiceResponse::map_into_left_body);
+    };
+
+    let response = if request.method() != Method::GET && request.method() != Method::HEAD {
+        HttpResponse::MethodNotAllowed().finish()
+    } else if custom_domain {
+        website_service::serve_domain(&organization_name, &bucket_name, request.path(), request.request()).await?
+    } else {
+        website_service::serve(&organization_name, &bucket_name, request.path(), "", request.request()).await?
+    };
+    Ok(request.into_response(response).map_into_right_body())
+}
+
+fn website_bucket(host: &str) -> Option<(String, String)> {
+    let domain = WEBSITE_DOMAIN.as_ref()?;
+    let (bucket_name, organization_name) = host.strip_suffix(domain.as_str())?.strip_suffix('.')?.split_once('.')?;
+    Some((organization_name.to_string(), bucket_name.to_string()))
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+// Static website settings of a public bucket, stored as JSON on the bucket
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct WebsiteConfig {
+    #[serde(default = "default_index_document")]
+    pub index_document: String,
+    pub error_document: Option<String>,
+    // Serves the root index document for every path that has no object, for client-side routers
+    #[serde(default)]
+    pub spa_fallback: bool,
+    #[serde(default)]
+    pub routing_rules: Vec<RoutingRule>,
+}
+
+// Redirects requests whose key starts with `key_prefix` and, when set, that would fail with `error_code`
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RoutingRule {
+    pub key_prefix: Option<String>,
+    pub error_code: Option<u16>,
+    pub redirect_protocol: Option<String>,
+    pub redirect_host: Option<String>,
+    pub replace_key_prefix_with: Option<String>,
+    pub replace_key_with: Option<String>,
+    pub redirect_code: Option<u16>,
+}
+
+fn default_index_document() -> String {
+    "index.html".to_string()
+}
+
+impl RoutingRule {
+    pub fn matches(&self, key: &str, error_code: Option<u16>) -> bool {
+        self.key_prefix.as_deref().is_none_or(|prefix| key.starts_with(prefix)) && self.error_code == error_code
+    }
+
+    pub fn redirect_key(&self, key: &str) -> String {
+        if let Some(replacement) = &self.replace_key_with {
+            return replacement.clone();
+        }
+        match (&self.replace_key_prefix_with, &self.key_prefix) {
+            (Some(replacement), Some(prefix)) => replacement.clone() + &key[prefix.len()..],
+            (Some(replacement), None) => replacement.clone() + key,
+            _ => key.to_string(),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::bucket::bucket_model::{Bucket, BucketVisibility};
+use crate::bucket::bucket_service;
+use crate::config::db_config;
+use crate::error::ApiResponse;
+use crate::file::file_service;
+use crate::folder::folder_service::EDITABLE_ROLES;
+use crate::organization::organization_model::UserOrganization;
+use crate::schema::{buckets, user_organizations};
+use crate::token::token_service;
+use crate::user::user_model::User;
+use crate::website::website_model::{RoutingRule, WebsiteConfig};
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use diesel::{BoolExpressionMethods, ExpressionMethods, JoinOnDsl, QueryDsl, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+
+pub async fn update(bucket_id: Uuid, website: Option<WebsiteConfig>, user: &User) -> Result<Bucket, ApiResponse> {
+    if let Some(website) = &website {
+        validate(website)?;
+    }
+    let mut conn = db_config::get_connection().await?;
+    let (bucket, user_organization) = buckets::table.find(bucket_id)
+        .left_join(user_organizations::table.on(buckets::organization_id.eq(user_organizations::organization_id).and(user_organizations::user_id.eq(user.id))))
+        .select((Bucket::as_select(), Option::<UserOrganization>::as_select()))
+        .first::<(Bucket, Option<UserOrganization>)>(&mut conn)
+        .await?;
+    match token_service::restrict(user_organization) {
+        Some(user_organization) if EDITABLE_ROLES.contains(&user_organization.role) => (),
+        _ => return Err(ApiResponse::new(StatusCode::FORBIDDEN, "You do not have permission to update a bucket".to_string()))
+    }
+    if website.is_some() && bucket.visibility != BucketVisibility::PUBLIC {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Only public buckets can host a website".to_string()));
+    }
+    let website = website.map(serde_json::to_value).transpose()
+        .map_err(|e| ApiResponse::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let bucket = diesel::update(buckets::table.find(bucket.id))
+        .set((buckets::website.eq(website), buckets::updated_at.eq(Utc::now().naive_utc())))
+        .get_result::<Bucket>(&mut conn)
+        .await?;
+    Ok(bucket)
+}
+
+fn validate(website: &WebsiteConfig) -> Result<(), ApiResponse> {
+    if website.index_document.is_empty() || website.index_document.contains('/') {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, "Index document must be a file name".to_string()));
+    }
+    let invalid_code = website.routing_rules.iter()
+        .filter_map(|rule| rule.redirect_code)
+        .find(|code| !(300..400).contains(code));
+    if let Some(code) = invalid_code {
+        return Err(ApiResponse::new(StatusCode::BAD_REQUEST, format!("{code} is not a redirect status code")));
+    }
+    Ok(())
+}
+
+fn not_found() -> ApiResponse {
+    ApiResponse::new(StatusCode::NOT_FOUND, "Not found".to_string())
+}
+
+async fn find_public_bucket(organization_name: &str, bucket_name: &str) -> Result<(Bucket, Option<WebsiteConfig>), ApiResponse> {
+    let bucket = bucket_service::find_by_name(organization_name, bucket_name).await?
+        .filter(|bucket| bucket.visibility == BucketVisibility::PUBLIC)
+        .ok_or_else(not_found)?;
+    let website = bucket.website.clone()
+        .and_then(|website| serde_json::from_value::<WebsiteConfig>(website).ok());
+    Ok((bucket, website))
+}
+
+// Serves `path` of a website bucket; `base` is the URL prefix the site is mounted under
+pub async fn serve(organization_name: &str, bucket_name: &str, path: &str, base: &str, request: &HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let (bucket, website) = find_public_bucket(organization_name, bucket_name).await?;
+    let website = website.ok_or_else(not_found)?;
+    serve_website(organization_name, &bucket, &website, path, base, request).await
+}
+
+// Serves a bucket behind a custom domain: as a website when it has one, otherwise object by object
+pub async fn serve_domain(organization_name: &str, bucket_name: &str, path: &str, request: &HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let (bucket, website) = find_public_bucket(organization_name, bucket_name).await?;
+    if let Some(website) = website {
+        return serve_website(organization_name, &bucket, &website, path, "", request).await;
+    }
+    let file = file_service::open_object(organization_name, &bucket, path.trim_start_matches('/')).await?
+        .ok_or_else(not_found)?;
+    Ok(file.respond_to(request).map_into_boxed_body())
+}
+
+async fn serve_website(organization_name: &str, bucket: &Bucket, website: &WebsiteConfig, path: &str, base: &str, request: &HttpRequest) -> Result<HttpResponse, ApiResponse> {
+    let key = path.trim_start_matches('/');
+    if let Some(rule) = website.routing_rules.iter().find(|rule| rule.matches(key, None)) {
+        return Ok(redirect(rule, key, base, request));
+    }
+    let object_key = if key.is_empty() || key.ends_with('/') { format!("{key}{}", website.index_document) } else { key.to_string() };
+    if let Some(file) = file_service::open_object(organization_name, bucket, &object_key).await? {
+        return Ok(file.respond_to(request).map_into_boxed_body());
+    }
+    // `docs` is a folder with an index document, send the browser to `docs/` so relative links resolve
+    if object_key == key && file_service::open_object(organization_name, bucket, &format!("{key}/{}", website.index_document)).await?.is_some() {
+        return Ok(HttpResponse::Found().insert_header((header::LOCATION, format!("{base}/{key}/"))).finish());
+    }
+
+    if let Some(rule) = website.routing_rules.iter().find(|rule| rule.matches(key, Some(StatusCode::NOT_FOUND.as_u16()))) {
+        return Ok(redirect(rule, key, base, request));
+    }
+    if website.spa_fallback
+        && let Some(file) = file_service::open_object(organization_name, bucket, &website.index_document).await? {
+        return Ok(file.respond_to(request).map_into_boxed_body());
+    }
+    if let Some(error_document) = &website.error_document
+        && let Some(file) = file_service::open_object(organization_name, bucket, error_document).await? {
+        let mut response = file.respond_to(request).map_into_boxed_body();
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+    Err(not_found())
+}
+
+fn redirect(rule: &RoutingRule, key: &str, base: &str, request: &HttpRequest) -> HttpResponse {
+    let key = rule.redirect_key(key);
+    let location = match &rule.redirect_host {
+        Some(host) => {
+            let protocol = rule.redirect_protocol.clone().unwrap_or_else(|| request.connection_info().scheme().to_string());
+            format!("{protocol}://{host}/{key}")
+        }
+        None => format!("{base}/{key}"),
+    };
+    let status = rule.redirect_code.and_then(|code| StatusCode::from_u16(code).ok()).unwrap_or(StatusCode::MOVED_PERMANENTLY);
+    HttpResponse::build(status).insert_header((header::LOCATION, location)).finish()
+}